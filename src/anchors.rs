@@ -0,0 +1,300 @@
+//! Working out which anchors (e.g. the `#installation` in
+//! `./setup.md#installation`) a chapter provides.
+
+use pulldown_cmark::{Event, Parser, Tag};
+use regex::Regex;
+use std::collections::HashMap;
+
+/// Get every anchor a chapter will have once it is rendered.
+///
+/// This is the `id` `mdbook` generates for each heading (with `-1`, `-2`,
+/// etc. appended to duplicates) plus any `id` or `name` attributes in inline
+/// HTML.
+pub(crate) fn anchors(src: &str) -> Vec<String> {
+    let html_anchor =
+        Regex::new(r#"\b(?:id|name)\s*=\s*["']([^"']+)["']"#).unwrap();
+    let mut anchors = Vec::new();
+    let mut id_counter = HashMap::new();
+
+    for item in headings_and_html(src) {
+        match item {
+            HeadingOrHtml::Heading(content) => {
+                anchors.push(mdbook_slug(&content, &mut id_counter));
+            },
+            HeadingOrHtml::Html(html) => anchors.extend(
+                html_anchor
+                    .captures_iter(&html)
+                    .map(|cap| cap[1].to_string()),
+            ),
+        }
+    }
+
+    anchors
+}
+
+/// Anchors which several headings in a chapter would share if `mdbook` didn't
+/// append `-1`, `-2`, etc. to make them unique, mapped to the anchor each of
+/// those headings actually gets.
+pub(crate) fn ambiguous_anchors(src: &str) -> HashMap<String, Vec<String>> {
+    let mut candidates: HashMap<String, Vec<String>> = HashMap::new();
+    let mut id_counter = HashMap::new();
+
+    for item in headings_and_html(src) {
+        if let HeadingOrHtml::Heading(content) = item {
+            let unique = mdbook_slug(&content, &mut id_counter);
+            candidates
+                .entry(mdbook_slug(&content, &mut HashMap::new()))
+                .or_default()
+                .push(unique);
+        }
+    }
+
+    candidates.retain(|_, ids| ids.len() > 1);
+    candidates
+}
+
+enum HeadingOrHtml {
+    /// The contents of a heading, rendered as HTML.
+    Heading(String),
+    /// Inline or block HTML.
+    Html(String),
+}
+
+/// Find every heading and snippet of HTML in a chapter, in order.
+fn headings_and_html(src: &str) -> Vec<HeadingOrHtml> {
+    let mut items = Vec::new();
+    let mut heading: Option<Vec<Event<'_>>> = None;
+
+    for event in Parser::new(src) {
+        match event {
+            Event::Start(Tag::Heading(_)) => heading = Some(Vec::new()),
+            Event::End(Tag::Heading(_)) => {
+                if let Some(events) = heading.take() {
+                    let mut content = String::new();
+                    pulldown_cmark::html::push_html(
+                        &mut content,
+                        events.into_iter(),
+                    );
+                    items.push(HeadingOrHtml::Heading(content));
+                }
+            },
+            Event::Html(ref html) => {
+                items.push(HeadingOrHtml::Html(html.to_string()));
+                if let Some(ref mut events) = heading {
+                    events.push(event);
+                }
+            },
+            other => {
+                if let Some(ref mut events) = heading {
+                    events.push(other);
+                }
+            },
+        }
+    }
+
+    items
+}
+
+/// The `id` `mdbook` gives a heading, the same as
+/// `mdbook::utils::unique_id_from_content()`.
+///
+/// The `content` is the heading's rendered HTML. Tags and HTML entities are
+/// removed, then any leading `#`s and surrounding whitespace, before the
+/// rest is normalized (see `mdbook::utils::normalize_id()`). Headings which
+/// have already been seen (according to the `id_counter`) get `-1`, `-2`,
+/// etc. appended.
+pub(crate) fn mdbook_slug(
+    content: &str,
+    id_counter: &mut HashMap<String, usize>,
+) -> String {
+    let tags = Regex::new(r"(<.*?>)").unwrap();
+    let mut content = tags.replace_all(content, "").into_owned();
+    for entity in &["&lt;", "&gt;", "&amp;", "&#39;", "&quot;"] {
+        content = content.replace(entity, "");
+    }
+
+    let trimmed = content.trim().trim_start_matches('#').trim();
+    let id = mdbook::utils::normalize_id(trimmed);
+    let count = id_counter.entry(id.clone()).or_insert(0);
+
+    let unique = if *count == 0 {
+        id
+    } else {
+        format!("{}-{}", id, count)
+    };
+    *count += 1;
+
+    unique
+}
+
+/// Does this fragment look like someone was expecting `mdbook` to generate
+/// anchors from section numbers (e.g. `#1-2` or `#section-1-2`)?
+pub(crate) fn looks_like_section_number(fragment: &str) -> bool {
+    let pattern = Regex::new(r"^(?:section-)?\d+(?:-\d+)*$").unwrap();
+    pattern.is_match(fragment)
+}
+
+/// Find the anchor a mistyped fragment (e.g. `#My Heading` or `#my_heading`)
+/// was probably meant to be, if any of them are close enough.
+pub(crate) fn closest_anchor<'a, I>(
+    fragment: &str,
+    anchors: I,
+) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let normalized = mdbook::utils::normalize_id(fragment);
+    // anything further away than this is probably a different heading
+    let max_distance = (normalized.chars().count() / 3).max(2);
+
+    anchors
+        .into_iter()
+        .map(|anchor| (edit_distance(&normalized, anchor), anchor))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, anchor)| anchor.as_str())
+}
+
+/// The Levenshtein distance between two strings.
+pub(crate) fn edit_distance(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+
+    for (i, l) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, r) in right.iter().enumerate() {
+            let substitution = previous[j] + if l == *r { 0 } else { 1 };
+            let insertion = current[j] + 1;
+            let deletion = previous[j + 1] + 1;
+            current.push(substitution.min(insertion).min(deletion));
+        }
+
+        previous = current;
+    }
+
+    previous[right.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_heading_and_html_anchors() {
+        let src = r#"# Getting Started
+
+## Installation
+
+Some text with <a name="custom-anchor"></a> an anchor.
+
+## Installation
+
+### `cargo` Usage
+"#;
+
+        let got = anchors(src);
+
+        assert_eq!(
+            got,
+            vec![
+                "getting-started",
+                "installation",
+                "custom-anchor",
+                "installation-1",
+                "cargo-usage",
+            ]
+        );
+    }
+
+    #[test]
+    fn find_anchors_shared_by_several_headings() {
+        let src =
+            "# Setup\n\n## Linux\n\n### Setup\n\n## Windows\n\n### Setup\n";
+
+        let got = ambiguous_anchors(src);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got["setup"], vec!["setup", "setup-1", "setup-2"]);
+    }
+
+    #[test]
+    fn slugs_match_the_ids_mdbook_generates() {
+        let inputs = vec![
+            ("1. Introduction", "1-introduction"),
+            ("2.1 What's New?", "21-whats-new"),
+            ("Überblick und Ausblick", "Überblick-und-ausblick"),
+            ("日本語の見出し", "日本語の見出し"),
+            ("Using <code>cargo</code>", "using-cargo"),
+            ("Rust &amp; C", "rust--c"),
+            ("#1 Fan", "1-fan"),
+            ("  Padded  ", "padded"),
+        ];
+
+        for (content, should_be) in inputs {
+            let got = mdbook_slug(content, &mut HashMap::new());
+            assert_eq!(got, should_be, "{}", content);
+        }
+    }
+
+    #[test]
+    fn repeated_slugs_get_a_numeric_suffix() {
+        let mut id_counter = HashMap::new();
+
+        let got: Vec<_> = vec!["Example", "Other", "Example", "Example"]
+            .into_iter()
+            .map(|content| mdbook_slug(content, &mut id_counter))
+            .collect();
+
+        assert_eq!(got, vec!["example", "other", "example-1", "example-2"]);
+    }
+
+    #[test]
+    fn anchors_for_headings_with_inline_markup() {
+        let src = "# The `Config` *type*\n\n## # Hashes\n\n## 1. Install\n";
+
+        let got = anchors(src);
+
+        assert_eq!(got, vec!["the-config-type", "hashes", "1-install"]);
+    }
+
+    #[test]
+    fn suggest_the_closest_anchor() {
+        let anchors = vec![
+            String::from("my-heading"),
+            String::from("installation"),
+            String::from("getting-started"),
+        ];
+
+        assert_eq!(closest_anchor("my_heading", &anchors), Some("my-heading"));
+        assert_eq!(closest_anchor("My Heading", &anchors), Some("my-heading"));
+        assert_eq!(
+            closest_anchor("instalation", &anchors),
+            Some("installation")
+        );
+        assert_eq!(closest_anchor("troubleshooting", &anchors), None);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn detect_section_numbers() {
+        let inputs = vec![
+            ("1", true),
+            ("1-2", true),
+            ("1-2-3", true),
+            ("section-1-2", true),
+            ("installation", false),
+            ("step-2", false),
+            ("1-", false),
+        ];
+
+        for (fragment, should_be) in inputs {
+            assert_eq!(
+                looks_like_section_number(fragment),
+                should_be,
+                "{}",
+                fragment
+            );
+        }
+    }
+}
@@ -1,32 +1,331 @@
 use anyhow::{Context, Error};
 use codespan_reporting::term::termcolor::ColorChoice;
-use mdbook::{renderer::RenderContext, MDBook};
-use std::{io, path::PathBuf};
+use log::LevelFilter;
+use mdbook::{
+    book::Book,
+    renderer::{RenderContext, Renderer},
+    MDBook,
+};
+use mdbook_linkcheck::{
+    BrokenLinks, ConfigError, ConfigOverrides, HashedRegex, LinkScope,
+    ReportFormat, RunOptions, TimedOut,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::Arc,
+    time::Duration,
+};
 use structopt::StructOpt;
 
-fn main() -> Result<(), Error> {
-    env_logger::init();
-    let args = Args::from_args();
+/// The book contains broken links.
+const EXIT_BROKEN_LINKS: i32 = 1;
+/// Invalid configuration or command-line arguments.
+const EXIT_CONFIG_ERROR: i32 = 2;
+/// Anything else (e.g. being unable to read a file).
+const EXIT_INTERNAL_ERROR: i32 = 3;
+/// Some links weren't checked before `--max-duration` was reached.
+const EXIT_TIMED_OUT: i32 = 4;
+
+fn main() {
+    let args = match Args::from_args_safe() {
+        Ok(args) => args,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e);
+            process::exit(EXIT_CONFIG_ERROR);
+        },
+        // --help and --version
+        Err(e) => e.exit(),
+    };
+
+    init_logging(args.quiet);
+
+    if let Err(e) = run(args) {
+        eprintln!("Error: {:?}", e);
+        process::exit(exit_code(&e));
+    }
+}
+
+fn init_logging(quiet: bool) {
+    let mut builder = env_logger::Builder::from_default_env();
+
+    if quiet {
+        // RUST_LOG still applies to other crates, but our own logs are
+        // silenced unless something actually goes wrong
+        builder.filter_module("mdbook_linkcheck", LevelFilter::Error);
+    }
+
+    builder.init();
+}
+
+fn exit_code(e: &Error) -> i32 {
+    if e.downcast_ref::<BrokenLinks>().is_some() {
+        EXIT_BROKEN_LINKS
+    } else if e.downcast_ref::<TimedOut>().is_some() {
+        EXIT_TIMED_OUT
+    } else if e.downcast_ref::<ConfigError>().is_some() {
+        EXIT_CONFIG_ERROR
+    } else {
+        EXIT_INTERNAL_ERROR
+    }
+}
+
+fn run(mut args: Args) -> Result<(), Error> {
+    // annotate pull requests when running on GitHub Actions, unless the user
+    // asked for something else
+    if args.format.is_none()
+        && std::env::var("GITHUB_ACTIONS").as_deref() == Ok("true")
+    {
+        args.format = Some(Format::Report(ReportFormat::Github));
+    }
+
+    if let Some(ref pattern) = args.merge_reports {
+        let format = match args.format {
+            Some(Format::Report(format)) => format,
+            Some(Format::TextCompact) | None => ReportFormat::Text,
+        };
+        return merge_reports(pattern, format);
+    }
+
+    if args.roots.len() > 1 {
+        return check_books(&args);
+    }
 
     // get a `RenderContext`, either from stdin (because we're used as a plugin)
     // or by instrumenting MDBook directly (in standalone mode).
     let ctx: RenderContext = if args.standalone {
-        let md =
-            MDBook::load(dunce::canonicalize(&args.root)?).map_err(to_sync)?;
-        let destination = md.build_dir_for("linkcheck");
-        RenderContext::new(md.root, md.book, md.config, destination)
+        load_standalone(&args.roots[0])?
+    } else if let Some(ref path) = args.context {
+        let f = File::open(path).with_context(|| {
+            ConfigError(format!(
+                "Unable to open the RenderContext at \"{}\"",
+                path.display()
+            ))
+        })?;
+        parse_render_context(BufReader::new(f))?
     } else {
-        serde_json::from_reader(io::stdin())
-            .context("Unable to parse RenderContext")?
+        parse_render_context(io::stdin())?
+    };
+
+    check_book(&args, &ctx, None)
+}
+
+/// What books checked in the same invocation share with each other.
+struct Shared {
+    runtime: Arc<tokio::runtime::Runtime>,
+    /// The cache file used by the first book with each
+    /// [`Config::fingerprint()`][fp], so books which make web requests
+    /// differently don't throw away each other's results.
+    ///
+    /// [fp]: mdbook_linkcheck::Config::fingerprint
+    cache_files: HashMap<String, PathBuf>,
+}
+
+/// Check several books one after another on the same runtime. Books with the
+/// same web request settings share a cache, so a web link used by more than
+/// one of them is only checked once.
+fn check_books(args: &Args) -> Result<(), Error> {
+    if !args.standalone {
+        return Err(Error::msg(ConfigError(String::from(
+            "Multiple books can only be checked when running standalone",
+        ))));
+    }
+    if args.files_from.is_some()
+        || args.report.is_some()
+        || args.junit.is_some()
+    {
+        return Err(Error::msg(ConfigError(String::from(
+            "--files-from, --report, and --junit can only be used when checking a single book",
+        ))));
+    }
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Unable to start the async runtime")?;
+    let mut shared = Shared {
+        runtime: Arc::new(runtime),
+        cache_files: HashMap::new(),
+    };
+    let mut errors = Vec::new();
+
+    for root in &args.roots {
+        log::info!("Checking the book at \"{}\"", root.display());
+
+        let result = load_standalone(root)
+            .and_then(|ctx| check_book(args, &ctx, Some(&mut shared)));
+
+        if let Err(e) = result {
+            errors.push(e.context(format!(
+                "Unable to check the book at \"{}\"",
+                root.display()
+            )));
+        }
+    }
+
+    // the most serious failure decides the exit code, the rest are printed
+    let worst = match (0..errors.len()).max_by_key(|&i| precedence(&errors[i]))
+    {
+        Some(worst) => errors.remove(worst),
+        None => return Ok(()),
+    };
+    for e in errors {
+        eprintln!("Error: {:?}", e);
+    }
+
+    Err(worst)
+}
+
+/// When several books fail, which failure decides the exit code.
+fn precedence(e: &Error) -> u8 {
+    match exit_code(e) {
+        EXIT_BROKEN_LINKS => 0,
+        EXIT_TIMED_OUT => 1,
+        EXIT_CONFIG_ERROR => 2,
+        _ => 3,
+    }
+}
+
+/// Get a [`RenderContext`] by instrumenting [`MDBook`] directly, the same as
+/// `mdbook build` would.
+fn load_standalone(root: &Path) -> Result<RenderContext, Error> {
+    let root = dunce::canonicalize(root).with_context(|| {
+        ConfigError(format!(
+            "Unable to find the book at \"{}\"",
+            root.display()
+        ))
+    })?;
+    let md = MDBook::load(root)
+        .map_err(to_sync)
+        .context(ConfigError(String::from("Unable to load the book")))?;
+    // run the preprocessors so we see the same chapters (e.g. with
+    // `{{#include}}`s expanded) as we would when mdbook invokes us
+    let (book, _) = md
+        .preprocess_book(&Standalone)
+        .map_err(to_sync)
+        .context("Unable to preprocess the book")?;
+    let destination = md.build_dir_for("linkcheck");
+
+    Ok(RenderContext::new(md.root, book, md.config, destination))
+}
+
+/// Check a single book, optionally sharing a runtime and cache with other
+/// books.
+fn check_book(
+    args: &Args,
+    ctx: &RenderContext,
+    mut shared: Option<&mut Shared>,
+) -> Result<(), Error> {
+    let cfg = mdbook_linkcheck::get_book_config(&ctx.root, &ctx.config)?;
+    let default_cache_file = ctx.destination.join("cache.json");
+    let cache_file = match (cfg.cache_file(&ctx.root), shared.as_mut()) {
+        (Some(cache_file), _) => cache_file,
+        (None, Some(shared)) => shared
+            .cache_files
+            .entry(cfg.fingerprint())
+            .or_insert(default_cache_file)
+            .clone(),
+        (None, None) => default_cache_file,
     };
 
-    let cache_file = ctx.destination.join("cache.json");
+    if args.show_cache {
+        println!("{}", mdbook_linkcheck::cache_summary(&cache_file, &cfg));
+        return Ok(());
+    }
+
+    if args.clear_cache {
+        mdbook_linkcheck::clear_cache(&cache_file)?;
+    }
+
+    let mut selected_files = args.selected_files.clone();
+
+    if let Some(ref files_from) = args.files_from {
+        let src_dir = &ctx.config.book.src;
+        let listed = if files_from == Path::new("-") {
+            // otherwise stdin is where mdbook sends the RenderContext
+            if !args.standalone && args.context.is_none() {
+                return Err(Error::msg(ConfigError(String::from(
+                    "Files can only be read from stdin when running standalone or with --context",
+                ))));
+            }
+            let stdin = io::stdin();
+            read_file_list(stdin.lock(), src_dir)?
+        } else {
+            let f = File::open(files_from).with_context(|| {
+                format!("Unable to open \"{}\"", files_from.display())
+            })?;
+            read_file_list(BufReader::new(f), src_dir)?
+        };
+
+        selected_files.get_or_insert_with(Vec::new).extend(listed);
+    }
+
+    if let Some(ref git_ref) = args.changed_since {
+        let diff = git_diff(&ctx.root, git_ref)?;
+        let changed = changed_chapters(
+            diff.as_bytes(),
+            &ctx.config.book.src,
+            &cfg.markdown_extensions,
+        )?;
+        log::info!(
+            "{} chapters have changed since \"{}\"",
+            changed.len(),
+            git_ref
+        );
+
+        selected_files.get_or_insert_with(Vec::new).extend(changed);
+    }
+
     let cache_file = if args.no_cache {
         None
     } else {
-        Some(cache_file.as_path())
+        Some(cache_file)
+    };
+    let mut options = RunOptions::default();
+    options.cache_file = cache_file;
+    options.colour = args.colour;
+    options.selected_files = selected_files;
+    options.progress = args.progress;
+    options.report_file = args.report.clone();
+    options.junit_file = args.junit.clone();
+    options.format = match args.format {
+        Some(Format::Report(format)) => Some(format),
+        Some(Format::TextCompact) | None => None,
+    };
+    options.compact = args.format == Some(Format::TextCompact);
+    options.scope = if args.internal_only {
+        LinkScope::Internal
+    } else if args.external_only {
+        LinkScope::External
+    } else {
+        LinkScope::All
+    };
+    options.grouped = args.grouped;
+    options.max_duration = args.max_duration.map(Duration::from_secs);
+    options.explain = args.explain;
+    options.quiet = args.quiet;
+    options.runtime = shared.map(|shared| Arc::clone(&shared.runtime));
+    options.overrides = ConfigOverrides {
+        follow_web_links: if args.follow_web_links {
+            Some(true)
+        } else if args.no_follow_web_links {
+            Some(false)
+        } else {
+            None
+        },
+        user_agent: args.user_agent.clone(),
+        request_timeout: args.request_timeout,
+        exclude: args.exclude.clone(),
+        strict: args.strict,
     };
-    mdbook_linkcheck::run(cache_file, args.colour, &ctx, args.selected_files)
+
+    mdbook_linkcheck::run_with_options(ctx, &options)
 }
 
 #[derive(Debug, Clone, StructOpt)]
@@ -38,11 +337,18 @@ struct Args {
     )]
     standalone: bool,
     #[structopt(
-        help = "The book to render.",
+        long = "context",
+        help = "Read the JSON RenderContext from this file instead of stdin (e.g. one captured from an earlier run).",
+        parse(from_os_str),
+        conflicts_with = "standalone"
+    )]
+    context: Option<PathBuf>,
+    #[structopt(
+        help = "The book to render. Several books can be checked one after another when running standalone.",
         parse(from_os_str),
         default_value = "."
     )]
-    root: PathBuf,
+    roots: Vec<PathBuf>,
     #[structopt(
         short = "c",
         long = "colour",
@@ -56,14 +362,138 @@ struct Args {
         short = "f",
         long = "files",
         help = "Check only the given files (check all files if omitted).
-Paths must be relative to the book root, e.g. 'chapter1/section1.md'."
+Paths must be relative to the book root, e.g. 'chapter1/section1.md', and may be globs like 'chapter*/*.md'.",
+        conflicts_with_all = &["files-from", "changed-since"]
     )]
     selected_files: Option<Vec<String>>,
+    #[structopt(
+        long = "files-from",
+        help = "Check only the files listed in this file, one per line (use '-' to read from stdin when running standalone or with --context).
+Paths may also include the book's source directory, e.g. 'src/chapter1/section1.md'.",
+        parse(from_os_str)
+    )]
+    files_from: Option<PathBuf>,
+    #[structopt(
+        long = "changed-since",
+        help = "Check only the chapters which changed since this git ref (e.g. 'origin/main'), according to 'git diff'."
+    )]
+    changed_since: Option<String>,
+    #[structopt(
+        long = "internal-only",
+        help = "Only check links within the book, without making any web requests.",
+        conflicts_with = "external-only"
+    )]
+    internal_only: bool,
+    #[structopt(
+        long = "external-only",
+        help = "Only check web links, skipping links to files in the book."
+    )]
+    external_only: bool,
     #[structopt(
         long = "no-cache",
         help = "Ignore any existing cache, neither using nor updating it."
     )]
     no_cache: bool,
+    #[structopt(
+        long = "clear-cache",
+        help = "Delete the cache before checking links."
+    )]
+    clear_cache: bool,
+    #[structopt(
+        long = "show-cache",
+        help = "Print a summary of the cache instead of checking links."
+    )]
+    show_cache: bool,
+    #[structopt(
+        long = "progress",
+        help = "Show a progress bar while checking links."
+    )]
+    progress: bool,
+    #[structopt(
+        long = "report",
+        help = "Save a JSON report of any problems to this file so it can be merged with --merge-reports later.",
+        parse(from_os_str)
+    )]
+    report: Option<PathBuf>,
+    #[structopt(
+        long = "junit",
+        help = "Write a JUnit XML report to this file.",
+        parse(from_os_str)
+    )]
+    junit: Option<PathBuf>,
+    #[structopt(
+        long = "merge-reports",
+        help = "Merge all the reports matching this glob (e.g. 'reports/*.json') instead of checking a book."
+    )]
+    merge_reports: Option<String>,
+    #[structopt(
+        long = "format",
+        help = "Print problems to stdout in this format. Merged reports default to \"text\", and \"github\" is used by default on GitHub Actions.
+\"text-compact\" prints one \"file:line:column: message\" line per problem instead of the usual diagnostics.",
+        parse(try_from_str = parse_format),
+        possible_values = &["text", "text-compact", "json", "rdjson", "github"]
+    )]
+    format: Option<Format>,
+    #[structopt(
+        long = "grouped",
+        help = "Print a header with the number of issues before the diagnostics for each file."
+    )]
+    grouped: bool,
+    #[structopt(
+        long = "max-duration",
+        help = "Stop checking links after this many seconds, reporting any links which weren't checked as skipped."
+    )]
+    max_duration: Option<u64>,
+    #[structopt(
+        long = "explain",
+        help = "Show the full path each broken link to a local file resolved to."
+    )]
+    explain: bool,
+    #[structopt(
+        short = "q",
+        long = "quiet",
+        help = "Only print broken links, and a summary if any were found.",
+        conflicts_with = "progress"
+    )]
+    quiet: bool,
+    #[structopt(
+        long = "follow-web-links",
+        help = "Check web links, regardless of the follow-web-links setting in book.toml.",
+        conflicts_with = "no-follow-web-links"
+    )]
+    follow_web_links: bool,
+    #[structopt(
+        long = "no-follow-web-links",
+        help = "Don't check web links, regardless of the follow-web-links setting in book.toml."
+    )]
+    no_follow_web_links: bool,
+    #[structopt(
+        long = "user-agent",
+        help = "The user-agent to send with web requests, instead of the one in book.toml."
+    )]
+    user_agent: Option<String>,
+    #[structopt(
+        long = "request-timeout",
+        help = "Give up on a web request after this many seconds, instead of using the request-timeout in book.toml."
+    )]
+    request_timeout: Option<u64>,
+    #[structopt(
+        long = "exclude",
+        help = "Ignore links matching this regular expression, as well as the exclude patterns in book.toml. May be used more than once.",
+        number_of_values = 1
+    )]
+    exclude: Vec<HashedRegex>,
+    #[structopt(
+        long = "strict",
+        help = "Treat warnings as errors, the same as setting warning-policy = \"error\" in book.toml."
+    )]
+    strict: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Report(ReportFormat),
+    TextCompact,
 }
 
 fn parse_colour(raw: &str) -> Result<ColorChoice, Error> {
@@ -76,6 +506,163 @@ fn parse_colour(raw: &str) -> Result<ColorChoice, Error> {
     }
 }
 
+fn parse_format(raw: &str) -> Result<Format, Error> {
+    match raw.to_lowercase().as_str() {
+        "text" => Ok(Format::Report(ReportFormat::Text)),
+        "text-compact" => Ok(Format::TextCompact),
+        "json" => Ok(Format::Report(ReportFormat::Json)),
+        "rdjson" => Ok(Format::Report(ReportFormat::Rdjson)),
+        "github" => Ok(Format::Report(ReportFormat::Github)),
+        _ => Err(Error::msg("Unknown report format")),
+    }
+}
+
+fn merge_reports(pattern: &str, format: ReportFormat) -> Result<(), Error> {
+    let report = mdbook_linkcheck::merge_reports(pattern)?;
+
+    let stdout = io::stdout();
+    report.write(stdout.lock(), format)?;
+
+    if report.has_errors() {
+        Err(Error::new(BrokenLinks))
+    } else {
+        Ok(())
+    }
+}
+
+/// Read a newline-separated list of files to check (e.g. the output of
+/// `git diff --name-only`), making each path relative to the book's source
+/// directory.
+fn read_file_list<R: BufRead>(
+    reader: R,
+    src_dir: &Path,
+) -> Result<Vec<String>, Error> {
+    let mut files = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.context("Unable to read the list of files")?;
+        let path = Path::new(line.trim());
+        if path.as_os_str().is_empty() {
+            continue;
+        }
+
+        let path = path.strip_prefix(".").unwrap_or(path);
+        let path = path.strip_prefix(src_dir).unwrap_or(path);
+        files.push(path.display().to_string());
+    }
+
+    Ok(files)
+}
+
+/// Ask git which files (relative to the book's root directory) have changed
+/// since `git_ref`, leaving out any which were deleted.
+fn git_diff(root: &Path, git_ref: &str) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("diff")
+        .arg("--name-only")
+        .arg("--relative")
+        .arg("--diff-filter=d")
+        .arg(git_ref)
+        .arg("--")
+        .current_dir(root)
+        .output()
+        .context("Unable to run git")?;
+
+    if !output.status.success() {
+        return Err(Error::msg(ConfigError(format!(
+            "Unable to find the files which changed since \"{}\": {}",
+            git_ref,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))));
+    }
+
+    String::from_utf8(output.stdout)
+        .context("git printed file names which aren't valid UTF-8")
+}
+
+/// Pick out the chapters from a list of changed files (e.g. the output of
+/// `git diff --name-only`), relative to the book's source directory.
+fn changed_chapters<R: BufRead>(
+    reader: R,
+    src_dir: &Path,
+    markdown_extensions: &[String],
+) -> Result<Vec<String>, Error> {
+    let mut chapters = Vec::new();
+
+    for line in reader.lines() {
+        let line = line.context("Unable to read the list of changed files")?;
+        let path = match Path::new(line.trim()).strip_prefix(src_dir) {
+            Ok(path) => path,
+            // not part of the book (e.g. the README)
+            Err(_) => continue,
+        };
+        let is_markdown = match path.extension() {
+            Some(ext) => markdown_extensions
+                .iter()
+                .any(|markdown| OsStr::new(markdown) == ext),
+            None => false,
+        };
+
+        if is_markdown {
+            chapters.push(path.display().to_string());
+        }
+    }
+
+    Ok(chapters)
+}
+
+/// Parse the [`RenderContext`] passed to us by `mdbook`.
+///
+/// Deserializing straight into a [`RenderContext`] means any field `mdbook`
+/// doesn't know about (e.g. a newer `rust.edition`) will abort the whole run,
+/// so we go via a [`Value`] and only pull out the bits we actually use.
+fn parse_render_context<R: Read>(reader: R) -> Result<RenderContext, Error> {
+    let mut raw: Value = serde_json::from_reader(reader)
+        .context("Unable to parse RenderContext")?;
+
+    let version: String = take_field(&mut raw, "version")?;
+    let root: PathBuf = take_field(&mut raw, "root")?;
+    let book: Book = take_field(&mut raw, "book")?;
+    let destination: PathBuf = take_field(&mut raw, "destination")?;
+
+    // we never look at the `[rust]` table, so drop it instead of letting
+    // unknown editions trip up the deserializer
+    if let Some(table) = raw.get_mut("config").and_then(Value::as_object_mut) {
+        table.remove("rust");
+    }
+    let config: mdbook::Config = take_field(&mut raw, "config")?;
+
+    let mut ctx = RenderContext::new(root, book, config, destination);
+    ctx.version = version;
+
+    Ok(ctx)
+}
+
+fn take_field<T: DeserializeOwned>(
+    raw: &mut Value,
+    name: &str,
+) -> Result<T, Error> {
+    let value = raw.get_mut(name).map(Value::take).with_context(|| {
+        format!("The RenderContext has no \"{}\" field", name)
+    })?;
+
+    serde_json::from_value(value).with_context(|| {
+        format!("Unable to parse the RenderContext's \"{}\"", name)
+    })
+}
+
+/// A stand-in for the real renderer, used when running the preprocessors in
+/// standalone mode.
+struct Standalone;
+
+impl Renderer for Standalone {
+    fn name(&self) -> &str { "linkcheck" }
+
+    fn render(&self, _ctx: &RenderContext) -> mdbook::errors::Result<()> {
+        Ok(())
+    }
+}
+
 fn to_sync(err: mdbook::errors::Error) -> Error {
     use std::{
         fmt::{self, Display, Formatter},
@@ -95,3 +682,69 @@ fn to_sync(err: mdbook::errors::Error) -> Error {
 
     Error::from(Synchronised(Mutex::new(err)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_files_relative_to_the_source_directory() {
+        let stdin = "src/chapter_1.md\n\n./src/nested/README.md\nsibling.md\n";
+
+        let got = read_file_list(stdin.as_bytes(), Path::new("src")).unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                "chapter_1.md",
+                Path::new("nested").join("README.md").to_str().unwrap(),
+                "sibling.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_check_chapters_which_changed() {
+        let diff = "README.md\nbook.toml\nsrc/chapter_1.md\n\
+                    src/images/logo.png\nsrc/nested/chapter_2.markdown\n";
+        let markdown_extensions =
+            vec![String::from("md"), String::from("markdown")];
+
+        let got = changed_chapters(
+            diff.as_bytes(),
+            Path::new("src"),
+            &markdown_extensions,
+        )
+        .unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                "chapter_1.md",
+                Path::new("nested")
+                    .join("chapter_2.markdown")
+                    .to_str()
+                    .unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn tolerate_unknown_rust_editions() {
+        let ctx = RenderContext::new(
+            "/path/to/book",
+            Book::new(),
+            mdbook::Config::default(),
+            "/path/to/book/book/linkcheck",
+        );
+        let mut raw = serde_json::to_value(&ctx).unwrap();
+        raw["config"]["rust"] = serde_json::json!({ "edition": "2024" });
+
+        let got = parse_render_context(raw.to_string().as_bytes()).unwrap();
+
+        assert_eq!(got.version, ctx.version);
+        assert_eq!(got.root, ctx.root);
+        assert_eq!(got.destination, ctx.destination);
+        assert_eq!(got.config.book.title, ctx.config.book.title);
+    }
+}
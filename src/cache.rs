@@ -0,0 +1,442 @@
+use crate::CacheTimeout;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// The results of previous web requests, persisted between runs so we don't
+/// need to hit the same servers every time a book is built.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cache {
+    /// The version of the cache format. Caches written by older versions of
+    /// `mdbook-linkcheck` won't have this field.
+    #[serde(default)]
+    version: u32,
+    /// The [`Config::fingerprint()`] used when these entries were checked.
+    ///
+    /// [`Config::fingerprint()`]: crate::Config::fingerprint
+    #[serde(default)]
+    config_fingerprint: Option<String>,
+    /// How the cache was used during the most recent run.
+    #[serde(default)]
+    stats: CacheStats,
+    entries: HashMap<String, CacheEntry>,
+    /// Local files which were found while resolving links, keyed by the
+    /// path that was linked to.
+    #[serde(default)]
+    local_files: HashMap<PathBuf, LocalFileEntry>,
+    /// Results for each translation of a multilingual book, keyed by
+    /// language.
+    #[serde(default)]
+    translations: HashMap<String, TranslationCache>,
+}
+
+/// The results which belong to a single translation.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct TranslationCache {
+    /// Web results, when translations don't share them (see
+    /// [`Config::shared_web_cache`]).
+    ///
+    /// [`Config::shared_web_cache`]: crate::Config::shared_web_cache
+    #[serde(default)]
+    entries: HashMap<String, CacheEntry>,
+    #[serde(default)]
+    local_files: HashMap<PathBuf, LocalFileEntry>,
+}
+
+impl Cache {
+    /// The current version of the cache format.
+    pub const FORMAT_VERSION: u32 = 2;
+
+    /// Was this cache written using the current [`Cache::FORMAT_VERSION`]?
+    pub fn is_current_format(&self) -> bool {
+        self.version == Cache::FORMAT_VERSION
+    }
+
+    /// The fingerprint of the config these results were created with, if
+    /// known.
+    pub fn config_fingerprint(&self) -> Option<&str> {
+        self.config_fingerprint.as_deref()
+    }
+
+    /// Record which config these results were created with.
+    pub fn set_config_fingerprint<S: Into<String>>(&mut self, fingerprint: S) {
+        self.config_fingerprint = Some(fingerprint.into());
+    }
+
+    /// Get the most recent result for a URL.
+    pub fn lookup(&self, url: &str) -> Option<&CacheEntry> {
+        self.entries.get(url)
+    }
+
+    /// Record the result of checking a URL.
+    pub fn insert<S: Into<String>>(&mut self, url: S, entry: CacheEntry) {
+        self.entries.insert(url.into(), entry);
+    }
+
+    /// Was this URL successfully checked within the last `timeout`?
+    pub fn is_still_valid(&self, url: &str, timeout: Duration) -> bool {
+        match self.fresh(url, timeout) {
+            Some(entry) => entry.successful,
+            None => false,
+        }
+    }
+
+    /// Get the result for a URL if it was checked within the last `timeout`.
+    pub fn fresh(&self, url: &str, timeout: Duration) -> Option<&CacheEntry> {
+        self.lookup(url).filter(|entry| entry.elapsed() < timeout)
+    }
+
+    /// How the cache was used during the most recent run.
+    pub fn stats(&self) -> CacheStats { self.stats }
+
+    /// Record that a URL's result was taken from the cache.
+    pub fn record_hit(&mut self) { self.stats.hits += 1; }
+
+    /// Record that a URL needed to be checked on the web.
+    pub fn record_miss(&mut self) { self.stats.misses += 1; }
+
+    /// Record that a local file was found using the cache instead of the file
+    /// system.
+    pub fn record_local_hit(&mut self) { self.stats.local_hits += 1; }
+
+    /// Get where a link to a local path resolved to last time, if it was
+    /// found.
+    pub fn lookup_local_file(&self, path: &Path) -> Option<&LocalFileEntry> {
+        self.local_files.get(path)
+    }
+
+    /// Remember where a link to a local path resolved to.
+    pub fn insert_local_file<P: Into<PathBuf>>(
+        &mut self,
+        path: P,
+        entry: LocalFileEntry,
+    ) {
+        self.local_files.insert(path.into(), entry);
+    }
+
+    /// Take out the results for one translation of a multilingual book so it
+    /// can be checked on its own. Local files are always kept separate for
+    /// each translation, while web results are only separate when
+    /// `share_web_results` is `false`.
+    ///
+    /// Use [`Cache::restore_translation()`] to put the results back
+    /// afterwards.
+    pub fn take_translation(
+        &mut self,
+        language: &str,
+        share_web_results: bool,
+    ) -> Cache {
+        let translation =
+            self.translations.remove(language).unwrap_or_default();
+        let entries = if share_web_results {
+            std::mem::take(&mut self.entries)
+        } else {
+            translation.entries
+        };
+
+        Cache {
+            version: self.version,
+            config_fingerprint: self.config_fingerprint.clone(),
+            stats: CacheStats::default(),
+            entries,
+            local_files: translation.local_files,
+            translations: HashMap::new(),
+        }
+    }
+
+    /// Put back the results taken out by [`Cache::take_translation()`],
+    /// including anything learned while checking the translation.
+    pub fn restore_translation(
+        &mut self,
+        language: &str,
+        translation: Cache,
+        share_web_results: bool,
+    ) {
+        let Cache {
+            stats,
+            entries,
+            local_files,
+            ..
+        } = translation;
+        let mut restored = TranslationCache {
+            entries: HashMap::new(),
+            local_files,
+        };
+
+        if share_web_results {
+            self.entries = entries;
+        } else {
+            restored.entries = entries;
+        }
+
+        self.stats.merge(stats);
+        self.translations.insert(language.to_string(), restored);
+    }
+
+    /// Start counting hits and misses from zero.
+    pub fn reset_stats(&mut self) { self.stats = CacheStats::default(); }
+
+    /// Summarise the contents of the cache, treating anything older than its
+    /// `timeout` as expired.
+    pub fn summary(&self, timeout: &CacheTimeout) -> CacheSummary {
+        CacheSummary {
+            entries: self.len(),
+            expired: self
+                .entries()
+                .filter(|(url, entry)| entry.elapsed() >= timeout.for_url(url))
+                .count(),
+            stats: self.stats,
+        }
+    }
+
+    /// Iterate over all the cached URLs and their results, including those
+    /// kept separately for each translation.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &CacheEntry)> + '_ {
+        self.entries
+            .iter()
+            .chain(
+                self.translations
+                    .values()
+                    .flat_map(|translation| translation.entries.iter()),
+            )
+            .map(|(url, entry)| (url.as_str(), entry))
+    }
+
+    /// The number of URLs in the cache.
+    pub fn len(&self) -> usize { self.entries().count() }
+
+    /// Is the cache empty?
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            version: Cache::FORMAT_VERSION,
+            config_fingerprint: None,
+            stats: CacheStats::default(),
+            entries: HashMap::new(),
+            local_files: HashMap::new(),
+            translations: HashMap::new(),
+        }
+    }
+}
+
+/// Counters for how often the cache was used.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct CacheStats {
+    /// The number of URLs whose result was taken from the cache.
+    pub hits: u64,
+    /// The number of URLs which needed to be checked on the web.
+    pub misses: u64,
+    /// The number of links to local files which were resolved without
+    /// touching the file system.
+    #[serde(default)]
+    pub local_hits: u64,
+}
+
+impl CacheStats {
+    /// Add the counters from another [`CacheStats`] (e.g. from a
+    /// translation's cache) to this one.
+    pub fn merge(&mut self, other: CacheStats) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.local_hits += other.local_hits;
+    }
+}
+
+/// A human-friendly overview of a [`Cache`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CacheSummary {
+    /// The number of cached URLs.
+    pub entries: usize,
+    /// How many of those are older than [`Config::cache_timeout`].
+    ///
+    /// [`Config::cache_timeout`]: crate::Config::cache_timeout
+    pub expired: usize,
+    /// How the cache was used during the most recent run.
+    pub stats: CacheStats,
+}
+
+impl Display for CacheSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Entries: {} ({} expired)", self.entries, self.expired)?;
+        write!(
+            f,
+            "Last run: {} hits, {} misses",
+            self.stats.hits, self.stats.misses
+        )
+    }
+}
+
+/// The result of checking a single URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// When the URL was checked.
+    pub timestamp: SystemTime,
+    /// Was the URL valid?
+    pub successful: bool,
+    /// The HTTP status code the server responded with, if we got that far.
+    #[serde(default)]
+    pub status: Option<u16>,
+    /// A short description of why the check failed.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Where the URL ended up after following redirects, if it was
+    /// redirected.
+    #[serde(default)]
+    pub redirected_to: Option<String>,
+    /// How much of the body was read, if we were checking that pages aren't
+    /// suspiciously small.
+    #[serde(default)]
+    pub body_bytes: Option<u64>,
+    /// The soft 404 pattern which matched the body, if any (see
+    /// [`Config::soft_404_patterns`]).
+    ///
+    /// [`Config::soft_404_patterns`]: crate::Config::soft_404_patterns
+    #[serde(default)]
+    pub soft_404: Option<String>,
+    /// How many times in a row the URL has failed, including this check.
+    #[serde(default)]
+    pub consecutive_failures: u32,
+}
+
+/// Where a link to a local file resolved to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalFileEntry {
+    /// The file the link resolved to.
+    pub resolved: PathBuf,
+    /// When the directory containing [`LocalFileEntry::resolved`] was last
+    /// modified. Adding, removing or renaming a file changes this, so the
+    /// entry is only trusted while it stays the same.
+    pub directory_modified: SystemTime,
+    /// The settings which affect how links are resolved (see
+    /// [`Config::resolution_fingerprint()`]).
+    ///
+    /// [`Config::resolution_fingerprint()`]: crate::Config::resolution_fingerprint
+    pub settings: String,
+}
+
+impl CacheEntry {
+    /// Create a new [`CacheEntry`] for a check which happened just now.
+    pub fn new(successful: bool) -> Self {
+        CacheEntry {
+            timestamp: SystemTime::now(),
+            successful,
+            status: None,
+            reason: None,
+            redirected_to: None,
+            body_bytes: None,
+            soft_404: None,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Create a new [`CacheEntry`] for a check which failed just now.
+    pub fn failed(status: Option<u16>, reason: Option<String>) -> Self {
+        CacheEntry {
+            status,
+            reason,
+            consecutive_failures: 1,
+            ..CacheEntry::new(false)
+        }
+    }
+
+    /// How long ago was this entry created?
+    pub fn elapsed(&self) -> Duration {
+        self.timestamp.elapsed().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarise_the_cache() {
+        let timeout = CacheTimeout::from(60);
+        let mut cache = Cache::default();
+        cache.insert("https://example.com/", CacheEntry::new(true));
+        cache.insert(
+            "https://example.com/old",
+            CacheEntry {
+                timestamp: SystemTime::now() - Duration::from_secs(120),
+                ..CacheEntry::new(true)
+            },
+        );
+        cache.record_hit();
+        cache.record_miss();
+        cache.record_miss();
+
+        let got = cache.summary(&timeout);
+
+        assert_eq!(got.entries, 2);
+        assert_eq!(got.expired, 1);
+        assert_eq!(
+            got.stats,
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                local_hits: 0,
+            }
+        );
+        assert_eq!(
+            got.to_string(),
+            "Entries: 2 (1 expired)\nLast run: 1 hits, 2 misses"
+        );
+    }
+
+    #[test]
+    fn translations_only_share_web_results() {
+        let mut cache = Cache::default();
+        cache.insert("https://example.com/", CacheEntry::new(true));
+
+        for (language, shared) in &[("en", true), ("fr", false)] {
+            let mut translation = cache.take_translation(language, *shared);
+            assert!(translation.lookup_local_file(Path::new("a.md")).is_none());
+            assert_eq!(
+                translation.lookup("https://example.com/").is_some(),
+                *shared
+            );
+
+            translation.insert_local_file(
+                "a.md",
+                LocalFileEntry {
+                    resolved: PathBuf::from(language),
+                    directory_modified: SystemTime::now(),
+                    settings: String::new(),
+                },
+            );
+            translation.insert(
+                format!("https://example.com/{}", language),
+                CacheEntry::new(true),
+            );
+            translation.record_miss();
+            cache.restore_translation(language, translation, *shared);
+        }
+
+        // the English results went into the shared section
+        assert!(cache.lookup("https://example.com/en").is_some());
+        assert!(cache.lookup("https://example.com/fr").is_none());
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.stats().misses, 2);
+
+        let fr = cache.take_translation("fr", false);
+        assert!(fr.lookup("https://example.com/fr").is_some());
+        assert_eq!(
+            fr.lookup_local_file(Path::new("a.md")).unwrap().resolved,
+            PathBuf::from("fr")
+        );
+        let en = cache.take_translation("en", true);
+        assert_eq!(
+            en.lookup_local_file(Path::new("a.md")).unwrap().resolved,
+            PathBuf::from("en")
+        );
+    }
+}
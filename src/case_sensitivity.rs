@@ -0,0 +1,150 @@
+//! Noticing links which only work on case-insensitive file systems (e.g.
+//! `./Image.png` when the file is actually called `image.png`).
+
+use codespan::Files;
+use linkcheck::Link;
+use std::{
+    ffi::OsString,
+    path::{Component, Path},
+};
+
+/// A link whose path only matches a file on disk if case is ignored.
+#[derive(Debug, Clone)]
+pub struct CaseMismatch {
+    /// The original link.
+    pub link: Link,
+    /// The link, with each path component changed to match the file system.
+    pub suggestion: String,
+}
+
+/// Check every link to a file inside the book against the real directory
+/// entries.
+pub(crate) fn find_mismatches(
+    links: &[Link],
+    src_dir: &Path,
+    files: &Files<String>,
+) -> Vec<CaseMismatch> {
+    links
+        .iter()
+        .filter_map(|link| {
+            let (path, _) = crate::validate::resolve_in_book(link, files)?;
+            let mismatches = mismatched_components(src_dir, &path);
+
+            if mismatches.is_empty() {
+                None
+            } else {
+                Some(CaseMismatch {
+                    link: link.clone(),
+                    suggestion: suggestion(&link.href, &mismatches),
+                })
+            }
+        })
+        .collect()
+}
+
+/// Walk down from `root` and find each component of `path` whose name only
+/// matches a directory entry when case is ignored, returning the name used
+/// in `path` and the name on disk.
+///
+/// We need to read the directory entries ourselves because
+/// [`Path::exists()`] is happy with any casing on a case-insensitive file
+/// system.
+fn mismatched_components(root: &Path, path: &Path) -> Vec<(String, String)> {
+    let mut dir = root.to_path_buf();
+    let mut mismatches = Vec::new();
+
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            _ => break,
+        };
+        let entries: Vec<OsString> = match dir.read_dir() {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name())
+                .collect(),
+            Err(_) => break,
+        };
+
+        if entries.iter().any(|entry| entry == name) {
+            dir.push(name);
+            continue;
+        }
+
+        let name = name.to_string_lossy();
+        let actual = entries.iter().find(|entry| {
+            entry.to_string_lossy().to_lowercase() == name.to_lowercase()
+        });
+
+        match actual {
+            Some(actual) => {
+                mismatches.push((
+                    name.into_owned(),
+                    actual.to_string_lossy().into_owned(),
+                ));
+                dir.push(actual);
+            },
+            // the file doesn't exist (e.g. `chapter_1.html` which is really
+            // `chapter_1.md`), so there's nothing more we can compare
+            None => break,
+        }
+    }
+
+    mismatches
+}
+
+fn suggestion(href: &str, mismatches: &[(String, String)]) -> String {
+    let (path, rest) = match href.find(&['#', '?'][..]) {
+        Some(ix) => href.split_at(ix),
+        None => (href, ""),
+    };
+
+    let fixed: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            mismatches
+                .iter()
+                .find(|(written, _)| written == segment)
+                .map(|(_, actual)| actual.as_str())
+                .unwrap_or(segment)
+        })
+        .collect();
+
+    format!("{}{}", fixed.join("/"), rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn notice_when_the_case_differs_from_the_file_on_disk() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir(root.join("images")).unwrap();
+        std::fs::write(root.join("images").join("logo.png"), "").unwrap();
+
+        let got = mismatched_components(
+            root,
+            &PathBuf::from("Images").join("Logo.png"),
+        );
+
+        assert_eq!(
+            got,
+            vec![
+                (String::from("Images"), String::from("images")),
+                (String::from("Logo.png"), String::from("logo.png")),
+            ]
+        );
+        assert_eq!(
+            suggestion("./Images/Logo.png#top", &got),
+            "./images/logo.png#top"
+        );
+        assert!(mismatched_components(
+            root,
+            &PathBuf::from("images").join("logo.png")
+        )
+        .is_empty());
+    }
+}
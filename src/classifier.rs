@@ -0,0 +1,81 @@
+//! A hook for applications which embed `mdbook-linkcheck` and need to decide
+//! how certain links are handled (e.g. links using a custom URL scheme).
+
+use linkcheck::Link;
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+/// What should happen to a link, as decided by a [`LinkClassifier`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkClassification {
+    /// The link is always valid and won't be checked.
+    Valid,
+    /// The link is skipped, the same as if it matched [`Config::exclude`].
+    ///
+    /// [`Config::exclude`]: crate::Config::exclude
+    Ignored,
+    /// The link is checked normally, even if it would otherwise be skipped
+    /// (e.g. because it matched [`Config::exclude`] or
+    /// [`Config::follow_web_links`] is turned off).
+    ///
+    /// [`Config::exclude`]: crate::Config::exclude
+    /// [`Config::follow_web_links`]: crate::Config::follow_web_links
+    Checked,
+}
+
+/// A function which is consulted before any of the built-in rules for
+/// deciding how a link should be handled. Returning `None` falls back to the
+/// normal behaviour.
+///
+/// # Examples
+///
+/// ```rust
+/// use mdbook_linkcheck::{Config, LinkClassification, LinkClassifier};
+///
+/// let cfg = Config {
+///     // Slack links can only be opened by the Slack app
+///     link_classifier: Some(LinkClassifier::new(|link| {
+///         if link.href.starts_with("slack://") {
+///             Some(LinkClassification::Valid)
+///         } else {
+///             None
+///         }
+///     })),
+///     ..Default::default()
+/// };
+/// # let _ = cfg;
+/// ```
+#[derive(Clone)]
+pub struct LinkClassifier(Arc<ClassifyFn>);
+
+type ClassifyFn = dyn Fn(&Link) -> Option<LinkClassification> + Send + Sync;
+
+impl LinkClassifier {
+    /// Create a new [`LinkClassifier`].
+    pub fn new<F>(classify: F) -> Self
+    where
+        F: Fn(&Link) -> Option<LinkClassification> + Send + Sync + 'static,
+    {
+        LinkClassifier(Arc::new(classify))
+    }
+
+    /// Decide what should happen to a link.
+    pub fn classify(&self, link: &Link) -> Option<LinkClassification> {
+        (self.0)(link)
+    }
+}
+
+impl Debug for LinkClassifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LinkClassifier").field(&"..").finish()
+    }
+}
+
+/// Two classifiers are only equal if they are the same function.
+impl PartialEq for LinkClassifier {
+    fn eq(&self, other: &LinkClassifier) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
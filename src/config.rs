@@ -1,13 +1,20 @@
-use crate::hashed_regex::HashedRegex;
+use crate::{hashed_regex::HashedRegex, LinkClassification, LinkClassifier};
 use anyhow::Error;
-use http::header::{HeaderName, HeaderValue};
+use codespan_reporting::diagnostic::Severity;
+use fnv::FnvHasher;
+use http::header::{HeaderName, HeaderValue, AUTHORIZATION};
+use linkcheck::Link;
 use log::Level;
-use reqwest::Client;
+use reqwest::{redirect, Client, Proxy, Url};
+use serde::{de::Error as _, Deserialize as _, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
     collections::HashMap,
     convert::TryFrom,
     fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
     str::FromStr,
     time::Duration,
 };
@@ -22,25 +29,222 @@ pub struct Config {
     pub follow_web_links: bool,
     /// Are we allowed to link to files outside of the book's source directory?
     pub traverse_parent_directories: bool,
-    /// Turns on support for latex. If true, then the latex fragments will be
-    /// cut off before the file is processed for link consistency.
-    pub latex_support: bool,
+    /// The file a link to a directory (e.g. `nested/`) resolves to. This must
+    /// be a bare file name like `index.md`, not a path.
+    #[serde(
+        default = "default_index_file",
+        deserialize_with = "deserialize_file_name"
+    )]
+    pub default_index_file: String,
+    /// File extensions (without the leading `.`) used by chapters written in
+    /// markdown. Links to files with these extensions must be included in
+    /// `SUMMARY.md`, and links to `.html` files are resolved to them.
+    #[serde(default = "default_markdown_extensions")]
+    pub markdown_extensions: Vec<String>,
+    /// The URL the book is deployed to (e.g. `https://example.com/docs/`).
+    /// Absolute links starting with its path (e.g. `/docs/intro.md`) are
+    /// resolved relative to the book's source directory and don't trigger
+    /// the absolute link warning.
+    #[serde(deserialize_with = "deserialize_site_url")]
+    pub site_url: Option<String>,
+    /// Turns on support for latex. If enabled, then the latex fragments will
+    /// be cut off before the file is processed for link consistency.
+    pub latex_support: LatexSupport,
     /// A list of URL patterns to ignore when checking remote links.
     #[serde(default)]
     pub exclude: Vec<HashedRegex>,
+    /// If not empty, only links matching at least one of these patterns are
+    /// checked. [`Config::exclude`] takes precedence.
+    #[serde(default)]
+    pub include: Vec<HashedRegex>,
+    /// Links using these URL schemes (e.g. `javascript:` or `data:`) can't
+    /// be checked, so they are always ignored. `mailto:` and `tel:` links are
+    /// still checked when [`Config::check_mailto`] is set.
+    #[serde(default = "default_ignore_schemes")]
+    pub ignore_schemes: Vec<String>,
+    /// Ignore web links to `localhost` and private network addresses (e.g.
+    /// `127.0.0.1`, `192.168.1.1` or `fe80::1`), which readers usually can't
+    /// reach and often aren't running while the book is checked. Host names
+    /// are resolved first, so this also skips names which point somewhere
+    /// private.
+    pub ignore_private_networks: bool,
+    /// Glob patterns for chapters (relative to the book's source directory)
+    /// whose links should never be checked.
+    #[serde(default)]
+    pub exclude_files: Vec<String>,
+    /// Glob patterns for chapters (relative to the book's source directory)
+    /// which are part of the book even though they aren't in `SUMMARY.md`
+    /// (e.g. because a preprocessor generates them).
+    #[serde(default)]
+    pub additional_summary_files: Vec<String>,
+    /// Should linking to a chapter which exists on disk but isn't in
+    /// `SUMMARY.md` be reported? Defaults to `true`.
+    #[serde(default = "default_check_included_in_summary")]
+    pub check_included_in_summary: bool,
+    /// A message to use instead of the usual one when a chapter isn't in
+    /// `SUMMARY.md` (e.g. pointing to the project's own contributing guide).
+    /// Any `{path}` is replaced with the chapter's path.
+    pub not_in_summary_message: Option<String>,
     /// The user-agent used whenever any web requests are made.
     #[serde(default = "default_user_agent")]
     pub user_agent: String,
-    /// The number of seconds a cached result is valid for.
-    #[serde(default = "default_cache_timeout")]
-    pub cache_timeout: u64,
+    /// A pool of user-agents to take turns using for web requests instead of
+    /// always sending [`Config::user_agent`]. Environment variables are
+    /// interpolated the same way as [`Config::http_headers`].
+    #[serde(default)]
+    pub user_agents: Vec<String>,
+    /// How long a cached result is valid for.
+    #[serde(default)]
+    pub cache_timeout: CacheTimeout,
+    /// Where to keep the cache instead of `cache.json` in the output
+    /// directory (see [`Config::cache_file()`]).
+    pub cache_file: Option<PathBuf>,
+    /// How many runs in a row a web link needs to fail before it is reported
+    /// as broken. This smooths over transient outages on flaky sites.
+    #[serde(default = "default_consecutive_failures_threshold")]
+    pub consecutive_failures_threshold: u32,
     /// The policy to use when warnings are encountered.
     #[serde(default)]
     pub warning_policy: WarningPolicy,
+    /// How should links to absolute paths on the author's computer (e.g.
+    /// `/home/user/notes.md` or `C:\Users\user\notes.md`) be treated?
+    #[serde(default = "default_absolute_fs_path_policy")]
+    pub absolute_fs_path_policy: WarningPolicy,
+    /// Absolute links (e.g. `/index.md`) matching any of these regexes are
+    /// intentional, so they aren't warned about.
+    #[serde(default)]
+    pub allow_absolute_patterns: Vec<HashedRegex>,
+    /// The maximum number of web requests which may be in flight at any one
+    /// time.
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+    /// How many times to retry a web link which failed with a server error
+    /// (`5xx`) or timed out.
+    pub retries: u32,
+    /// The longest a server may ask us to wait (in seconds) with a
+    /// `Retry-After` header after responding with `429 Too Many Requests`.
+    /// Waiting any longer than this reports the link as broken.
+    #[serde(default = "default_max_retry_after")]
+    pub max_retry_after: u64,
+    /// How many seconds to wait for a web request before giving up on it.
+    /// Requests never time out by default.
+    pub request_timeout: Option<u64>,
+    /// Should the translations in a multilingual book share the results of
+    /// web requests, or should each language keep its own? Results for local
+    /// files are never shared between translations.
+    pub shared_web_cache: bool,
+    /// Should we make sure a link's fragment (e.g. the `#installation` in
+    /// `./setup.md#installation`) matches one of the headings in the file it
+    /// points to? Links like `#installation` are checked against the chapter
+    /// they were written in.
+    pub check_fragments: bool,
+    /// Should we make sure `mailto:` links contain a valid email address and
+    /// `tel:` links a valid phone number? Otherwise they are ignored.
+    pub check_mailto: bool,
+    /// Should bare URLs inside code blocks (e.g. in comments) be checked too?
+    pub check_code_blocks: bool,
+    /// Should bare URLs in the titles of links and images (e.g.
+    /// `[text](./page.md "See https://example.com/")`) be checked too?
+    pub check_titles: bool,
+    /// Skip links inside blockquotes (e.g. example links in a quoted
+    /// passage), counting them as ignored.
+    pub ignore_in_blockquotes: bool,
+    /// Warn when a link only works because the file system is
+    /// case-insensitive (e.g. `./Image.png` when the file is `image.png`).
+    pub warn_on_case_mismatch: bool,
+    /// Warn about links to `http://` URLs, suggesting the `https://`
+    /// equivalent instead.
+    pub warn_on_insecure_links: bool,
+    /// Always check web links with a `GET` request instead of trying a
+    /// (cheaper) `HEAD` request first, for servers which don't handle `HEAD`
+    /// properly. See [`Config::check_mode_for()`].
+    pub always_use_get: bool,
+    /// What to do when a web link redirects somewhere else.
+    pub redirect_policy: RedirectPolicy,
+    /// The maximum number of redirects to follow before giving up.
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+    /// Mention web links which were redirected somewhere else, even though
+    /// they still work. This is a quieter version of
+    /// [`RedirectPolicy::Warn`].
+    pub report_redirects: bool,
+    /// Report each broken link once, listing every place it was used,
+    /// instead of emitting a separate diagnostic for each occurrence.
+    pub deduplicate_reports: bool,
+    /// If set, successful responses from web links with a body smaller than
+    /// this many bytes are reported as suspicious.
+    pub min_body_bytes: Option<u64>,
+    /// Regexes which are matched against the body of web pages that
+    /// responded successfully. A match is reported as a possible "soft 404"
+    /// (a missing page which still returns `200 OK`). Only the first
+    /// [`Config::SOFT_404_BODY_LIMIT`] bytes of each page are searched.
+    #[serde(default)]
+    pub soft_404_patterns: Vec<HashedRegex>,
+    /// Stop reading a web page's body after this many bytes, even if more is
+    /// needed to check [`Config::min_body_bytes`] or
+    /// [`Config::soft_404_patterns`]. A page which is cut short is never
+    /// reported as being too small.
+    pub max_response_bytes: Option<u64>,
+    /// If checking links takes longer than this many seconds, emit a warning
+    /// listing the slowest hosts and files.
+    pub time_budget: Option<u64>,
+    /// Fail the run if more than this fraction (between `0.0` and `1.0`) of
+    /// all links were ignored, usually because of an overly broad
+    /// [`Config::exclude`].
+    pub max_ignored_ratio: Option<f64>,
+    /// The proxy to send `http://` requests through. Falls back to the
+    /// `HTTP_PROXY` environment variable.
+    #[serde(deserialize_with = "deserialize_proxy_url")]
+    pub http_proxy: Option<String>,
+    /// The proxy to send `https://` requests through. Falls back to the
+    /// `HTTPS_PROXY` environment variable.
+    #[serde(deserialize_with = "deserialize_proxy_url")]
+    pub https_proxy: Option<String>,
+    /// Hosts which should be contacted directly instead of going through a
+    /// proxy (e.g. `localhost` or `.example.com`). Falls back to the
+    /// `NO_PROXY` environment variable.
+    pub no_proxy: Vec<String>,
+    /// Regex substitutions applied (in order) to every link before it is
+    /// checked, so links can be checked the way the deployed site will
+    /// resolve them.
+    #[serde(default)]
+    pub link_rewrites: Vec<LinkRewrite>,
     /// The map of regexes representing sets of web sites and
     /// the list of HTTP headers that must be sent to matching sites.
+    ///
+    /// Patterns are matched against the URL's host unless
+    /// [`HeaderSet::match_full_url`] is set.
+    #[serde(default)]
+    pub http_headers: HashMap<HashedRegex, HeaderSet>,
+    /// Credentials to send to web sites whose host matches one of these
+    /// regexes, using HTTP basic authentication. Environment variables are
+    /// interpolated the same way as [`Config::http_headers`].
+    #[serde(default)]
+    pub basic_auth: HashMap<HashedRegex, BasicAuth>,
+    /// The `Content-Type` that web links matching one of these regexes must
+    /// respond with (e.g. `application/pdf`). Patterns are matched against
+    /// the whole URL and the longest matching pattern wins.
+    #[serde(default)]
+    pub expected_content_type: HashMap<HashedRegex, String>,
+    /// How web links matching one of these regexes are checked, for sites
+    /// which reject or rate-limit normal `GET` requests. Patterns are matched
+    /// against the whole URL and the longest matching pattern wins.
+    #[serde(default)]
+    pub check_mode: HashMap<HashedRegex, CheckMode>,
+    /// Anchors which will exist in a chapter once the book is rendered, but
+    /// can't be found in its source (e.g. the terms on an auto-generated
+    /// glossary page), keyed by the chapter's path relative to the source
+    /// directory.
     #[serde(default)]
-    pub http_headers: HashMap<HashedRegex, Vec<HttpHeader>>,
+    pub generated_anchors: HashMap<PathBuf, Vec<String>>,
+    /// Use a different [`WarningPolicy`] for some kinds of warnings.
+    #[serde(default)]
+    pub warning_policy_overrides: WarningPolicyOverrides,
+    /// Decides how links are handled before any of the built-in rules are
+    /// applied. This can only be set when using `mdbook-linkcheck` as a
+    /// library.
+    #[serde(skip)]
+    pub link_classifier: Option<LinkClassifier>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -50,6 +254,198 @@ pub struct HttpHeader {
     pub value: String,
 }
 
+/// The headers sent to web sites matching one of the
+/// [`Config::http_headers`] patterns.
+///
+/// In `book.toml` this is either a list of headers, or a table with a
+/// `headers` list and the `match-full-url` flag.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+#[serde(from = "RawHeaderSet", into = "RawHeaderSet")]
+pub struct HeaderSet {
+    /// The headers to send.
+    pub headers: Vec<HttpHeader>,
+    /// Match the pattern against the whole URL instead of just its host.
+    pub match_full_url: bool,
+}
+
+impl From<Vec<HttpHeader>> for HeaderSet {
+    fn from(headers: Vec<HttpHeader>) -> Self {
+        HeaderSet {
+            headers,
+            match_full_url: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawHeaderSet {
+    Headers(Vec<HttpHeader>),
+    #[serde(rename_all = "kebab-case")]
+    Table {
+        headers: Vec<HttpHeader>,
+        #[serde(default)]
+        match_full_url: bool,
+    },
+}
+
+impl From<RawHeaderSet> for HeaderSet {
+    fn from(raw: RawHeaderSet) -> Self {
+        match raw {
+            RawHeaderSet::Headers(headers) => HeaderSet::from(headers),
+            RawHeaderSet::Table {
+                headers,
+                match_full_url,
+            } => HeaderSet {
+                headers,
+                match_full_url,
+            },
+        }
+    }
+}
+
+impl From<HeaderSet> for RawHeaderSet {
+    fn from(set: HeaderSet) -> Self {
+        if set.match_full_url {
+            RawHeaderSet::Table {
+                headers: set.headers,
+                match_full_url: true,
+            }
+        } else {
+            RawHeaderSet::Headers(set.headers)
+        }
+    }
+}
+
+/// How long cached web results are trusted for (see
+/// [`Config::cache_timeout`]).
+///
+/// In `book.toml` this is either a number of seconds, or a table with the
+/// `default` number of seconds and `overrides` for URLs matching a regex.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(from = "RawCacheTimeout", into = "RawCacheTimeout")]
+pub struct CacheTimeout {
+    /// The number of seconds a cached result is valid for, unless one of the
+    /// [`CacheTimeout::overrides`] matches.
+    pub default: u64,
+    /// The number of seconds results for URLs matching these regexes are
+    /// valid for. When several match, the longest pattern wins.
+    pub overrides: HashMap<HashedRegex, u64>,
+}
+
+impl CacheTimeout {
+    /// How long the cached result for this URL is valid for.
+    pub fn for_url(&self, url: &str) -> Duration {
+        let seconds = self
+            .overrides
+            .iter()
+            .filter(|(pattern, _)| pattern.find(url).is_some())
+            .max_by_key(|(pattern, _)| pattern.string.len())
+            .map(|(_, &seconds)| seconds)
+            .unwrap_or(self.default);
+
+        Duration::from_secs(seconds)
+    }
+}
+
+impl Default for CacheTimeout {
+    fn default() -> Self { CacheTimeout::from(default_cache_timeout()) }
+}
+
+impl From<u64> for CacheTimeout {
+    fn from(default: u64) -> Self {
+        CacheTimeout {
+            default,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawCacheTimeout {
+    Seconds(u64),
+    Table {
+        #[serde(default = "default_cache_timeout")]
+        default: u64,
+        #[serde(default)]
+        overrides: HashMap<HashedRegex, u64>,
+    },
+}
+
+impl From<RawCacheTimeout> for CacheTimeout {
+    fn from(raw: RawCacheTimeout) -> Self {
+        match raw {
+            RawCacheTimeout::Seconds(default) => CacheTimeout::from(default),
+            RawCacheTimeout::Table { default, overrides } => {
+                CacheTimeout { default, overrides }
+            },
+        }
+    }
+}
+
+impl From<CacheTimeout> for RawCacheTimeout {
+    fn from(timeout: CacheTimeout) -> Self {
+        if timeout.overrides.is_empty() {
+            RawCacheTimeout::Seconds(timeout.default)
+        } else {
+            RawCacheTimeout::Table {
+                default: timeout.default,
+                overrides: timeout.overrides,
+            }
+        }
+    }
+}
+
+/// A username and password for HTTP basic authentication (see
+/// [`Config::basic_auth`]).
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct BasicAuth {
+    /// The username, which may contain environment variables.
+    pub username: String,
+    /// The password, which may contain environment variables.
+    pub password: String,
+}
+
+impl BasicAuth {
+    /// The value of the `Authorization` header, after interpolating
+    /// environment variables.
+    pub(crate) fn header_value(&self) -> Result<HeaderValue, Error> {
+        let credentials = format!(
+            "{}:{}",
+            interpolate_env_string(&self.username)?,
+            interpolate_env_string(&self.password)?
+        );
+        let mut value: HeaderValue =
+            format!("Basic {}", base64::encode(credentials)).parse()?;
+        value.set_sensitive(true);
+
+        Ok(value)
+    }
+}
+
+/// One of the [`Config::http_headers`] patterns, along with how it should be
+/// matched.
+#[derive(Debug, Clone)]
+pub(crate) struct HeaderPattern {
+    pub(crate) pattern: HashedRegex,
+    pub(crate) match_full_url: bool,
+}
+
+impl HeaderPattern {
+    /// Should the headers be sent when requesting this URL?
+    pub(crate) fn matches(&self, url: &Url) -> bool {
+        if self.match_full_url {
+            self.pattern.find(url.as_str()).is_some()
+        } else {
+            match url.host_str() {
+                Some(host) => self.pattern.find(host).is_some(),
+                None => false,
+            }
+        }
+    }
+}
+
 impl HttpHeader {
     pub(crate) fn interpolate(&self) -> Result<HeaderValue, Error> {
         interpolate_env(&self.value)
@@ -66,34 +462,348 @@ impl Config {
     /// The default cache timeout (around 12 hours).
     pub const DEFAULT_CACHE_TIMEOUT: Duration =
         Duration::from_secs(60 * 60 * 12);
+    /// The file links to a directory resolve to by default. The `index`
+    /// preprocessor renders it as `index.html`.
+    pub const DEFAULT_INDEX_FILE: &'static str = "README.md";
+    /// The default number of simultaneous web requests.
+    pub const DEFAULT_MAX_CONCURRENCY: usize = 20;
+    /// The default number of redirects to follow.
+    pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+    /// The default for [`Config::max_retry_after`], in seconds.
+    pub const DEFAULT_MAX_RETRY_AFTER: u64 = 30;
     /// The default user-agent.
     pub const DEFAULT_USER_AGENT: &'static str =
         concat!(env!("CARGO_PKG_NAME"), "-", env!("CARGO_PKG_VERSION"));
+    /// The prefix for environment variables which override settings from
+    /// `book.toml` (see [`Config::apply_env_overrides()`]).
+    pub const ENV_PREFIX: &'static str = "MDBOOK_LINKCHECK_";
+    /// How much of each web page is searched for
+    /// [`Config::soft_404_patterns`], in bytes.
+    pub const SOFT_404_BODY_LIMIT: u64 = 256 * 1024;
+
+    /// Let environment variables override a handful of settings, so things
+    /// like CI can change them without editing `book.toml`.
+    ///
+    /// | Variable                            | Setting            |
+    /// | ----------------------------------- | ------------------ |
+    /// | `MDBOOK_LINKCHECK_FOLLOW_WEB_LINKS` | `follow-web-links` |
+    /// | `MDBOOK_LINKCHECK_WARNING_POLICY`   | `warning-policy`   |
+    /// | `MDBOOK_LINKCHECK_CACHE_TIMEOUT`    | `cache-timeout`    |
+    /// | `MDBOOK_LINKCHECK_MAX_CONCURRENCY`  | `max-concurrency`  |
+    /// | `MDBOOK_LINKCHECK_RETRIES`          | `retries`          |
+    ///
+    /// Booleans may be `1`, `true`, `0` or `false`. Empty variables are
+    /// ignored.
+    pub fn apply_env_overrides(&mut self) -> Result<(), Error> {
+        self.apply_overrides(|name| std::env::var(name).ok())
+    }
+
+    fn apply_overrides<F>(&mut self, lookup: F) -> Result<(), Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let var = |setting: &str| {
+            let name = format!("{}{}", Config::ENV_PREFIX, setting);
+            lookup(&name)
+                .filter(|value| !value.is_empty())
+                .map(|value| (name, value))
+        };
+
+        if let Some((name, value)) = var("FOLLOW_WEB_LINKS") {
+            self.follow_web_links = parse_env_bool(&name, &value)?;
+        }
+        if let Some((name, value)) = var("WARNING_POLICY") {
+            self.warning_policy = parse_env(&name, &value)?;
+        }
+        if let Some((name, value)) = var("CACHE_TIMEOUT") {
+            self.cache_timeout.default = parse_env(&name, &value)?;
+        }
+        if let Some((name, value)) = var("MAX_CONCURRENCY") {
+            self.max_concurrency = parse_env(&name, &value)?;
+        }
+        if let Some((name, value)) = var("RETRIES") {
+            self.retries = parse_env(&name, &value)?;
+        }
+
+        Ok(())
+    }
+
+    /// The cache file set in `book.toml`, if any. Relative paths are relative
+    /// to the book's `root` directory.
+    pub fn cache_file(&self, root: &Path) -> Option<PathBuf> {
+        self.cache_file
+            .as_ref()
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(|path| root.join(path))
+    }
+
+    /// The path part of [`Config::site_url`], always ending with a `/` (e.g.
+    /// `/docs/`).
+    pub fn site_path(&self) -> Option<String> {
+        let url: Url = self.site_url.as_ref()?.parse().ok()?;
+        let path = url.path();
+
+        if path.ends_with('/') {
+            Some(path.to_string())
+        } else {
+            Some(format!("{}/", path))
+        }
+    }
 
-    /// Checks [`Config::exclude`] to see if the provided link should be
-    /// skipped.
+    /// Checks [`Config::exclude`] and [`Config::include`] to see if the
+    /// provided link should be skipped.
     pub fn should_skip(&self, link: &str) -> bool {
-        self.exclude.iter().any(|pat| pat.find(link).is_some())
+        let excluded = self.exclude.iter().any(|pat| pat.find(link).is_some());
+        let included = self.include.is_empty()
+            || self.include.iter().any(|pat| pat.find(link).is_some());
+
+        excluded || !included
+    }
+
+    /// The `Content-Type` a web link is expected to respond with, according
+    /// to [`Config::expected_content_type`].
+    pub fn expected_content_type_for(&self, url: &str) -> Option<&str> {
+        self.expected_content_type
+            .iter()
+            .filter(|(pattern, _)| pattern.find(url).is_some())
+            .max_by_key(|(pattern, _)| pattern.string.len())
+            .map(|(_, content_type)| content_type.as_str())
+    }
+
+    /// How a web link should be checked, according to
+    /// [`Config::check_mode`].
+    ///
+    /// Links which don't match any of the patterns use a `HEAD` request,
+    /// unless [`Config::always_use_get`] is set or the body is needed to
+    /// check [`Config::min_body_bytes`] or [`Config::soft_404_patterns`].
+    pub fn check_mode_for(&self, url: &str) -> CheckMode {
+        let explicit = self
+            .check_mode
+            .iter()
+            .filter(|(pattern, _)| pattern.find(url).is_some())
+            .max_by_key(|(pattern, _)| pattern.string.len())
+            .map(|(_, &mode)| mode);
+
+        match explicit {
+            Some(mode) => mode,
+            None if self.always_use_get || self.needs_body() => CheckMode::Get,
+            None => CheckMode::Head,
+        }
+    }
+
+    /// Do web pages need to be downloaded to check [`Config::min_body_bytes`]
+    /// or [`Config::soft_404_patterns`]?
+    pub(crate) fn needs_body(&self) -> bool {
+        self.min_body_bytes.is_some() || !self.soft_404_patterns.is_empty()
+    }
+
+    /// Apply each of the [`Config::link_rewrites`] to a link, in order.
+    pub fn rewrite_link<'a>(&self, href: &'a str) -> Cow<'a, str> {
+        let mut href = Cow::Borrowed(href);
+
+        for rewrite in &self.link_rewrites {
+            if let Cow::Owned(rewritten) = rewrite
+                .pattern
+                .replace_all(&href, rewrite.replacement.as_str())
+            {
+                href = Cow::Owned(rewritten);
+            }
+        }
+
+        href
+    }
+
+    /// Does the link use one of the [`Config::ignore_schemes`]?
+    pub fn has_ignored_scheme(&self, link: &str) -> bool {
+        let scheme = match link.find(':') {
+            Some(ix) => &link[..ix],
+            None => return false,
+        };
+        let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+
+        is_scheme
+            && self
+                .ignore_schemes
+                .iter()
+                .any(|ignored| ignored.eq_ignore_ascii_case(scheme))
+    }
+
+    /// Ask the [`Config::link_classifier`] (if there is one) what should
+    /// happen to a link.
+    pub fn classify(&self, link: &Link) -> Option<LinkClassification> {
+        self.link_classifier
+            .as_ref()
+            .and_then(|classifier| classifier.classify(link))
+    }
+
+    /// Checks [`Config::exclude_files`] to see if links in a chapter should
+    /// be skipped.
+    pub fn should_skip_file(&self, path: &Path) -> bool {
+        matches_any_glob(&self.exclude_files, path)
+    }
+
+    /// A hash of every setting which changes how web requests are made.
+    ///
+    /// Cached results are only trusted when they were created with the same
+    /// fingerprint, so changing (for example) an authorization header will
+    /// recheck links instead of trusting results from before the change.
+    /// Only the settings as written are hashed (e.g. `Bearer $TOKEN`), so
+    /// secrets taken from environment variables never end up in the cache.
+    ///
+    /// The hash is stable across Rust releases, so upgrading the compiler
+    /// doesn't throw away the cache.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = FnvHasher::default();
+
+        self.user_agent.hash(&mut hasher);
+        self.user_agents.hash(&mut hasher);
+
+        // HashMap iteration order isn't stable, so sort the headers first
+        let mut http_headers: Vec<_> = self
+            .http_headers
+            .iter()
+            .map(|(pattern, set)| {
+                let headers: Vec<_> = set
+                    .headers
+                    .iter()
+                    .map(|header| (header.name.as_str(), header.value.as_str()))
+                    .collect();
+                (pattern.string.as_str(), set.match_full_url, headers)
+            })
+            .collect();
+        http_headers.sort();
+        http_headers.hash(&mut hasher);
+
+        let mut basic_auth: Vec<_> = self
+            .basic_auth
+            .iter()
+            .map(|(pattern, auth)| {
+                (
+                    pattern.string.as_str(),
+                    auth.username.as_str(),
+                    auth.password.as_str(),
+                )
+            })
+            .collect();
+        basic_auth.sort();
+        basic_auth.hash(&mut hasher);
+
+        let mut expected_content_type: Vec<_> = self
+            .expected_content_type
+            .iter()
+            .map(|(pattern, content_type)| {
+                (pattern.string.as_str(), content_type)
+            })
+            .collect();
+        expected_content_type.sort();
+        expected_content_type.hash(&mut hasher);
+
+        let mut check_mode: Vec<_> = self
+            .check_mode
+            .iter()
+            .map(|(pattern, mode)| (pattern.string.as_str(), mode))
+            .collect();
+        check_mode.sort_by_key(|&(pattern, _)| pattern);
+        check_mode.hash(&mut hasher);
+
+        self.always_use_get.hash(&mut hasher);
+        self.redirect_policy.hash(&mut hasher);
+        self.max_redirects.hash(&mut hasher);
+        self.min_body_bytes.hash(&mut hasher);
+        self.soft_404_patterns.hash(&mut hasher);
+        self.max_response_bytes.hash(&mut hasher);
+        self.http_proxy.hash(&mut hasher);
+        self.https_proxy.hash(&mut hasher);
+        self.no_proxy.hash(&mut hasher);
+
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// A hash of every setting which changes which file a link to a local
+    /// path resolves to.
+    pub fn resolution_fingerprint(&self) -> String {
+        let mut hasher = FnvHasher::default();
+
+        self.traverse_parent_directories.hash(&mut hasher);
+        self.default_index_file.hash(&mut hasher);
+        self.markdown_extensions.hash(&mut hasher);
+
+        format!("{:016x}", hasher.finish())
     }
 
     pub(crate) fn client(&self) -> Client {
         let mut headers = http::HeaderMap::new();
         headers
             .insert(http::header::USER_AGENT, self.user_agent.parse().unwrap());
-        Client::builder().default_headers(headers).build().unwrap()
+        let redirect_policy = match self.redirect_policy {
+            RedirectPolicy::None => redirect::Policy::none(),
+            RedirectPolicy::Follow | RedirectPolicy::Warn => {
+                redirect::Policy::limited(self.max_redirects)
+            },
+        };
+
+        let mut builder = Client::builder()
+            .default_headers(headers)
+            .redirect(redirect_policy);
+
+        // reqwest already uses the proxy environment variables by default
+        if self.http_proxy.is_some()
+            || self.https_proxy.is_some()
+            || !self.no_proxy.is_empty()
+        {
+            builder = builder.proxy(self.proxy());
+        }
+
+        if let Some(seconds) = self.request_timeout {
+            builder = builder.timeout(Duration::from_secs(seconds));
+        }
+
+        builder.build().unwrap()
+    }
+
+    fn proxy(&self) -> Proxy {
+        let http_proxy =
+            self.http_proxy.clone().or_else(|| env_var("HTTP_PROXY"));
+        let https_proxy =
+            self.https_proxy.clone().or_else(|| env_var("HTTPS_PROXY"));
+        let no_proxy = if self.no_proxy.is_empty() {
+            env_var("NO_PROXY")
+                .map(|hosts| {
+                    hosts.split(',').map(|h| h.trim().to_string()).collect()
+                })
+                .unwrap_or_default()
+        } else {
+            self.no_proxy.clone()
+        };
+
+        Proxy::custom(move |url| {
+            let host = url.host_str().unwrap_or_default();
+            if no_proxy.iter().any(|pattern| bypasses_proxy(host, pattern)) {
+                return None;
+            }
+
+            match url.scheme() {
+                "http" => http_proxy.clone(),
+                "https" => https_proxy.clone(),
+                _ => None,
+            }
+        })
     }
 
     pub(crate) fn interpolate_headers(
         &self,
         warning_policy: WarningPolicy,
-    ) -> Vec<(HashedRegex, Vec<(HeaderName, HeaderValue)>)> {
+    ) -> Vec<(HeaderPattern, Vec<(HeaderName, HeaderValue)>)> {
         let mut all_headers = Vec::new();
         let log_level = warning_policy.to_log_level();
 
-        for (pattern, headers) in &self.http_headers {
+        for (pattern, set) in &self.http_headers {
             let mut interpolated = Vec::new();
 
-            for header in headers {
+            for header in &set.headers {
                 match header.interpolate() {
                     Ok(value) => {
                         interpolated.push((header.name.clone(), value))
@@ -115,11 +825,56 @@ impl Config {
                 }
             }
 
-            all_headers.push((pattern.clone(), interpolated));
+            let pattern = HeaderPattern {
+                pattern: pattern.clone(),
+                match_full_url: set.match_full_url,
+            };
+            all_headers.push((pattern, interpolated));
+        }
+
+        for (pattern, auth) in &self.basic_auth {
+            match auth.header_value() {
+                Ok(value) => {
+                    let pattern = HeaderPattern {
+                        pattern: pattern.clone(),
+                        match_full_url: false,
+                    };
+                    all_headers.push((pattern, vec![(AUTHORIZATION, value)]));
+                },
+                Err(e) => log::log!(
+                    log_level,
+                    "Unable to interpolate the basic-auth credentials for \"{}\" because {}",
+                    pattern.string,
+                    e
+                ),
+            }
         }
 
         all_headers
     }
+
+    pub(crate) fn interpolate_user_agents(
+        &self,
+        warning_policy: WarningPolicy,
+    ) -> Vec<HeaderValue> {
+        let log_level = warning_policy.to_log_level();
+
+        self.user_agents
+            .iter()
+            .filter_map(|user_agent| match interpolate_env(user_agent) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    log::log!(
+                        log_level,
+                        "Unable to interpolate the \"{}\" user-agent because {}",
+                        user_agent,
+                        e
+                    );
+                    None
+                },
+            })
+            .collect()
+    }
 }
 
 impl Default for Config {
@@ -127,12 +882,60 @@ impl Default for Config {
         Config {
             follow_web_links: false,
             traverse_parent_directories: false,
-            latex_support: false,
+            default_index_file: default_index_file(),
+            markdown_extensions: default_markdown_extensions(),
+            site_url: None,
+            latex_support: LatexSupport::default(),
             exclude: Vec::new(),
+            include: Vec::new(),
+            ignore_schemes: default_ignore_schemes(),
+            ignore_private_networks: false,
+            exclude_files: Vec::new(),
+            additional_summary_files: Vec::new(),
+            check_included_in_summary: default_check_included_in_summary(),
+            not_in_summary_message: None,
             user_agent: default_user_agent(),
+            user_agents: Vec::new(),
             http_headers: HashMap::new(),
+            basic_auth: HashMap::new(),
+            expected_content_type: HashMap::new(),
+            check_mode: HashMap::new(),
+            generated_anchors: HashMap::new(),
+            warning_policy_overrides: WarningPolicyOverrides::default(),
+            link_classifier: None,
             warning_policy: WarningPolicy::Warn,
-            cache_timeout: Config::DEFAULT_CACHE_TIMEOUT.as_secs(),
+            absolute_fs_path_policy: default_absolute_fs_path_policy(),
+            allow_absolute_patterns: Vec::new(),
+            cache_timeout: CacheTimeout::default(),
+            cache_file: None,
+            max_concurrency: Config::DEFAULT_MAX_CONCURRENCY,
+            retries: 0,
+            max_retry_after: Config::DEFAULT_MAX_RETRY_AFTER,
+            request_timeout: None,
+            shared_web_cache: true,
+            check_fragments: false,
+            check_mailto: false,
+            check_code_blocks: false,
+            check_titles: false,
+            ignore_in_blockquotes: false,
+            warn_on_case_mismatch: false,
+            warn_on_insecure_links: false,
+            always_use_get: false,
+            redirect_policy: RedirectPolicy::default(),
+            max_redirects: Config::DEFAULT_MAX_REDIRECTS,
+            report_redirects: false,
+            deduplicate_reports: false,
+            consecutive_failures_threshold:
+                default_consecutive_failures_threshold(),
+            min_body_bytes: None,
+            soft_404_patterns: Vec::new(),
+            max_response_bytes: None,
+            time_budget: None,
+            max_ignored_ratio: None,
+            http_proxy: None,
+            https_proxy: None,
+            no_proxy: Vec::new(),
+            link_rewrites: Vec::new(),
         }
     }
 }
@@ -182,8 +985,142 @@ impl Into<String> for HttpHeader {
 
 fn default_cache_timeout() -> u64 { Config::DEFAULT_CACHE_TIMEOUT.as_secs() }
 fn default_user_agent() -> String { Config::DEFAULT_USER_AGENT.to_string() }
+fn default_max_concurrency() -> usize { Config::DEFAULT_MAX_CONCURRENCY }
+fn default_consecutive_failures_threshold() -> u32 { 1 }
+fn default_max_redirects() -> usize { Config::DEFAULT_MAX_REDIRECTS }
+fn default_max_retry_after() -> u64 { Config::DEFAULT_MAX_RETRY_AFTER }
+fn default_absolute_fs_path_policy() -> WarningPolicy { WarningPolicy::Error }
+fn default_check_included_in_summary() -> bool { true }
+fn default_index_file() -> String { Config::DEFAULT_INDEX_FILE.to_string() }
+fn default_markdown_extensions() -> Vec<String> { vec![String::from("md")] }
+
+fn default_ignore_schemes() -> Vec<String> {
+    vec![
+        String::from("javascript"),
+        String::from("data"),
+        String::from("mailto"),
+        String::from("tel"),
+    ]
+}
+
+/// Does a path (relative to the book's source directory) match any of these
+/// glob patterns?
+pub(crate) fn matches_any_glob(globs: &[String], path: &Path) -> bool {
+    // Note: the patterns always use forward slashes
+    let path = path.display().to_string().replace('\\', "/");
+
+    globs.iter().any(|glob| {
+        HashedRegex::from_glob(glob)
+            .map(|pattern| pattern.is_match(&path))
+            .unwrap_or(false)
+    })
+}
+
+fn parse_env<T>(name: &str, value: &str) -> Result<T, Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.trim().parse().map_err(|e| {
+        Error::msg(format!("Unable to parse `{}` (\"{}\"): {}", name, value, e))
+    })
+}
+
+fn parse_env_bool(name: &str, value: &str) -> Result<bool, Error> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(Error::msg(format!(
+            "`{}` should be one of 1, true, 0 or false, not \"{}\"",
+            name, value
+        ))),
+    }
+}
+
+/// Read an environment variable, also trying the lowercase version (e.g.
+/// `https_proxy`) because both are in common use.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .or_else(|_| std::env::var(name.to_lowercase()))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// Does a `no-proxy` entry match this host? `*` matches everything and
+/// `example.com` or `.example.com` match the domain and its subdomains.
+fn bypasses_proxy(host: &str, pattern: &str) -> bool {
+    let domain = pattern.trim_start_matches('.');
+
+    pattern == "*"
+        || host.eq_ignore_ascii_case(domain)
+        || host
+            .to_lowercase()
+            .ends_with(&format!(".{}", domain.to_lowercase()))
+}
+
+fn deserialize_proxy_url<'de, D>(de: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(de)?;
+
+    match Url::parse(&raw) {
+        Ok(url)
+            if url.has_host()
+                && ["http", "https", "socks5", "socks5h"]
+                    .contains(&url.scheme()) =>
+        {
+            Ok(Some(raw))
+        },
+        _ => Err(D::Error::custom(format!(
+            "\"{}\" isn't a valid proxy URL (e.g. \"http://proxy.example.com:8080\")",
+            raw
+        ))),
+    }
+}
+
+fn deserialize_site_url<'de, D>(de: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(de)?;
+
+    match Url::parse(&raw) {
+        Ok(url) if url.has_host() && ["http", "https"].contains(&url.scheme()) => {
+            Ok(Some(raw))
+        },
+        _ => Err(D::Error::custom(format!(
+            "\"{}\" isn't a valid site URL (e.g. \"https://example.com/docs/\")",
+            raw
+        ))),
+    }
+}
+
+fn deserialize_file_name<'de, D>(de: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(de)?;
+    let is_bare_file_name = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains(&['/', '\\'][..]);
+
+    if is_bare_file_name {
+        Ok(name)
+    } else {
+        Err(D::Error::custom(format!(
+            "\"{}\" should be a file name (e.g. \"index.md\"), not a path",
+            name
+        )))
+    }
+}
 
 fn interpolate_env(value: &str) -> Result<HeaderValue, Error> {
+    Ok(interpolate_env_string(value)?.parse()?)
+}
+
+fn interpolate_env_string(value: &str) -> Result<String, Error> {
     use std::{iter::Peekable, str::CharIndices};
 
     fn is_ident(ch: char) -> bool { ch.is_ascii_alphanumeric() || ch == '_' }
@@ -246,7 +1183,128 @@ fn interpolate_env(value: &str) -> Result<HeaderValue, Error> {
         res.push('\\');
     }
 
-    Ok(res.parse()?)
+    Ok(res)
+}
+
+/// Which latex fragments should be removed before looking for links?
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LatexSupport {
+    /// `true` removes the usual `$...$`, `$$...$$`, `\(...\)` and `\[...\]`
+    /// fragments, while `false` turns latex support off.
+    Enabled(bool),
+    /// Remove the usual fragments, as well as anything using these
+    /// delimiters (e.g. `\begin{equation}...\end{equation}`).
+    Custom {
+        /// The extra delimiters.
+        delimiters: Vec<LatexDelimiter>,
+    },
+}
+
+impl LatexSupport {
+    /// Should latex fragments be removed at all?
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            LatexSupport::Enabled(enabled) => *enabled,
+            LatexSupport::Custom { .. } => true,
+        }
+    }
+
+    /// Any delimiters to remove on top of the usual ones.
+    pub fn delimiters(&self) -> &[LatexDelimiter] {
+        match self {
+            LatexSupport::Enabled(_) => &[],
+            LatexSupport::Custom { delimiters } => delimiters,
+        }
+    }
+}
+
+impl Default for LatexSupport {
+    fn default() -> LatexSupport { LatexSupport::Enabled(false) }
+}
+
+impl From<bool> for LatexSupport {
+    fn from(enabled: bool) -> LatexSupport { LatexSupport::Enabled(enabled) }
+}
+
+/// A kind of latex fragment to remove, on top of the ones recognised by
+/// default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LatexDelimiter {
+    /// Everything from `start` up to the next `end`, including newlines.
+    Pair {
+        /// The text a fragment starts with.
+        start: String,
+        /// The text a fragment ends with.
+        end: String,
+    },
+    /// Everything matching a regular expression.
+    Regex {
+        /// The pattern to remove.
+        regex: HashedRegex,
+    },
+}
+
+impl LatexDelimiter {
+    /// A pattern matching every fragment using this delimiter.
+    pub(crate) fn pattern(&self) -> String {
+        match self {
+            LatexDelimiter::Pair { start, end } => format!(
+                r"{}(?s:.*?){}",
+                regex::escape(start),
+                regex::escape(end)
+            ),
+            LatexDelimiter::Regex { regex } => regex.string.clone(),
+        }
+    }
+}
+
+/// What should happen when a web link redirects somewhere else?
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RedirectPolicy {
+    /// Follow redirects (up to [`Config::max_redirects`]) and check the page
+    /// they end up at.
+    Follow,
+    /// Don't follow redirects, treating the redirect itself as the final
+    /// response.
+    None,
+    /// Follow redirects, but emit a warning suggesting the link be updated to
+    /// point at the final URL.
+    Warn,
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> RedirectPolicy { RedirectPolicy::Follow }
+}
+
+/// A regex substitution applied to links before they are checked (see
+/// [`Config::link_rewrites`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinkRewrite {
+    /// The part of the link to replace.
+    pub pattern: HashedRegex,
+    /// What to replace it with. Capture groups can be referred to using
+    /// `$1`, `$name`, etc.
+    pub replacement: String,
+}
+
+/// How are web links checked?
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckMode {
+    /// Send a `GET` request.
+    Get,
+    /// Send a `HEAD` request, falling back to `GET` when the server responds
+    /// with `405 Method Not Allowed` or `501 Not Implemented`.
+    Head,
+    /// Only check that the host name resolves, without sending any requests.
+    DnsOnly,
+}
+
+impl Default for CheckMode {
+    fn default() -> CheckMode { CheckMode::Get }
 }
 
 /// How should warnings be treated?
@@ -262,6 +1320,16 @@ pub enum WarningPolicy {
 }
 
 impl WarningPolicy {
+    /// How problems covered by this policy should be reported, or `None` if
+    /// they should be ignored.
+    pub(crate) fn severity(self) -> Option<Severity> {
+        match self {
+            WarningPolicy::Error => Some(Severity::Error),
+            WarningPolicy::Warn => Some(Severity::Warning),
+            WarningPolicy::Ignore => None,
+        }
+    }
+
     pub(crate) fn to_log_level(self) -> Level {
         match self {
             WarningPolicy::Error => Level::Error,
@@ -275,6 +1343,156 @@ impl Default for WarningPolicy {
     fn default() -> WarningPolicy { WarningPolicy::Warn }
 }
 
+impl FromStr for WarningPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(WarningPolicy::Ignore),
+            "warn" => Ok(WarningPolicy::Warn),
+            "error" => Ok(WarningPolicy::Error),
+            _ => Err(Error::msg(
+                "expected one of \"ignore\", \"warn\" or \"error\"",
+            )),
+        }
+    }
+}
+
+/// Per-category overrides for [`Config::warning_policy`]. Any category which
+/// isn't set falls back to [`Config::warning_policy`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct WarningPolicyOverrides {
+    /// Reference-style links which were never defined (e.g. `[foo]` without
+    /// a `[foo]: ...` definition).
+    pub incomplete_link: Option<WarningPolicy>,
+    /// Links relative to the book's root (e.g. `/chapter_1.md`), which break
+    /// when the book is viewed from the file system.
+    pub absolute_link: Option<WarningPolicy>,
+    /// Link reference definitions which were defined more than once.
+    pub duplicate_definition: Option<WarningPolicy>,
+    /// Fragments shared by several headings.
+    pub ambiguous_fragment: Option<WarningPolicy>,
+    /// Links whose case doesn't match the file on disk.
+    pub case_mismatch: Option<WarningPolicy>,
+    /// Links to `http://` URLs (see [`Config::warn_on_insecure_links`]).
+    pub insecure_link: Option<WarningPolicy>,
+    /// Web links which redirect somewhere else.
+    pub redirect: Option<WarningPolicy>,
+    /// Web pages smaller than [`Config::min_body_bytes`].
+    pub small_body: Option<WarningPolicy>,
+    /// Web pages matching one of the [`Config::soft_404_patterns`].
+    pub soft_404: Option<WarningPolicy>,
+    /// Taking longer than [`Config::time_budget`].
+    pub over_budget: Option<WarningPolicy>,
+    /// Images which couldn't be found. Unlike the other categories, these
+    /// are errors unless overridden, regardless of [`Config::warning_policy`].
+    pub broken_image: Option<WarningPolicy>,
+    /// Links to markdown files which exist but aren't part of the book
+    /// because they aren't listed in `SUMMARY.md`. These are also errors
+    /// unless overridden.
+    pub not_in_summary: Option<WarningPolicy>,
+    /// Patterns in [`Config::exclude`] or [`Config::http_headers`] which
+    /// didn't match any links.
+    pub unused_pattern: Option<WarningPolicy>,
+    /// Links which aren't web links or paths to local files (e.g. because of
+    /// a malformed URL), so they couldn't be checked. These are ignored
+    /// unless overridden.
+    pub unknown_link: Option<WarningPolicy>,
+}
+
+impl WarningPolicyOverrides {
+    /// Turn every category which is set to [`WarningPolicy::Warn`] into an
+    /// error.
+    fn treat_warnings_as_errors(&mut self) {
+        let WarningPolicyOverrides {
+            ref mut incomplete_link,
+            ref mut absolute_link,
+            ref mut duplicate_definition,
+            ref mut ambiguous_fragment,
+            ref mut case_mismatch,
+            ref mut insecure_link,
+            ref mut redirect,
+            ref mut small_body,
+            ref mut soft_404,
+            ref mut over_budget,
+            ref mut broken_image,
+            ref mut not_in_summary,
+            ref mut unused_pattern,
+            ref mut unknown_link,
+        } = *self;
+        let categories = vec![
+            incomplete_link,
+            absolute_link,
+            duplicate_definition,
+            ambiguous_fragment,
+            case_mismatch,
+            insecure_link,
+            redirect,
+            small_body,
+            soft_404,
+            over_budget,
+            broken_image,
+            not_in_summary,
+            unused_pattern,
+            unknown_link,
+        ];
+
+        for policy in categories {
+            if *policy == Some(WarningPolicy::Warn) {
+                *policy = Some(WarningPolicy::Error);
+            }
+        }
+    }
+}
+
+/// Settings which take precedence over `book.toml` for a single run (e.g.
+/// from command-line flags). Anything left as `None` keeps its configured
+/// value.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConfigOverrides {
+    /// Replaces [`Config::follow_web_links`].
+    pub follow_web_links: Option<bool>,
+    /// Replaces [`Config::user_agent`], and stops [`Config::user_agents`]
+    /// from being used.
+    pub user_agent: Option<String>,
+    /// Replaces [`Config::request_timeout`].
+    pub request_timeout: Option<u64>,
+    /// Extra patterns to add to [`Config::exclude`].
+    pub exclude: Vec<HashedRegex>,
+    /// Treat every warning as an error by setting [`Config::warning_policy`]
+    /// to [`WarningPolicy::Error`]. Anything else configured to warn (e.g.
+    /// [`Config::absolute_fs_path_policy`] or one of the
+    /// [`Config::warning_policy_overrides`]) becomes an error too, while
+    /// ignored categories stay ignored.
+    pub strict: bool,
+}
+
+impl ConfigOverrides {
+    /// Apply these overrides to a [`Config`].
+    pub fn apply(&self, cfg: &mut Config) {
+        if let Some(follow_web_links) = self.follow_web_links {
+            cfg.follow_web_links = follow_web_links;
+        }
+        if let Some(ref user_agent) = self.user_agent {
+            cfg.user_agent = user_agent.clone();
+            cfg.user_agents.clear();
+        }
+        if let Some(request_timeout) = self.request_timeout {
+            cfg.request_timeout = Some(request_timeout);
+        }
+        cfg.exclude.extend(self.exclude.iter().cloned());
+
+        if self.strict {
+            cfg.warning_policy = WarningPolicy::Error;
+            if cfg.absolute_fs_path_policy == WarningPolicy::Warn {
+                cfg.absolute_fs_path_policy = WarningPolicy::Error;
+            }
+            cfg.warning_policy_overrides.treat_warnings_as_errors();
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -283,14 +1501,74 @@ mod tests {
 
     const CONFIG: &str = r#"follow-web-links = true
 traverse-parent-directories = true
+default-index-file = "index.md"
+markdown-extensions = ["md", "markdown"]
+site-url = "https://example.com/docs/"
 latex-support = true
 exclude = ["google\\.com"]
+include = ["github\\.com", "\\.md"]
+ignore-schemes = ["javascript", "vscode"]
+ignore-private-networks = true
+exclude-files = ["generated/*.md"]
+additional-summary-files = ["api/*.md"]
+check-included-in-summary = false
+not-in-summary-message = "{path} needs to be added to SUMMARY.md, see CONTRIBUTING.md"
 user-agent = "Internet Explorer"
+user-agents = ["Firefox", "Chrome $VERSION"]
 cache-timeout = 3600
+cache-file = ".cache/linkcheck.json"
+consecutive-failures-threshold = 2
 warning-policy = "error"
+absolute-fs-path-policy = "warn"
+allow-absolute-patterns = ["^/api/"]
+max-concurrency = 8
+retries = 2
+max-retry-after = 10
+request-timeout = 30
+shared-web-cache = false
+check-fragments = true
+check-mailto = true
+check-code-blocks = true
+check-titles = true
+ignore-in-blockquotes = true
+warn-on-case-mismatch = true
+warn-on-insecure-links = true
+always-use-get = true
+redirect-policy = "warn"
+max-redirects = 3
+report-redirects = true
+deduplicate-reports = true
+min-body-bytes = 512
+soft-404-patterns = ["Page not found"]
+max-response-bytes = 1048576
+time-budget = 300
+max-ignored-ratio = 0.25
+http-proxy = "http://proxy.example.com:8080"
+https-proxy = "http://proxy.example.com:8443"
+no-proxy = ["localhost", ".internal.example.com"]
+
+[[link-rewrites]]
+pattern = "^https://docs\\.example\\.com/"
+replacement = "/"
 
 [http-headers]
 https = ["accept: html/text", "authorization: Basic $TOKEN"]
+[basic-auth."intranet\\.example\\.com"]
+username = "docs"
+password = "$INTRANET_PASSWORD"
+
+[expected-content-type]
+"\\.pdf$" = "application/pdf"
+
+[check-mode]
+"api\\.example\\.com" = "dns-only"
+
+[generated-anchors]
+"glossary.md" = ["rust", "cargo"]
+
+[warning-policy-overrides]
+incomplete-link = "error"
+absolute-link = "ignore"
 "#;
 
     #[test]
@@ -300,18 +1578,105 @@ https = ["accept: html/text", "authorization: Basic $TOKEN"]
         let should_be = Config {
             follow_web_links: true,
             warning_policy: WarningPolicy::Error,
+            absolute_fs_path_policy: WarningPolicy::Warn,
+            allow_absolute_patterns: vec![HashedRegex::new("^/api/").unwrap()],
             traverse_parent_directories: true,
+            default_index_file: String::from("index.md"),
+            markdown_extensions: vec![
+                String::from("md"),
+                String::from("markdown"),
+            ],
+            site_url: Some(String::from("https://example.com/docs/")),
             exclude: vec![HashedRegex::new(r"google\.com").unwrap()],
+            include: vec![
+                HashedRegex::new(r"github\.com").unwrap(),
+                HashedRegex::new(r"\.md").unwrap(),
+            ],
+            ignore_schemes: vec![
+                String::from("javascript"),
+                String::from("vscode"),
+            ],
+            ignore_private_networks: true,
+            exclude_files: vec![String::from("generated/*.md")],
+            additional_summary_files: vec![String::from("api/*.md")],
+            check_included_in_summary: false,
+            not_in_summary_message: Some(String::from(
+                "{path} needs to be added to SUMMARY.md, see CONTRIBUTING.md",
+            )),
             user_agent: String::from("Internet Explorer"),
+            user_agents: vec![
+                String::from("Firefox"),
+                String::from("Chrome $VERSION"),
+            ],
             http_headers: HashMap::from_iter(vec![(
                 HashedRegex::new("https").unwrap(),
-                vec![
+                HeaderSet::from(vec![
                     "Accept: html/text".try_into().unwrap(),
                     "Authorization: Basic $TOKEN".try_into().unwrap(),
-                ],
+                ]),
+            )]),
+            basic_auth: HashMap::from_iter(vec![(
+                HashedRegex::new(r"intranet\.example\.com").unwrap(),
+                BasicAuth {
+                    username: String::from("docs"),
+                    password: String::from("$INTRANET_PASSWORD"),
+                },
+            )]),
+            expected_content_type: HashMap::from_iter(vec![(
+                HashedRegex::new(r"\.pdf$").unwrap(),
+                String::from("application/pdf"),
+            )]),
+            check_mode: HashMap::from_iter(vec![(
+                HashedRegex::new(r"api\.example\.com").unwrap(),
+                CheckMode::DnsOnly,
+            )]),
+            generated_anchors: HashMap::from_iter(vec![(
+                PathBuf::from("glossary.md"),
+                vec![String::from("rust"), String::from("cargo")],
             )]),
-            cache_timeout: 3600,
-            latex_support: true,
+            warning_policy_overrides: WarningPolicyOverrides {
+                incomplete_link: Some(WarningPolicy::Error),
+                absolute_link: Some(WarningPolicy::Ignore),
+                ..Default::default()
+            },
+            cache_timeout: CacheTimeout::from(3600),
+            cache_file: Some(PathBuf::from(".cache/linkcheck.json")),
+            consecutive_failures_threshold: 2,
+            latex_support: LatexSupport::Enabled(true),
+            max_concurrency: 8,
+            retries: 2,
+            max_retry_after: 10,
+            request_timeout: Some(30),
+            shared_web_cache: false,
+            check_fragments: true,
+            check_mailto: true,
+            check_code_blocks: true,
+            check_titles: true,
+            ignore_in_blockquotes: true,
+            warn_on_case_mismatch: true,
+            warn_on_insecure_links: true,
+            always_use_get: true,
+            redirect_policy: RedirectPolicy::Warn,
+            max_redirects: 3,
+            report_redirects: true,
+            deduplicate_reports: true,
+            min_body_bytes: Some(512),
+            soft_404_patterns: vec![HashedRegex::new("Page not found").unwrap()],
+            max_response_bytes: Some(1024 * 1024),
+            time_budget: Some(300),
+            max_ignored_ratio: Some(0.25),
+            http_proxy: Some(String::from("http://proxy.example.com:8080")),
+            https_proxy: Some(String::from("http://proxy.example.com:8443")),
+            no_proxy: vec![
+                String::from("localhost"),
+                String::from(".internal.example.com"),
+            ],
+            link_rewrites: vec![LinkRewrite {
+                pattern: HashedRegex::new(r"^https://docs\.example\.com/")
+                    .unwrap(),
+                replacement: String::from("/"),
+            }],
+            link_classifier: None,
         };
 
         let got: Config = toml::from_str(CONFIG).unwrap();
@@ -331,6 +1696,348 @@ https = ["accept: html/text", "authorization: Basic $TOKEN"]
         assert_eq!(reserialized, CONFIG);
     }
 
+    #[test]
+    fn changing_a_header_changes_the_fingerprint() {
+        let original: Config = toml::from_str(CONFIG).unwrap();
+        let mut changed = original.clone();
+        changed.http_headers.insert(
+            HashedRegex::new("github").unwrap(),
+            HeaderSet::from(vec!["Authorization: Bearer $GITHUB_TOKEN"
+                .try_into()
+                .unwrap()]),
+        );
+
+        assert_eq!(original.fingerprint(), original.clone().fingerprint());
+        assert_ne!(original.fingerprint(), changed.fingerprint());
+    }
+
+    #[test]
+    fn the_fingerprint_doesnt_include_secrets() {
+        let mut cfg = Config::default();
+        cfg.http_headers.insert(
+            HashedRegex::new("github").unwrap(),
+            HeaderSet::from(vec!["Authorization: Bearer $FINGERPRINT_TOKEN"
+                .try_into()
+                .unwrap()]),
+        );
+
+        std::env::set_var("FINGERPRINT_TOKEN", "first");
+        let first = cfg.fingerprint();
+        std::env::set_var("FINGERPRINT_TOKEN", "second");
+        let second = cfg.fingerprint();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn changing_the_proxy_or_response_limit_changes_the_fingerprint() {
+        let original = Config::default();
+        let proxied = Config {
+            https_proxy: Some(String::from("http://proxy.example.com:3128")),
+            ..Default::default()
+        };
+        let limited = Config {
+            max_response_bytes: Some(1024),
+            ..Default::default()
+        };
+
+        assert_ne!(original.fingerprint(), proxied.fingerprint());
+        assert_ne!(original.fingerprint(), limited.fingerprint());
+    }
+
+    #[test]
+    fn header_patterns_only_match_the_host_by_default() {
+        let src = r#"
+[http-headers]
+'github\.com' = ["Authorization: Bearer abc123"]
+https = { headers = ["Accept: text/html"], match-full-url = true }
+"#;
+        let cfg: Config = toml::from_str(src).unwrap();
+        let headers = cfg.interpolate_headers(WarningPolicy::Warn);
+        let sent = |url: &str| {
+            let url: Url = url.parse().unwrap();
+            let mut names: Vec<String> = headers
+                .iter()
+                .filter(|(pattern, _)| pattern.matches(&url))
+                .flat_map(|(_, headers)| {
+                    headers.iter().map(|(name, _)| name.to_string())
+                })
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(
+            sent("https://github.com/rust-lang/mdBook"),
+            vec!["accept", "authorization"]
+        );
+        assert_eq!(sent("https://example.com/github.com/"), vec!["accept"]);
+        assert_eq!(
+            sent("https://example.com/?next=github.com"),
+            vec!["accept"]
+        );
+        assert!(sent("http://example.com/").is_empty());
+    }
+
+    #[test]
+    fn basic_auth_credentials_are_only_sent_to_matching_hosts() {
+        std::env::set_var("LINKCHECK_TEST_PASSWORD", "pass");
+        let src = r#"
+[basic-auth.'intranet\.example\.com']
+username = "user"
+password = "$LINKCHECK_TEST_PASSWORD"
+"#;
+        let cfg: Config = toml::from_str(src).unwrap();
+        let headers = cfg.interpolate_headers(WarningPolicy::Warn);
+        let authorization = |url: &str| {
+            let url: Url = url.parse().unwrap();
+            headers
+                .iter()
+                .filter(|(pattern, _)| pattern.matches(&url))
+                .flat_map(|(_, headers)| headers.iter())
+                .find(|(name, _)| *name == AUTHORIZATION)
+                .map(|(_, value)| value.to_str().unwrap().to_string())
+        };
+
+        assert_eq!(
+            authorization("https://intranet.example.com/wiki/"),
+            Some(String::from("Basic dXNlcjpwYXNz"))
+        );
+        assert_eq!(authorization("https://example.com/"), None);
+    }
+
+    #[test]
+    fn cache_timeouts_can_be_overridden_for_some_urls() {
+        let src = r#"
+[cache-timeout]
+default = 86400
+overrides = { 'staging\.example\.com' = 60, 'staging\.example\.com/stable/' = 3600 }
+"#;
+
+        let cfg: Config = toml::from_str(src).unwrap();
+
+        let timeout = |url| cfg.cache_timeout.for_url(url).as_secs();
+        assert_eq!(timeout("https://staging.example.com/latest/"), 60);
+        assert_eq!(timeout("https://staging.example.com/stable/"), 3600);
+        assert_eq!(timeout("https://example.com/"), 86400);
+
+        // the plain number of seconds still works
+        let cfg: Config = toml::from_str("cache-timeout = 120").unwrap();
+        assert_eq!(cfg.cache_timeout, CacheTimeout::from(120));
+    }
+
+    #[test]
+    fn latex_support_can_list_extra_delimiters() {
+        let src = r#"
+[latex-support]
+delimiters = [
+    { start = '\begin{equation}', end = '\end{equation}' },
+    { regex = 'math!\{[^}]*\}' },
+]
+"#;
+
+        let got: Config = toml::from_str(src).unwrap();
+
+        assert!(got.latex_support.is_enabled());
+        assert_eq!(
+            got.latex_support.delimiters(),
+            &[
+                LatexDelimiter::Pair {
+                    start: String::from(r"\begin{equation}"),
+                    end: String::from(r"\end{equation}"),
+                },
+                LatexDelimiter::Regex {
+                    regex: HashedRegex::new(r"math!\{[^}]*\}").unwrap(),
+                },
+            ]
+        );
+        assert_eq!(
+            got.latex_support.delimiters()[0].pattern(),
+            r"\\begin\{equation\}(?s:.*?)\\end\{equation\}"
+        );
+    }
+
+    #[test]
+    fn only_check_links_matching_an_include_pattern() {
+        let cfg = Config {
+            exclude: vec![HashedRegex::new(r"github\.com/rust-lang").unwrap()],
+            include: vec![HashedRegex::new(r"github\.com").unwrap()],
+            ..Default::default()
+        };
+
+        assert!(!cfg.should_skip("https://github.com/Michael-F-Bryan"));
+        assert!(cfg.should_skip("https://crates.io/crates/mdbook"));
+        assert!(cfg.should_skip("./chapter_1.md"));
+        // exclude takes precedence
+        assert!(cfg.should_skip("https://github.com/rust-lang/mdBook"));
+        // an empty include list checks everything
+        assert!(!Config::default().should_skip("./chapter_1.md"));
+    }
+
+    #[test]
+    fn command_line_overrides_are_applied_on_top_of_the_config() {
+        let mut cfg = Config {
+            exclude: vec![HashedRegex::new(r"google\.com").unwrap()],
+            user_agents: vec![String::from("Firefox")],
+            request_timeout: Some(30),
+            ..Default::default()
+        };
+        let overrides = ConfigOverrides {
+            follow_web_links: Some(true),
+            user_agent: Some(String::from("my-crawler")),
+            exclude: vec![HashedRegex::new(r"^\./drafts/").unwrap()],
+            ..Default::default()
+        };
+
+        overrides.apply(&mut cfg);
+
+        assert!(cfg.follow_web_links);
+        assert_eq!(cfg.user_agent, "my-crawler");
+        assert!(cfg.user_agents.is_empty());
+        assert_eq!(cfg.request_timeout, Some(30));
+        assert!(cfg.should_skip("https://google.com/"));
+        assert!(cfg.should_skip("./drafts/chapter_1.md"));
+        assert!(!cfg.should_skip("./chapter_1.md"));
+    }
+
+    #[test]
+    fn strict_mode_turns_warnings_into_errors() {
+        let mut cfg = Config {
+            absolute_fs_path_policy: WarningPolicy::Warn,
+            warning_policy_overrides: WarningPolicyOverrides {
+                redirect: Some(WarningPolicy::Warn),
+                unknown_link: Some(WarningPolicy::Ignore),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let overrides = ConfigOverrides {
+            strict: true,
+            ..Default::default()
+        };
+
+        overrides.apply(&mut cfg);
+
+        assert_eq!(cfg.warning_policy, WarningPolicy::Error);
+        assert_eq!(cfg.absolute_fs_path_policy, WarningPolicy::Error);
+        let got = cfg.warning_policy_overrides;
+        assert_eq!(got.redirect, Some(WarningPolicy::Error));
+        assert_eq!(got.unknown_link, Some(WarningPolicy::Ignore));
+        assert_eq!(got.soft_404, None);
+    }
+
+    #[test]
+    fn override_settings_with_environment_variables() {
+        let env = |vars: Vec<(&str, &str)>| {
+            let vars: HashMap<String, String> = vars
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            move |name: &str| vars.get(name).cloned()
+        };
+        let mut cfg = Config::default();
+
+        cfg.apply_overrides(env(vec![
+            ("MDBOOK_LINKCHECK_FOLLOW_WEB_LINKS", "1"),
+            ("MDBOOK_LINKCHECK_WARNING_POLICY", "error"),
+            ("MDBOOK_LINKCHECK_RETRIES", "3"),
+        ]))
+        .unwrap();
+
+        assert!(cfg.follow_web_links);
+        assert_eq!(cfg.warning_policy, WarningPolicy::Error);
+        assert_eq!(cfg.retries, 3);
+
+        cfg.apply_overrides(env(vec![
+            ("MDBOOK_LINKCHECK_FOLLOW_WEB_LINKS", "false"),
+            ("MDBOOK_LINKCHECK_MAX_CONCURRENCY", ""),
+        ]))
+        .unwrap();
+
+        assert!(!cfg.follow_web_links);
+        assert_eq!(cfg.max_concurrency, Config::DEFAULT_MAX_CONCURRENCY);
+
+        let got = cfg.apply_overrides(env(vec![(
+            "MDBOOK_LINKCHECK_FOLLOW_WEB_LINKS",
+            "sometimes",
+        )]));
+        assert!(got.is_err());
+    }
+
+    #[test]
+    fn the_default_index_file_must_be_a_file_name() {
+        for path in &["nested/index.md", "..\\index.md", "..", ""] {
+            let src = format!("default-index-file = {:?}", path);
+
+            let got = toml::from_str::<Config>(&src);
+
+            assert!(got.is_err(), "{}", path);
+        }
+
+        let got: Config =
+            toml::from_str("default-index-file = 'index.md'").unwrap();
+        assert_eq!(got.default_index_file, "index.md");
+    }
+
+    #[test]
+    fn the_site_path_always_ends_with_a_slash() {
+        let inputs = vec![
+            ("https://example.com", "/"),
+            ("https://example.com/docs", "/docs/"),
+            ("https://example.com/docs/", "/docs/"),
+        ];
+
+        for (site_url, should_be) in inputs {
+            let cfg = Config {
+                site_url: Some(String::from(site_url)),
+                ..Default::default()
+            };
+
+            assert_eq!(cfg.site_path().unwrap(), should_be);
+        }
+        assert!(Config::default().site_path().is_none());
+
+        for site_url in &["/docs/", "example.com/docs", "file:///docs/"] {
+            let src = format!("site-url = {:?}", site_url);
+
+            assert!(toml::from_str::<Config>(&src).is_err(), "{}", site_url);
+        }
+    }
+
+    #[test]
+    fn malformed_proxy_urls_are_rejected() {
+        for proxy in &["proxy.example.com:8080", "ftp://proxy.example.com", ""]
+        {
+            let src = format!("https-proxy = {:?}", proxy);
+
+            let got = toml::from_str::<Config>(&src).unwrap_err();
+
+            assert!(got.to_string().contains("isn't a valid proxy URL"));
+        }
+    }
+
+    #[test]
+    fn skip_the_proxy_for_some_hosts() {
+        let inputs = vec![
+            ("example.com", "example.com", true),
+            ("docs.example.com", ".example.com", true),
+            ("docs.example.com", "example.com", true),
+            ("notexample.com", "example.com", false),
+            ("example.com", "*", true),
+            ("example.org", "example.com", false),
+        ];
+
+        for (host, pattern, should_be) in inputs {
+            assert_eq!(
+                bypasses_proxy(host, pattern),
+                should_be,
+                "{} {}",
+                host,
+                pattern
+            );
+        }
+    }
+
     #[test]
     fn interpolation() {
         std::env::set_var("SUPER_SECRET_TOKEN", "abcdefg123456");
@@ -1,14 +1,18 @@
-use crate::{Config, HashedRegex};
+use crate::{
+    config::HeaderPattern, progress::Progress, timings::Timings,
+    web::HttpResponder, Cache, Config, LinkClassification,
+};
 use codespan::Files;
 use http::header::{HeaderMap, HeaderName, HeaderValue};
-use linkcheck::{
-    validation::{Cache, Options},
-    Link,
-};
+use linkcheck::{validation::Options, Link};
 use reqwest::{Client, Url};
 use std::{
     path::Path,
-    sync::{Mutex, MutexGuard},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::SystemTime,
 };
 
 /// The [`linkcheck::validation::Context`].
@@ -21,7 +25,36 @@ pub struct Context<'a> {
     pub(crate) client: Client,
     pub(crate) filesystem_options: Options,
     pub(crate) interpolated_headers:
-        Vec<(HashedRegex, Vec<(HeaderName, HeaderValue)>)>,
+        Vec<(HeaderPattern, Vec<(HeaderName, HeaderValue)>)>,
+    pub(crate) progress: Progress,
+    pub(crate) user_agents: Vec<HeaderValue>,
+    pub(crate) next_user_agent: AtomicUsize,
+    pub(crate) timings: Mutex<Timings>,
+    /// When this run started, so we can tell which cache entries were
+    /// created by previous runs.
+    pub(crate) run_started: SystemTime,
+    /// Answers web requests instead of the network (used for testing).
+    pub(crate) responder: Option<Arc<dyn HttpResponder>>,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn lock_cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().expect("Lock was poisoned")
+    }
+
+    pub(crate) fn lock_timings(&self) -> MutexGuard<'_, Timings> {
+        self.timings.lock().expect("Lock was poisoned")
+    }
+
+    /// Take turns using each of the user-agents in [`Config::user_agents`].
+    pub(crate) fn next_user_agent(&self) -> Option<HeaderValue> {
+        if self.user_agents.is_empty() {
+            return None;
+        }
+
+        let index = self.next_user_agent.fetch_add(1, Ordering::Relaxed);
+        Some(self.user_agents[index % self.user_agents.len()].clone())
+    }
 }
 
 impl<'a> linkcheck::validation::Context for Context<'a> {
@@ -29,29 +62,42 @@ impl<'a> linkcheck::validation::Context for Context<'a> {
 
     fn filesystem_options(&self) -> &Options { &self.filesystem_options }
 
-    fn cache(&self) -> Option<MutexGuard<Cache>> {
-        Some(self.cache.lock().expect("Lock was poisoned"))
-    }
-
     fn should_ignore(&self, link: &Link) -> bool {
-        if !self.cfg.follow_web_links {
-            if let Ok(_) = link.href.parse::<Url>() {
-                return true;
-            }
+        match self.cfg.classify(link) {
+            Some(LinkClassification::Ignored) => return true,
+            Some(LinkClassification::Valid)
+            | Some(LinkClassification::Checked) => return false,
+            None => {},
+        }
+
+        if self.cfg.has_ignored_scheme(&link.href) {
+            return true;
+        }
+
+        // only http(s) links are web links, anything else with a scheme
+        // (e.g. a `htps://` typo) is left for the unknown link check
+        let is_web = crate::web::web_url(&link.href).is_some();
+
+        if !self.cfg.follow_web_links && is_web {
+            return true;
+        }
+
+        if self.cfg.ignore_private_networks
+            && crate::web::web_url(&link.href)
+                .map(|url| crate::web::is_private_network(&url))
+                .unwrap_or(false)
+        {
+            return true;
         }
 
-        self.cfg
-            .exclude
-            .iter()
-            .any(|re| re.find(&link.href).is_some())
+        self.cfg.should_skip(&link.href)
     }
 
     fn url_specific_headers(&self, url: &Url) -> HeaderMap {
-        let url = url.to_string();
         let mut headers = HeaderMap::new();
 
         for (pattern, matching_headers) in &self.interpolated_headers {
-            if pattern.find(&url).is_some() {
+            if pattern.matches(url) {
                 for (name, value) in matching_headers {
                     headers.insert(name.clone(), value.clone());
                 }
@@ -45,6 +45,40 @@ impl HashedRegex {
 
         Ok(HashedRegex { string, re })
     }
+
+    /// Create a [`HashedRegex`] which matches the same strings as a glob
+    /// pattern.
+    ///
+    /// A `*` matches anything except a `/`, `**` matches anything at all, and
+    /// `?` matches a single character other than `/`. The pattern must match
+    /// the whole string.
+    ///
+    /// ```
+    /// use mdbook_linkcheck::HashedRegex;
+    ///
+    /// let re = HashedRegex::from_glob("./drafts/*.md").unwrap();
+    /// assert!(re.is_match("./drafts/chapter_1.md"));
+    /// assert!(!re.is_match("./drafts/nested/chapter_1.md"));
+    /// ```
+    pub fn from_glob(glob: &str) -> Result<Self, regex::Error> {
+        let mut pattern = String::from("^");
+        let mut chars = glob.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    pattern.push_str(".*");
+                },
+                '*' => pattern.push_str("[^/]*"),
+                '?' => pattern.push_str("[^/]"),
+                other => pattern.push_str(&regex::escape(&other.to_string())),
+            }
+        }
+
+        pattern.push('$');
+        HashedRegex::new(&pattern)
+    }
 }
 
 impl<'de> Deserialize<'de> for HashedRegex {
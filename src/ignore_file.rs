@@ -0,0 +1,80 @@
+//! Support for a `.linkcheckignore` file in the book's root directory.
+//!
+//! Each non-blank line is a glob (see [`HashedRegex::from_glob()`]) matched
+//! against a link's href, or a regular expression if prefixed with `regex:`.
+//! Lines starting with `#` are comments.
+
+use crate::{ConfigError, HashedRegex};
+use anyhow::{Context, Error};
+use std::{io::ErrorKind, path::Path};
+
+/// The name of the ignore file, relative to the book's root directory.
+pub const IGNORE_FILE: &str = ".linkcheckignore";
+
+/// Load the patterns from the book's [`IGNORE_FILE`], if there is one.
+pub fn load(root: &Path) -> Result<Vec<HashedRegex>, Error> {
+    let path = root.join(IGNORE_FILE);
+
+    match std::fs::read_to_string(&path) {
+        Ok(src) => parse(&src).with_context(|| {
+            ConfigError(format!("Unable to parse \"{}\"", path.display()))
+        }),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(Error::from(e))
+            .with_context(|| format!("Unable to read \"{}\"", path.display())),
+    }
+}
+
+fn parse(src: &str) -> Result<Vec<HashedRegex>, Error> {
+    let mut patterns = Vec::new();
+
+    for (line_number, line) in src.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let pattern = match line.strip_prefix("regex:") {
+            Some(regex) => HashedRegex::new(regex.trim()),
+            None => HashedRegex::from_glob(line),
+        }
+        .with_context(|| {
+            format!("Invalid pattern on line {}: \"{}\"", line_number + 1, line)
+        })?;
+
+        patterns.push(pattern);
+    }
+
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_globs_and_regexes() {
+        let src = r#"
+# Chapters which haven't been written yet
+./drafts/*
+
+regex:^https://example\.com/private/
+"#;
+
+        let got = parse(src).unwrap();
+
+        assert_eq!(got.len(), 2);
+        assert!(got[0].is_match("./drafts/chapter_1.md"));
+        assert!(!got[0].is_match("./chapter_1.md"));
+        assert!(got[1].is_match("https://example.com/private/page"));
+        assert!(!got[1].is_match("https://example.com/public/page"));
+    }
+
+    #[test]
+    fn invalid_regexes_are_reported_with_their_line() {
+        let err = parse("regex:(unclosed").unwrap_err();
+
+        assert!(err.to_string().contains("line 1"));
+    }
+}
@@ -0,0 +1,136 @@
+//! A structured view of the problems in a [`ValidationOutcome`], for
+//! applications which want to do their own reporting without going through
+//! `codespan_reporting`'s [`Diagnostic`].
+//!
+//! [`Diagnostic`]: codespan_reporting::diagnostic::Diagnostic
+
+use crate::{IncompleteLink, ValidationOutcome};
+use codespan::{FileId, Span};
+use linkcheck::Link;
+
+/// A problem found while checking a book, as passed to
+/// [`ValidationOutcome::for_each_issue()`].
+///
+/// Issues are reported regardless of the [`WarningPolicy`] for their
+/// category, so it's up to the caller to decide which ones matter.
+///
+/// [`WarningPolicy`]: crate::WarningPolicy
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue {
+    /// A link or image which couldn't be resolved.
+    BrokenLink(IssueDetails),
+    /// A reference-style link (e.g. `[foo]`) whose URL was never defined.
+    /// The [`IssueDetails::href`] is the reference name.
+    Incomplete(IssueDetails),
+    /// A link to a file which exists on disk but isn't part of the book.
+    NotInSummary(IssueDetails),
+    /// An absolute link, either to the root of the site (e.g. `/index.md`)
+    /// or to a file on the author's computer (e.g. `/home/user/notes.md`).
+    AbsoluteLink(IssueDetails),
+}
+
+impl Issue {
+    /// Where the issue is and what went wrong.
+    pub fn details(&self) -> &IssueDetails {
+        match self {
+            Issue::BrokenLink(details)
+            | Issue::Incomplete(details)
+            | Issue::NotInSummary(details)
+            | Issue::AbsoluteLink(details) => details,
+        }
+    }
+}
+
+/// Information shared by every kind of [`Issue`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct IssueDetails {
+    /// The file the issue was found in.
+    pub file: FileId,
+    /// Where the issue occurred in the file's source text.
+    pub span: Span,
+    /// The link, as written in the source.
+    pub href: String,
+    /// A human-readable description of the problem.
+    pub message: String,
+}
+
+impl IssueDetails {
+    fn new(link: &Link, message: String) -> Self {
+        IssueDetails {
+            file: link.file,
+            span: link.span,
+            href: link.href.clone(),
+            message,
+        }
+    }
+}
+
+impl ValidationOutcome {
+    /// Visit each of the [`Issue`]s found while checking the book.
+    ///
+    /// ```rust
+    /// # fn main() -> Result<(), anyhow::Error> {
+    /// use mdbook_linkcheck::{check_markdown, Config, Issue};
+    ///
+    /// let src = "[missing](./does-not-exist.md) and [undefined][foo]";
+    /// let outcome = check_markdown(src, &Config::default())?;
+    ///
+    /// let mut hrefs = Vec::new();
+    /// outcome.for_each_issue(|issue| match issue {
+    ///     Issue::BrokenLink(details) | Issue::Incomplete(details) => {
+    ///         hrefs.push(details.href)
+    ///     },
+    ///     _ => {},
+    /// });
+    ///
+    /// assert_eq!(hrefs, vec!["./does-not-exist.md", "foo"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn for_each_issue(&self, mut f: impl FnMut(Issue)) {
+        for invalid in self.invalid_links.iter().chain(&self.invalid_images) {
+            let message = crate::validate::most_specific_error_message(invalid);
+            let details = IssueDetails::new(&invalid.link, message);
+
+            if crate::validate::is_not_in_summary(invalid) {
+                f(Issue::NotInSummary(details));
+            } else {
+                f(Issue::BrokenLink(details));
+            }
+        }
+
+        for incomplete in &self.incomplete_links {
+            let IncompleteLink {
+                ref reference,
+                file,
+                span,
+                ..
+            } = *incomplete;
+
+            f(Issue::Incomplete(IssueDetails {
+                file,
+                span,
+                href: reference.clone(),
+                message: format!(
+                    "Did you forget to define a URL for `{}`?",
+                    reference
+                ),
+            }));
+        }
+
+        for link in &self.absolute_links {
+            f(Issue::AbsoluteLink(IssueDetails::new(
+                link,
+                String::from("Absolute link should be made relative"),
+            )));
+        }
+
+        for link in &self.absolute_fs_paths {
+            let message = format!(
+                "\"{}\" is an absolute path on the author's computer",
+                link.href
+            );
+            f(Issue::AbsoluteLink(IssueDetails::new(link, message)));
+        }
+    }
+}
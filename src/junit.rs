@@ -0,0 +1,253 @@
+//! Reporting results as JUnit XML so they can be picked up by CI test
+//! reporters.
+
+use crate::ValidationOutcome;
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files};
+use linkcheck::Link;
+use std::{collections::BTreeMap, fs::File, io::Write, path::Path};
+
+/// A JUnit XML report where each checked file is a `<testsuite>` and each
+/// link is a `<testcase>`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JUnitReport {
+    suites: Vec<TestSuite>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TestSuite {
+    name: String,
+    cases: Vec<TestCase>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct TestCase {
+    name: String,
+    failure: Option<Failure>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Failure {
+    message: String,
+    text: String,
+}
+
+impl JUnitReport {
+    /// Add the results of checking a set of files to the report.
+    pub fn add(&mut self, outcome: &ValidationOutcome, files: &Files<String>) {
+        let mut cases_by_file: BTreeMap<FileId, Vec<(&Link, TestCase)>> =
+            BTreeMap::new();
+
+        for link in &outcome.valid_links {
+            cases_by_file.entry(link.file).or_default().push((
+                link,
+                TestCase {
+                    name: link.href.clone(),
+                    failure: None,
+                },
+            ));
+        }
+
+        for invalid in
+            outcome.invalid_links.iter().chain(&outcome.invalid_images)
+        {
+            let link = &invalid.link;
+            let message = crate::validate::most_specific_error_message(invalid);
+            let text = format!("{}: {}", location(link, files), message);
+
+            cases_by_file.entry(link.file).or_default().push((
+                link,
+                TestCase {
+                    name: link.href.clone(),
+                    failure: Some(Failure { message, text }),
+                },
+            ));
+        }
+
+        for (file, mut cases) in cases_by_file {
+            cases.sort_by_key(|(link, _)| link.span);
+
+            self.suites.push(TestSuite {
+                name: files.name(file).to_string_lossy().into_owned(),
+                cases: cases.into_iter().map(|(_, case)| case).collect(),
+            });
+        }
+    }
+
+    /// The total number of testcases.
+    pub fn tests(&self) -> usize {
+        self.suites.iter().map(|suite| suite.cases.len()).sum()
+    }
+
+    /// The number of failing testcases.
+    pub fn failures(&self) -> usize {
+        self.suites.iter().map(TestSuite::failures).sum()
+    }
+
+    /// Write the report as XML.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<testsuites name="mdbook-linkcheck" tests="{}" failures="{}">"#,
+            self.tests(),
+            self.failures()
+        )?;
+
+        for suite in &self.suites {
+            writeln!(
+                writer,
+                r#"  <testsuite name="{}" tests="{}" failures="{}">"#,
+                escape(&suite.name),
+                suite.cases.len(),
+                suite.failures()
+            )?;
+
+            for case in &suite.cases {
+                write!(
+                    writer,
+                    r#"    <testcase name="{}" classname="{}""#,
+                    escape(&case.name),
+                    escape(&suite.name)
+                )?;
+
+                match case.failure {
+                    Some(ref failure) => {
+                        writeln!(writer, ">")?;
+                        writeln!(
+                            writer,
+                            r#"      <failure message="{}">{}</failure>"#,
+                            escape(&failure.message),
+                            escape(&failure.text)
+                        )?;
+                        writeln!(writer, "    </testcase>")?;
+                    },
+                    None => writeln!(writer, "/>")?,
+                }
+            }
+
+            writeln!(writer, "  </testsuite>")?;
+        }
+
+        writeln!(writer, "</testsuites>")?;
+
+        Ok(())
+    }
+
+    /// Save the report to a file.
+    pub fn save(&self, filename: &Path) -> Result<(), Error> {
+        if let Some(parent) = filename.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Unable to create \"{}\"", parent.display())
+            })?;
+        }
+
+        let f = File::create(filename).with_context(|| {
+            format!("Unable to create \"{}\"", filename.display())
+        })?;
+
+        self.write(f)
+    }
+}
+
+impl TestSuite {
+    fn failures(&self) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| case.failure.is_some())
+            .count()
+    }
+}
+
+/// The `file:line:column` a link was found at.
+fn location(link: &Link, files: &Files<String>) -> String {
+    let name = files.name(link.file).to_string_lossy();
+
+    match files.location(link.file, link.span.start()) {
+        Ok(location) => format!(
+            "{}:{}:{}",
+            name,
+            location.line.to_usize() + 1,
+            location.column.to_usize() + 1
+        ),
+        Err(_) => name.into_owned(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            other => escaped.push(other),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::Span;
+    use linkcheck::validation::{InvalidLink, Reason};
+    use std::io::{Error as IoError, ErrorKind};
+
+    fn render(report: &JUnitReport) -> String {
+        let mut buffer = Vec::new();
+        report.write(&mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn valid_and_invalid_links_become_testcases() {
+        let mut files = Files::new();
+        let src = "[valid](./a.md)\n\nSee [broken](./b.md?x=1&y=2)\n";
+        let file = files.add("chapter_1.md", String::from(src));
+        let valid = Link::new("./a.md", Span::new(0, 15), file);
+        let broken = Link::new("./b.md?x=1&y=2", Span::new(21, 49), file);
+        let outcome = ValidationOutcome {
+            valid_links: vec![valid],
+            invalid_links: vec![InvalidLink {
+                link: broken,
+                reason: Reason::Io(IoError::new(
+                    ErrorKind::PermissionDenied,
+                    "Permission denied",
+                )),
+            }],
+            ..Default::default()
+        };
+        let mut report = JUnitReport::default();
+
+        report.add(&outcome, &files);
+        let got = render(&report);
+
+        let should_be = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="mdbook-linkcheck" tests="2" failures="1">
+  <testsuite name="chapter_1.md" tests="2" failures="1">
+    <testcase name="./a.md" classname="chapter_1.md"/>
+    <testcase name="./b.md?x=1&amp;y=2" classname="chapter_1.md">
+      <failure message="Permission denied">chapter_1.md:3:5: Permission denied</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#;
+        assert_eq!(got, should_be);
+    }
+
+    #[test]
+    fn an_empty_report_is_still_valid_xml() {
+        let got = render(&JUnitReport::default());
+
+        let should_be = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="mdbook-linkcheck" tests="0" failures="0">
+</testsuites>
+"#;
+        assert_eq!(got, should_be);
+    }
+}
@@ -1,5 +1,6 @@
 /// This module provides an (experimental ad-hoc) functionality of
 /// supporting latex in `mdbook-linkcheck`.
+use crate::config::LatexDelimiter;
 use std::collections::HashSet;
 
 /// A struct that maps text changes from file B to file A, where file
@@ -117,8 +118,11 @@ impl ByteIndexMap {
 }
 
 /// Filters out latex code snippets from md files to avoid false link
-/// matches.
-pub(crate) fn filter_out_latex(src: &str) -> (String, ByteIndexMap) {
+/// matches, including any fragments using the `extra` delimiters.
+pub(crate) fn filter_out_latex(
+    src: &str,
+    extra: &[LatexDelimiter],
+) -> (String, ByteIndexMap) {
     use regex::Regex;
 
     let mut byte_index_map = ByteIndexMap::new();
@@ -148,6 +152,11 @@ pub(crate) fn filter_out_latex(src: &str) -> (String, ByteIndexMap) {
         src = reg.replace_all(&src, replacement).to_string();
     };
 
+    // Custom delimiters go first because their fragments may contain the
+    // usual ones (e.g. a `$` inside `\begin{equation}...\end{equation}`)
+    for delimiter in extra {
+        process_regex(&delimiter.pattern(), "LATEX_CUSTOM_SUBSTITUTED");
+    }
     // Everything between a pair of $$ including newlines
     process_regex(r"\$\$[^\$]*\$\$", "LATEX_DOUBLE_DOLLAR_SUBSTITUTED");
     // Everything between a pair of $ excluding newlines
@@ -25,39 +25,232 @@ extern crate pretty_assertions;
 /// A semver range specifying which versions of `mdbook` this crate supports.
 pub const COMPATIBLE_MDBOOK_VERSIONS: &str = "^0.4.0";
 
+mod anchors;
+mod cache;
+mod case_sensitivity;
+mod classifier;
 mod config;
 mod context;
 mod hashed_regex;
+mod ignore_file;
+mod issues;
+mod junit;
 mod latex;
 mod links;
+mod local_files;
+mod mailto;
+mod progress;
+mod report;
+#[cfg(test)]
+mod test_server;
+mod timings;
+mod unused_patterns;
 mod validate;
+mod web;
 
 pub use crate::{
-    config::{Config, WarningPolicy},
+    cache::{Cache, CacheEntry, CacheStats, CacheSummary, LocalFileEntry},
+    case_sensitivity::CaseMismatch,
+    classifier::{LinkClassification, LinkClassifier},
+    config::{
+        BasicAuth, CacheTimeout, CheckMode, Config, ConfigOverrides, HeaderSet,
+        LatexDelimiter, LatexSupport, LinkRewrite, RedirectPolicy,
+        WarningPolicy, WarningPolicyOverrides,
+    },
     context::Context,
     hashed_regex::HashedRegex,
-    links::{extract as extract_links, IncompleteLink},
-    validate::{validate, NotInSummary, ValidationOutcome},
+    ignore_file::{load as load_ignore_file, IGNORE_FILE},
+    issues::{Issue, IssueDetails},
+    junit::JUnitReport,
+    links::{
+        extract as extract_links, find_duplicate_definitions,
+        DuplicateDefinition, IncompleteLink,
+    },
+    mailto::MalformedLink,
+    report::{merge_reports, LinkCounts, Problem, Report, ReportFormat},
+    timings::OverBudget,
+    unused_patterns::UnusedPattern,
+    validate::{
+        validate, AmbiguousFragment, MissingFragment, NotInSummary,
+        ValidationOutcome, WrongExtension,
+    },
+    web::{
+        CachedFailure, Redirect, RetryAfterTooLong, SmallBody, Soft404,
+        UnexpectedContentType, UnresolvedHost,
+    },
 };
 
 use anyhow::{Context as _, Error};
 use codespan::{FileId, Files};
 use codespan_reporting::{
-    diagnostic::{Diagnostic, Severity},
-    term::termcolor::{ColorChoice, StandardStream},
+    diagnostic::{Diagnostic, LabelStyle, Severity},
+    term::termcolor::{ColorChoice, StandardStream, WriteColor},
 };
-use linkcheck::validation::Cache;
 use mdbook::{
     book::{Book, BookItem},
-    renderer::RenderContext,
+    renderer::{RenderContext, Renderer},
+    MDBook,
 };
 use semver::{Version, VersionReq};
-use std::{fs::File, path::Path};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt::{self, Display, Formatter},
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::runtime::Runtime;
 
-/// Run the link checking pipeline.
+/// Options controlling how [`run_with_options()`] checks a book.
 ///
-/// If `selected_files` is `Some`, then links in the given list of files are
-/// checked, rather than checking links in all files.
+/// More options may be added in the future, so start from
+/// [`RunOptions::default()`] and set the fields you need.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct RunOptions {
+    /// If `Some`, this file is used as a cache; otherwise, no caching is
+    /// used, and any existing cache is ignored. [`Config::cache_file`] takes
+    /// precedence when it is set.
+    pub cache_file: Option<PathBuf>,
+    /// Whether diagnostics should be printed in colour.
+    pub colour: ColorChoice,
+    /// If `Some`, then links in the given list of files are checked, rather
+    /// than checking links in all files.
+    pub selected_files: Option<Vec<String>>,
+    /// Show a progress bar while checking links instead of periodically
+    /// logging how many links have been checked.
+    pub progress: bool,
+    /// If `Some`, a JSON [`Report`] is written to this file so it can be
+    /// merged with the results from other runs (see [`merge_reports()`]).
+    pub report_file: Option<PathBuf>,
+    /// If `Some`, a JUnit XML report is written to this file.
+    pub junit_file: Option<PathBuf>,
+    /// If `Some`, any problems are also printed to stdout in this format,
+    /// with paths relative to the book's root directory.
+    pub format: Option<ReportFormat>,
+    /// Print each diagnostic as a single `file:line:column: message` line
+    /// (relative to the book's root directory) instead of showing the source
+    /// code around it.
+    pub compact: bool,
+    /// Which links should be checked.
+    pub scope: LinkScope,
+    /// Print a `=== chapter.md (3 issues) ===` header before the diagnostics
+    /// for each file. Web links are checked after local links, so a file may
+    /// get a second header for problems found with its web links.
+    pub grouped: bool,
+    /// If `Some`, give up on any links which haven't been checked after this
+    /// long and report them as skipped.
+    pub max_duration: Option<Duration>,
+    /// Add a note to each broken link to a local file saying which path it
+    /// resolved to.
+    pub explain: bool,
+    /// Only print diagnostics for errors, and only print the final counts
+    /// when something failed.
+    pub quiet: bool,
+    /// Settings which take precedence over the book's configuration.
+    pub overrides: ConfigOverrides,
+    /// If `Some`, links are checked on this runtime instead of a new one, so
+    /// it can be shared when checking several books.
+    pub runtime: Option<Arc<Runtime>>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            cache_file: None,
+            colour: ColorChoice::Auto,
+            selected_files: None,
+            progress: false,
+            report_file: None,
+            junit_file: None,
+            format: None,
+            compact: false,
+            scope: LinkScope::default(),
+            grouped: false,
+            max_duration: None,
+            explain: false,
+            quiet: false,
+            overrides: ConfigOverrides::default(),
+            runtime: None,
+        }
+    }
+}
+
+/// Which kinds of links should be checked?
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LinkScope {
+    /// Check every link.
+    All,
+    /// Only check links within the book (and other files on disk), never
+    /// making any web requests.
+    Internal,
+    /// Only check web links, skipping every check on the file system
+    /// (including whether chapters are in `SUMMARY.md`).
+    External,
+}
+
+impl LinkScope {
+    /// Split the links into those which should be checked and those which
+    /// should be ignored.
+    pub fn partition(
+        self,
+        links: Vec<linkcheck::Link>,
+    ) -> (Vec<linkcheck::Link>, Vec<linkcheck::Link>) {
+        links.into_iter().partition(|link| {
+            let is_web = link.href.parse::<reqwest::Url>().is_ok()
+                || crate::web::is_protocol_relative(&link.href);
+
+            match self {
+                LinkScope::All => true,
+                LinkScope::Internal => !is_web,
+                LinkScope::External => is_web,
+            }
+        })
+    }
+}
+
+impl Default for LinkScope {
+    fn default() -> LinkScope { LinkScope::All }
+}
+
+/// The error returned by [`run()`] when the book contains broken links.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BrokenLinks;
+
+impl Display for BrokenLinks {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("One or more incorrect links")
+    }
+}
+
+impl std::error::Error for BrokenLinks {}
+
+/// The error returned by [`run_with_options()`] when some links weren't checked
+/// because [`RunOptions::max_duration`] was exceeded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TimedOut;
+
+impl Display for TimedOut {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("Gave up before every link was checked")
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// Attached to errors caused by how the link checker was configured or
+/// invoked (e.g. an invalid `output.linkcheck` table), so they can be told
+/// apart from problems with the book itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError(pub String);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.0) }
+}
+
+/// Run the link checking pipeline.
 ///
 /// If `cache_file` is `Some`, it is used as a cache; otherwise, no caching is
 /// used, and any existing cache is ignored.
@@ -67,58 +260,440 @@ pub fn run(
     ctx: &RenderContext,
     selected_files: Option<Vec<String>>,
 ) -> Result<(), Error> {
-    let mut cache = if let Some(cache_file) = cache_file {
-        load_cache(cache_file)
-    } else {
-        Cache::default()
+    let options = RunOptions {
+        cache_file: cache_file.map(Path::to_path_buf),
+        colour,
+        selected_files,
+        ..Default::default()
     };
 
+    run_with_options(ctx, &options)
+}
+
+/// Run the link checking pipeline with more control over how the book is
+/// checked.
+pub fn run_with_options(
+    ctx: &RenderContext,
+    options: &RunOptions,
+) -> Result<(), Error> {
+    let RunOptions {
+        ref cache_file,
+        colour,
+        ref selected_files,
+        progress,
+        ref report_file,
+        ref junit_file,
+        format,
+        compact,
+        scope,
+        grouped,
+        max_duration,
+        explain,
+        quiet,
+        ref overrides,
+        ref runtime,
+    } = *options;
+    let cache_file = cache_file.as_deref();
+
     log::info!("Started the link checker");
     log::debug!("Selected file: {:?}", selected_files);
 
-    let cfg = crate::get_config(&ctx.config)?;
+    let book_cfg = crate::get_book_config(&ctx.root, &ctx.config)?;
+    let mut cfg = book_cfg.clone();
+    overrides.apply(&mut cfg);
+    cfg.exclude.extend(crate::load_ignore_file(&ctx.root)?);
     crate::version_check(&ctx.version)?;
 
+    // the location in book.toml takes precedence, unless caching is disabled
+    let configured_cache_file = cfg.cache_file(&ctx.root);
+    let cache_file = cache_file
+        .map(|default| configured_cache_file.as_deref().unwrap_or(default));
+
+    let fingerprint = cfg.fingerprint();
+    let mut cache = if let Some(cache_file) = cache_file {
+        load_cache(cache_file, &fingerprint)
+    } else {
+        Cache::default()
+    };
+
     if log::log_enabled!(log::Level::Trace) {
         for line in format!("{:#?}", cfg).lines() {
             log::trace!("{}", line);
         }
     }
 
-    let file_filter = |fname: &Path| {
-        if let Some(ref selected_files) = selected_files {
-            selected_files.contains(&fname.display().to_string())
+    // each selected file may be a glob (e.g. "chapter_*/*.md"), and a path
+    // without any wildcards only matches itself
+    let selected_patterns: Option<Vec<HashedRegex>> =
+        selected_files.as_ref().map(|selected_files| {
+            selected_files
+                .iter()
+                .filter_map(|glob| HashedRegex::from_glob(glob).ok())
+                .collect()
+        });
+    let file_filter = |fname: &Path| match selected_patterns {
+        Some(ref patterns) => {
+            // Note: the patterns always use forward slashes
+            let fname = fname.display().to_string().replace('\\', "/");
+            patterns.iter().any(|pattern| pattern.is_match(&fname))
+        },
+        None => true,
+    };
+
+    let deadline =
+        max_duration.map(|max_duration| Instant::now() + max_duration);
+    let runtime = match *runtime {
+        Some(ref runtime) => Arc::clone(runtime),
+        None => Arc::new(crate::validate::runtime()?),
+    };
+    let mut broken_links = 0;
+    let mut timed_out = 0;
+    let mut has_errors = false;
+    let mut report = Report::default();
+    let mut output = Report::default();
+    let mut junit = JUnitReport::default();
+    let mut hrefs = HashSet::new();
+
+    for source in book_sources(ctx)? {
+        if let Some(ref language) = source.language {
+            log::info!("Checking the \"{}\" translation", language);
+        }
+
+        let relative_src_dir = source
+            .src_dir
+            .strip_prefix(&ctx.root)
+            .unwrap_or(&source.src_dir);
+        // diagnostics are emitted as soon as each batch of links is checked
+        let mut diags = Vec::new();
+        let mut emit = |files: &Files<String>,
+                        outcome: &ValidationOutcome|
+         -> Result<(), Error> {
+            let got = outcome.generate_diagnostics(files, &cfg);
+            let shown = shown_diagnostics(&got, quiet);
+
+            if compact {
+                let stderr = std::io::stderr();
+                Report::from_diagnostics(files, &shown)
+                    .with_prefix(relative_src_dir)
+                    .write(stderr.lock(), ReportFormat::Text)?;
+            } else {
+                report_errors(files, &shown, colour, grouped)?;
+            }
+
+            diags.extend(got);
+            Ok(())
+        };
+
+        // each translation keeps its own local file results, and only
+        // shares web results when asked to
+        let mut translation_cache = source.language.as_ref().map(|language| {
+            cache.take_translation(language, cfg.shared_web_cache)
+        });
+        let got = check_links(
+            &source.src_dir,
+            &source.book,
+            translation_cache.as_mut().unwrap_or(&mut cache),
+            &cfg,
+            &runtime,
+            file_filter,
+            scope,
+            progress,
+            explain,
+            deadline,
+            &mut emit,
+        );
+        if let (Some(language), Some(translation_cache)) =
+            (source.language.as_ref(), translation_cache)
+        {
+            cache.restore_translation(
+                language,
+                translation_cache,
+                cfg.shared_web_cache,
+            );
+        }
+        let (files, outcome) = got?;
+        let mut source_report = Report::from_diagnostics(&files, &diags);
+        source_report.counts = Some(outcome.counts());
+        let relative_report = source_report.with_prefix(relative_src_dir);
+
+        output.extend(relative_report);
+        report.extend(source_report);
+        junit.add(&outcome, &files);
+        hrefs.extend(
+            crate::unused_patterns::hrefs(&outcome).map(ToString::to_string),
+        );
+
+        broken_links +=
+            outcome.invalid_links.len() + outcome.invalid_images.len();
+        timed_out += outcome.timed_out.len();
+        has_errors |= diags.iter().any(|diag| diag.severity >= Severity::Error);
+    }
+
+    // patterns are only unused if we looked at every link
+    if selected_files.is_none() && scope == LinkScope::All {
+        let unused = crate::unused_patterns::find_unused_patterns(
+            &book_cfg,
+            hrefs.iter().map(String::as_str),
+        );
+        let policy = cfg
+            .warning_policy_overrides
+            .unused_pattern
+            .unwrap_or(cfg.warning_policy);
+        let diags = crate::unused_patterns::diagnostics(&unused, policy);
+        let files = Files::new();
+        let unused_report = Report::from_diagnostics(&files, &diags);
+        let shown = shown_diagnostics(&diags, quiet);
+
+        if compact {
+            let stderr = std::io::stderr();
+            Report::from_diagnostics(&files, &shown)
+                .write(stderr.lock(), ReportFormat::Text)?;
         } else {
-            true
+            report_errors(&files, &shown, colour, grouped)?;
         }
-    };
 
-    let (files, outcome) = check_links(&ctx, &mut cache, &cfg, file_filter)?;
-    let diags = outcome.generate_diagnostics(&files, cfg.warning_policy);
-    report_errors(&files, &diags, colour)?;
+        output.extend(unused_report.clone());
+        report.extend(unused_report);
+        has_errors |= diags.iter().any(|diag| diag.severity >= Severity::Error);
+    }
 
     if let Some(cache_file) = cache_file {
         save_cache(cache_file, &cache);
+        let stats = cache.stats();
+        log::info!("Web cache: {} hits, {} misses", stats.hits, stats.misses);
+    }
+
+    if let Some(report_file) = report_file {
+        report.save(report_file)?;
+    }
+
+    if let Some(junit_file) = junit_file {
+        junit.save(junit_file)?;
+    }
+
+    if let Some(format) = format {
+        let stdout = std::io::stdout();
+        output.write(stdout.lock(), format)?;
+    }
+
+    let failed = has_errors || timed_out > 0;
+
+    // the JSON output already includes the counts
+    if format != Some(ReportFormat::Json) && (failed || !quiet) {
+        eprintln!("{}", output.counts.unwrap_or_default());
+    }
+
+    if let Some(max_ignored_ratio) = cfg.max_ignored_ratio {
+        let counts = output.counts.unwrap_or_default();
+
+        if counts.total > 0 {
+            let ratio = counts.ignored as f64 / counts.total as f64;
+
+            if ratio > max_ignored_ratio {
+                return Err(Error::msg(ConfigError(format!(
+                    "{} of {} links ({:.0}%) were ignored, which is more than \
+                     the maximum ratio of {:.0}% (max-ignored-ratio = {})",
+                    counts.ignored,
+                    counts.total,
+                    ratio * 100.0,
+                    max_ignored_ratio * 100.0,
+                    max_ignored_ratio,
+                ))));
+            }
+        }
     }
 
-    if diags.iter().any(|diag| diag.severity >= Severity::Error) {
-        log::info!("{} broken links found", outcome.invalid_links.len());
-        Err(Error::msg("One or more incorrect links"))
+    if timed_out > 0 {
+        // the results are incomplete, so that takes precedence over any
+        // broken links we did find
+        log::info!(
+            "{} links weren't checked before the maximum duration was reached",
+            timed_out
+        );
+        Err(Error::new(TimedOut))
+    } else if has_errors {
+        log::info!("{} broken links found", broken_links);
+        Err(Error::new(BrokenLinks))
     } else {
         log::info!("No broken links found");
         Ok(())
     }
 }
 
-/// Get the configuration used by `mdbook-linkcheck`.
+/// Check the links in a single markdown document without needing a full
+/// `mdbook` [`RenderContext`].
+///
+/// Relative links are resolved against the current directory and, because
+/// there is no `SUMMARY.md`, any markdown file that exists counts as part of
+/// the book. Spans in the returned [`ValidationOutcome`] are byte offsets into
+/// `source`.
+///
+/// ```rust
+/// # fn main() -> Result<(), anyhow::Error> {
+/// use mdbook_linkcheck::{check_markdown, Config};
+///
+/// let src = "Have a look at [the next chapter](./does-not-exist.md).";
+///
+/// let outcome = check_markdown(src, &Config::default())?;
+///
+/// assert_eq!(outcome.invalid_links.len(), 1);
+/// let broken = &outcome.invalid_links[0].link;
+/// assert_eq!(broken.href, "./does-not-exist.md");
+/// let span = broken.span;
+/// assert!(src[span.start().to_usize()..span.end().to_usize()]
+///     .contains("./does-not-exist.md"));
+/// # Ok(())
+/// # }
+/// ```
+pub fn check_markdown(
+    source: &str,
+    cfg: &Config,
+) -> Result<ValidationOutcome, Error> {
+    let current_dir = std::env::current_dir()
+        .context("Unable to determine the current directory")?;
+    let mut files = Files::new();
+    let file_id = files.add("input.md", source.to_string());
+
+    let (links, incomplete_links) = extract_links(cfg, vec![file_id], &files);
+    let duplicate_definitions =
+        find_duplicate_definitions(cfg, vec![file_id], &files);
+    let mut outcome = crate::validate(
+        &links,
+        cfg,
+        &current_dir,
+        &mut Cache::default(),
+        &files,
+        &[file_id],
+        incomplete_links,
+    )?;
+    outcome.duplicate_definitions = duplicate_definitions;
+
+    // there's no SUMMARY.md to leave things out of
+    let (not_in_summary, invalid_links): (Vec<_>, Vec<_>) = outcome
+        .invalid_links
+        .into_iter()
+        .partition(crate::validate::is_not_in_summary);
+    outcome.invalid_links = invalid_links;
+    outcome
+        .valid_links
+        .extend(not_in_summary.into_iter().map(|invalid| invalid.link));
+    outcome.valid_links.sort_by_key(|link| link.span);
+
+    Ok(outcome)
+}
+
+/// Summarise the contents of a cache file.
+pub fn cache_summary(cache_file: &Path, cfg: &Config) -> CacheSummary {
+    read_cache(cache_file).summary(&cfg.cache_timeout)
+}
+
+/// Delete a cache file, if it exists.
+pub fn clear_cache(cache_file: &Path) -> Result<(), Error> {
+    match std::fs::remove_file(cache_file) {
+        Ok(_) => {
+            log::debug!("Deleted \"{}\"", cache_file.display());
+            Ok(())
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::from(e)
+            .context(format!("Unable to delete \"{}\"", cache_file.display()))),
+    }
+}
+
+/// The environment variable pointing to a file with settings shared between
+/// several books (see [`get_book_config()`]).
+pub const SHARED_CONFIG_VAR: &str = "MDBOOK_LINKCHECK_CONFIG";
+
+/// The file containing settings shared between several books, which is looked
+/// for in a book's root directory and its parents when [`SHARED_CONFIG_VAR`]
+/// isn't set.
+pub const SHARED_CONFIG_FILE: &str = "linkcheck.toml";
+
+/// Get the configuration used by `mdbook-linkcheck`, including any overrides
+/// from environment variables (see [`Config::apply_env_overrides()`]).
+///
+/// Settings from the file [`SHARED_CONFIG_VAR`] points to are used for
+/// anything the book's `output.linkcheck` table doesn't set. Use
+/// [`get_book_config()`] to also find a [`SHARED_CONFIG_FILE`] next to the
+/// book.
 pub fn get_config(cfg: &mdbook::Config) -> Result<Config, Error> {
-    match cfg.get("output.linkcheck") {
-        Some(raw) => raw
-            .clone()
-            .try_into()
-            .context("Unable to deserialize the `output.linkcheck` table.")
-            .map_err(Error::from),
-        None => Ok(Config::default()),
+    let shared = std::env::var_os(SHARED_CONFIG_VAR)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from);
+
+    load_config(cfg, shared.as_deref())
+}
+
+/// The same as [`get_config()`], except when [`SHARED_CONFIG_VAR`] isn't set
+/// the closest [`SHARED_CONFIG_FILE`] in the book's `root` directory or one of
+/// its parents is used.
+pub fn get_book_config(
+    root: &Path,
+    cfg: &mdbook::Config,
+) -> Result<Config, Error> {
+    let shared = match std::env::var_os(SHARED_CONFIG_VAR) {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => root
+            .ancestors()
+            .map(|dir| dir.join(SHARED_CONFIG_FILE))
+            .find(|candidate| candidate.is_file()),
+    };
+
+    load_config(cfg, shared.as_deref())
+}
+
+fn load_config(
+    cfg: &mdbook::Config,
+    shared: Option<&Path>,
+) -> Result<Config, Error> {
+    let mut raw = match shared {
+        Some(shared) => {
+            log::debug!(
+                "Loading shared settings from \"{}\"",
+                shared.display()
+            );
+            let text = std::fs::read_to_string(shared).with_context(|| {
+                ConfigError(format!("Unable to read \"{}\"", shared.display()))
+            })?;
+            text.parse::<toml::Value>().with_context(|| {
+                ConfigError(format!("Unable to parse \"{}\"", shared.display()))
+            })?
+        },
+        None => toml::Value::Table(Default::default()),
+    };
+
+    if let Some(book) = cfg.get("output.linkcheck") {
+        merge_toml(&mut raw, book.clone());
+    }
+
+    let mut config: Config = raw.try_into().context(ConfigError(
+        String::from("Unable to deserialize the `output.linkcheck` table."),
+    ))?;
+
+    config
+        .apply_env_overrides()
+        .context(ConfigError(String::from(
+            "Unable to apply settings from environment variables",
+        )))?;
+
+    Ok(config)
+}
+
+/// Merge `overrides` into `base`, recursing into tables so (for example) a
+/// book can add to the shared `http-headers` without replacing them.
+fn merge_toml(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_toml(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    },
+                }
+            }
+        },
+        (base, overrides) => *base = overrides,
     }
 }
 
@@ -134,7 +709,24 @@ pub fn version_check(version: &str) -> Result<(), Error> {
             "mdbook-linkcheck isn't compatible with this version of mdbook ({} is not in the range {})",
             found, constraints
         );
-        Err(Error::msg(msg))
+        Err(Error::msg(ConfigError(msg)))
+    }
+}
+
+/// Read the book's `SUMMARY.md` into memory so diagnostics can point at it
+/// (see [`ValidationOutcome::summary`]).
+pub fn load_summary_into_memory(
+    src_dir: &Path,
+    dest: &mut Files<String>,
+) -> Option<FileId> {
+    let path = src_dir.join("SUMMARY.md");
+
+    match std::fs::read_to_string(&path) {
+        Ok(content) => Some(dest.add("SUMMARY.md", content)),
+        Err(e) => {
+            log::debug!("Unable to read \"{}\": {}", path.display(), e);
+            None
+        },
     }
 }
 
@@ -169,62 +761,328 @@ where
     ids
 }
 
+/// The diagnostics which should be printed, leaving out anything less severe
+/// than an error when running quietly.
+fn shown_diagnostics(
+    diags: &[Diagnostic<FileId>],
+    quiet: bool,
+) -> Cow<'_, [Diagnostic<FileId>]> {
+    if quiet {
+        Cow::Owned(
+            diags
+                .iter()
+                .filter(|diag| diag.severity >= Severity::Error)
+                .cloned()
+                .collect(),
+        )
+    } else {
+        Cow::Borrowed(diags)
+    }
+}
+
 fn report_errors(
     files: &Files<String>,
     diags: &[Diagnostic<FileId>],
     colour: ColorChoice,
+    grouped: bool,
 ) -> Result<(), Error> {
     let mut writer = StandardStream::stderr(colour);
+    write_diagnostics(&mut writer, files, diags, grouped)
+}
+
+fn write_diagnostics<W: WriteColor>(
+    writer: &mut W,
+    files: &Files<String>,
+    diags: &[Diagnostic<FileId>],
+    grouped: bool,
+) -> Result<(), Error> {
     let cfg = codespan_reporting::term::Config::default();
 
-    for diag in diags {
-        codespan_reporting::term::emit(&mut writer, &cfg, files, diag)?;
+    if !grouped {
+        for diag in diags {
+            codespan_reporting::term::emit(writer, &cfg, files, diag)?;
+        }
+
+        return Ok(());
+    }
+
+    for (file_id, group) in group_by_file(diags) {
+        if let Some(file_id) = file_id {
+            let issues = if group.len() == 1 { "issue" } else { "issues" };
+            writeln!(
+                writer,
+                "=== {} ({} {}) ===",
+                Path::new(files.name(file_id)).display(),
+                group.len(),
+                issues
+            )?;
+        }
+
+        for diag in group {
+            codespan_reporting::term::emit(writer, &cfg, files, diag)?;
+        }
     }
 
     Ok(())
 }
 
-fn check_links<F>(
+/// Group diagnostics by the file their primary label points into, keeping
+/// the order each file was first seen in. Diagnostics which aren't about a
+/// particular file (e.g. an unused pattern) are grouped under `None`.
+fn group_by_file(
+    diags: &[Diagnostic<FileId>],
+) -> Vec<(Option<FileId>, Vec<&Diagnostic<FileId>>)> {
+    let mut groups: Vec<(Option<FileId>, Vec<&Diagnostic<FileId>>)> =
+        Vec::new();
+
+    for diag in diags {
+        let file_id = diag
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .or_else(|| diag.labels.first())
+            .map(|label| label.file_id);
+
+        match groups.iter_mut().find(|(id, _)| *id == file_id) {
+            Some((_, group)) => group.push(diag),
+            None => groups.push((file_id, vec![diag])),
+        }
+    }
+
+    groups
+}
+
+/// One of the books to be checked.
+///
+/// Normally this is just the book `mdbook` gave us, but multilingual books
+/// have a separate source directory (and `SUMMARY.md`) for each language.
+struct BookSource<'a> {
+    language: Option<String>,
+    src_dir: PathBuf,
+    book: Cow<'a, Book>,
+}
+
+fn book_sources(ctx: &RenderContext) -> Result<Vec<BookSource<'_>>, Error> {
+    let languages = ctx
+        .config
+        .get("language")
+        .and_then(|value| value.as_table())
+        .filter(|_| ctx.config.book.multilingual);
+
+    let languages = match languages {
+        Some(languages) if !languages.is_empty() => languages,
+        _ => {
+            return Ok(vec![BookSource {
+                language: None,
+                src_dir: ctx.source_dir(),
+                book: Cow::Borrowed(&ctx.book),
+            }])
+        },
+    };
+
+    // mdbook gives us the (already preprocessed) default language, so only
+    // the other translations need to be loaded
+    let default_language = languages
+        .iter()
+        .find(|(_, language)| {
+            language
+                .get("default")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(false)
+        })
+        .map(|(name, _)| name.as_str())
+        .or(ctx.config.book.language.as_deref());
+    let mut sources = Vec::new();
+
+    for language in languages.keys() {
+        let src_dir = ctx.source_dir().join(language);
+        let book = if Some(language.as_str()) == default_language {
+            Cow::Borrowed(&ctx.book)
+        } else {
+            Cow::Owned(load_translation(ctx, language).with_context(|| {
+                format!(
+                    "Unable to load the \"{}\" translation from \"{}\"",
+                    language,
+                    src_dir.display()
+                )
+            })?)
+        };
+
+        sources.push(BookSource {
+            language: Some(language.clone()),
+            src_dir,
+            book,
+        });
+    }
+
+    Ok(sources)
+}
+
+/// Load a translation and run it through the book's preprocessors, so we see
+/// the same chapters (e.g. with `{{#include}}`s expanded) as mdbook would.
+fn load_translation(
     ctx: &RenderContext,
+    language: &str,
+) -> Result<Book, Error> {
+    let mut config = ctx.config.clone();
+    config.book.src = config.book.src.join(language);
+    let md = MDBook::load_with_config(&ctx.root, config)?;
+    let (book, _) = md.preprocess_book(&Linkcheck)?;
+
+    Ok(book)
+}
+
+/// A stand-in for this renderer, so preprocessors which are only enabled for
+/// some renderers are run the same as they would be for us.
+struct Linkcheck;
+
+impl Renderer for Linkcheck {
+    fn name(&self) -> &str { "linkcheck" }
+
+    fn render(&self, _ctx: &RenderContext) -> mdbook::errors::Result<()> {
+        Ok(())
+    }
+}
+
+/// Check the links in a book, calling `on_outcome` with the results for local
+/// links and then (if there are any) with the results for web links.
+#[allow(clippy::too_many_arguments)]
+fn check_links<F, O>(
+    src_dir: &Path,
+    book: &Book,
     cache: &mut Cache,
     cfg: &Config,
+    runtime: &Runtime,
     file_filter: F,
+    scope: LinkScope,
+    progress: bool,
+    explain: bool,
+    deadline: Option<Instant>,
+    mut on_outcome: O,
 ) -> Result<(Files<String>, ValidationOutcome), Error>
 where
     F: Fn(&Path) -> bool,
+    O: FnMut(&Files<String>, &ValidationOutcome) -> Result<(), Error>,
 {
     log::info!("Scanning book for links");
     let mut files: Files<String> = Files::new();
-    let file_ids =
-        crate::load_files_into_memory(&ctx.book, &mut files, file_filter);
-    let (links, incomplete_links) =
-        crate::extract_links(cfg, file_ids.clone(), &files);
+    let src = dunce::canonicalize(src_dir)
+        .context("Unable to resolve the source directory")?;
+    // every chapter is loaded so links to chapters which weren't selected
+    // aren't mistaken for links to files that aren't in SUMMARY.md
+    let file_ids = crate::load_files_into_memory(book, &mut files, |_| true);
+    let selected: Vec<FileId> = file_ids
+        .iter()
+        .copied()
+        .filter(|id| file_filter(Path::new(files.name(*id))))
+        .collect();
+    let summary_filter = file_filter(Path::new("SUMMARY.md"));
+    let summary = crate::load_summary_into_memory(&src, &mut files);
+    let (mut links, mut incomplete_links) =
+        crate::extract_links(cfg, selected.clone(), &files);
+
+    if let Some(summary) = summary.filter(|_| summary_filter) {
+        let (summary_links, summary_incomplete) =
+            extract_summary_links(cfg, summary, &files);
+        links.extend(summary_links);
+        incomplete_links.extend(summary_incomplete);
+    }
+
     log::info!(
         "Found {} links ({} incomplete links)",
         links.len(),
         incomplete_links.len()
     );
-    let src = dunce::canonicalize(ctx.source_dir())
-        .context("Unable to resolve the source directory")?;
-    let outcome = crate::validate(
-        &links,
-        &cfg,
+    let (links, skipped) = scope.partition(links);
+    // local links are quick to check, so any problems with them are reported
+    // before we start waiting on web servers
+    let (web_links, local_links): (Vec<_>, Vec<_>) = links
+        .into_iter()
+        .partition(|link| crate::web::web_url(&link.href).is_some());
+
+    let mut validator = crate::validate::Validator::new(
+        cfg,
         &src,
         cache,
         &files,
         &file_ids,
-        incomplete_links,
-    )?;
+        runtime,
+        local_links.len() + web_links.len(),
+        progress,
+        deadline,
+    );
+    let mut outcome = validator.check(&local_links, incomplete_links);
+    outcome.ignored.extend(skipped);
+    outcome.duplicate_definitions =
+        crate::find_duplicate_definitions(cfg, selected, &files);
+    if explain {
+        outcome.resolved_paths =
+            crate::validate::resolved_paths(&outcome, &src, cfg, &files);
+    }
+    outcome.summary = summary;
+
+    if web_links.is_empty() {
+        outcome.over_budget = validator.finish();
+        on_outcome(&files, &outcome)?;
+    } else {
+        validator.suspend(|| on_outcome(&files, &outcome))?;
+        let mut web_outcome = validator.check(&web_links, Vec::new());
+        web_outcome.over_budget = validator.finish();
+        on_outcome(&files, &web_outcome)?;
+        outcome.merge(web_outcome);
+    }
 
     Ok((files, outcome))
 }
 
-fn load_cache(filename: &Path) -> Cache {
+/// Find the links in `SUMMARY.md` so typos in chapter paths get reported.
+///
+/// Draft chapters (e.g. `[Coming Soon]()`) don't have a path yet, so their
+/// empty links are skipped.
+fn extract_summary_links(
+    cfg: &Config,
+    summary: FileId,
+    files: &Files<String>,
+) -> (Vec<linkcheck::Link>, Vec<IncompleteLink>) {
+    let (links, incomplete_links) =
+        crate::extract_links(cfg, std::iter::once(summary), files);
+    let links = links
+        .into_iter()
+        .filter(|link| !link.href.trim().is_empty())
+        .collect();
+
+    (links, incomplete_links)
+}
+
+/// Load the cache, throwing away any results which were created with a
+/// different [`Config::fingerprint()`].
+fn load_cache(filename: &Path, fingerprint: &str) -> Cache {
+    let mut cache = read_cache(filename);
+
+    if !cache.is_empty() && cache.config_fingerprint() != Some(fingerprint) {
+        log::info!(
+            "The settings used for web requests have changed, so every link will be rechecked"
+        );
+        cache = Cache::default();
+    }
+
+    cache.set_config_fingerprint(fingerprint);
+    cache.reset_stats();
+    cache
+}
+
+fn read_cache(filename: &Path) -> Cache {
     log::debug!("Loading cache from {}", filename.display());
 
     match File::open(filename) {
-        Ok(f) => match serde_json::from_reader(f) {
-            Ok(cache) => cache,
+        Ok(f) => match serde_json::from_reader::<_, Cache>(f) {
+            Ok(cache) if cache.is_current_format() => cache,
+            Ok(_) => {
+                log::info!(
+                    "The cache was written by an older version of mdbook-linkcheck, so every link will be rechecked"
+                );
+                Cache::default()
+            },
             Err(e) => {
                 log::warn!("Unable to deserialize the cache: {}", e);
                 Cache::default()
@@ -246,19 +1104,149 @@ fn save_cache(filename: &Path, cache: &Cache) {
 
     log::debug!("Saving the cache to {}", filename.display());
 
-    match File::create(filename) {
-        Ok(f) => {
-            if let Err(e) = serde_json::to_writer(f, cache) {
-                log::warn!("Saving the cache as JSON failed: {}", e);
-            }
-        },
-        Err(e) => log::warn!("Unable to create the cache file: {}", e),
+    // write to a temporary file first and rename it over the old cache, so
+    // being interrupted part way through (e.g. by Ctrl-C) can't leave behind
+    // a truncated cache
+    let temp = temporary_cache_file(filename);
+
+    if let Err(e) = write_cache(&temp, cache) {
+        log::warn!("Unable to save the cache: {:?}", e);
+        let _ = std::fs::remove_file(&temp);
+        return;
+    }
+
+    if let Err(e) = std::fs::rename(&temp, filename) {
+        log::warn!("Unable to replace the cache file: {}", e);
+        let _ = std::fs::remove_file(&temp);
     }
 }
 
+fn write_cache(filename: &Path, cache: &Cache) -> Result<(), Error> {
+    let f =
+        File::create(filename).context("Unable to create the cache file")?;
+    let mut writer = std::io::BufWriter::new(f);
+    serde_json::to_writer(&mut writer, cache)
+        .context("Saving the cache as JSON failed")?;
+    let f = writer.into_inner().context("Unable to write the cache")?;
+    f.sync_all().context("Unable to write the cache")?;
+
+    Ok(())
+}
+
+/// A file next to the cache to write to before it replaces the cache. The
+/// process ID is included so concurrent runs don't clobber each other.
+fn temporary_cache_file(filename: &Path) -> PathBuf {
+    let mut name = filename
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(format!(".{}.tmp", std::process::id()));
+
+    filename.with_file_name(name)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use codespan_reporting::term::termcolor::NoColor;
+
+    #[test]
+    fn the_cache_is_replaced_atomically() {
+        let temp = tempfile::tempdir().unwrap();
+        let cache_file = temp.path().join("cache.json");
+        std::fs::write(&cache_file, "{\"entries\": {").unwrap();
+        // pretend an earlier run was interrupted half way through saving
+        let leftover = temporary_cache_file(&cache_file);
+        std::fs::write(&leftover, "{\"entries\": {\"https://exa").unwrap();
+        let mut cache = Cache::default();
+        cache.insert_local_file(
+            PathBuf::from("chapter_1.md"),
+            LocalFileEntry {
+                resolved: PathBuf::from("/book/src/chapter_1.md"),
+                directory_modified: std::time::SystemTime::UNIX_EPOCH,
+                settings: String::from("abc"),
+            },
+        );
+
+        save_cache(&cache_file, &cache);
+
+        let saved = std::fs::read_to_string(&cache_file).unwrap();
+        let got: Cache = serde_json::from_str(&saved).unwrap();
+        assert_eq!(got, cache);
+        assert!(!leftover.exists());
+    }
+
+    #[test]
+    fn book_settings_take_precedence_over_shared_ones() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(SHARED_CONFIG_FILE),
+            "follow-web-links = true\n\
+             cache-timeout = 60\n\
+             exclude = ['crates\\.io']\n\
+             [warning-policy-overrides]\n\
+             redirect = 'ignore'\n",
+        )
+        .unwrap();
+        let root = temp.path().join("books").join("guide");
+        std::fs::create_dir_all(&root).unwrap();
+        let book_cfg: mdbook::Config = "[output.linkcheck]\n\
+                                        cache-timeout = 3600\n\
+                                        [output.linkcheck.warning-policy-overrides]\n\
+                                        small-body = 'error'\n"
+            .parse()
+            .unwrap();
+
+        let got = get_book_config(&root, &book_cfg).unwrap();
+
+        assert!(got.follow_web_links);
+        assert_eq!(got.cache_timeout, CacheTimeout::from(3600));
+        assert_eq!(got.exclude, vec![HashedRegex::new(r"crates\.io").unwrap()]);
+        // nested tables are merged instead of replaced
+        assert_eq!(
+            got.warning_policy_overrides.redirect,
+            Some(WarningPolicy::Ignore)
+        );
+        assert_eq!(
+            got.warning_policy_overrides.small_body,
+            Some(WarningPolicy::Error)
+        );
+    }
+
+    #[test]
+    fn group_diagnostics_by_file() {
+        let mut files = Files::new();
+        let first = files.add("chapter_1.md", String::from("[a](./a.md)"));
+        let second = files.add(
+            Path::new("nested").join("chapter_2.md"),
+            String::from("[b](./b.md)"),
+        );
+        let diag = |file| {
+            Diagnostic::error()
+                .with_message("File not found")
+                .with_labels(vec![
+                    codespan_reporting::diagnostic::Label::primary(file, 0..11),
+                ])
+        };
+        let diags = vec![diag(first), diag(second), diag(first), diag(first)];
+        let mut writer = NoColor::new(Vec::new());
+
+        write_diagnostics(&mut writer, &files, &diags, true).unwrap();
+
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let headers: Vec<_> = output
+            .lines()
+            .filter(|line| line.starts_with("==="))
+            .collect();
+        let second_header = format!(
+            "=== {} (1 issue) ===",
+            Path::new("nested").join("chapter_2.md").display()
+        );
+        assert_eq!(
+            headers,
+            vec!["=== chapter_1.md (3 issues) ===", second_header.as_str()]
+        );
+    }
 
     #[test]
     fn always_stay_compatible_with_mdbook_dependency() {
@@ -270,4 +1258,57 @@ mod tests {
             got.unwrap_err()
         );
     }
+
+    #[test]
+    fn local_links_are_reported_before_web_links_are_checked() {
+        use crate::test_server::TestServer;
+        use mdbook::book::Chapter;
+
+        let server = TestServer::unresponsive();
+        let temp = tempfile::tempdir().unwrap();
+        let content =
+            format!("[broken](./missing.md) and [web]({})", server.url());
+        std::fs::write(temp.path().join("chapter_1.md"), &content).unwrap();
+        let mut book = Book::new();
+        book.push_item(Chapter::new(
+            "Chapter 1",
+            content,
+            "chapter_1.md",
+            Vec::new(),
+        ));
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+        let mut reported = Vec::new();
+
+        // bail as soon as the local links have been reported, like a user
+        // hitting ctrl-C while the web links are being checked
+        let got = check_links(
+            temp.path(),
+            &book,
+            &mut Cache::default(),
+            &cfg,
+            &crate::validate::runtime().unwrap(),
+            |_| true,
+            LinkScope::All,
+            false,
+            false,
+            None,
+            |files, outcome| {
+                reported.extend(
+                    outcome
+                        .generate_diagnostics(files, &Config::default())
+                        .into_iter()
+                        .map(|diag| diag.message),
+                );
+                Err(Error::msg("web checking was cancelled"))
+            },
+        );
+
+        assert!(got.is_err());
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].contains("missing.md"), "{:?}", reported);
+        assert_eq!(server.request_count(), 0);
+    }
 }
@@ -1,11 +1,16 @@
 use crate::{
+    anchors::edit_distance,
     config::Config,
     latex::{filter_out_latex, ByteIndexMap},
 };
 use codespan::{ByteIndex, FileId, Files, Span};
 use linkcheck::Link;
-use pulldown_cmark::{BrokenLink, CowStr};
-use std::{cell::RefCell, fmt::Debug};
+use pulldown_cmark::{BrokenLink, CowStr, Event, Options, Parser, Tag};
+use regex::{Captures, Regex};
+use std::{
+    borrow::Cow, cell::RefCell, collections::HashMap, fmt::Debug, ops::Range,
+    path::Path,
+};
 
 /// Search every file in the [`Files`] and collate all the links that are
 /// found.
@@ -21,12 +26,24 @@ where
     let broken_links = RefCell::new(Vec::new());
 
     for file_id in target_files {
-        let src = files.source(file_id);
+        if cfg.should_skip_file(Path::new(files.name(file_id))) {
+            log::debug!(
+                "Skipping {} because it matches \"exclude-files\"",
+                files.name(file_id).to_string_lossy()
+            );
+            continue;
+        }
+
+        let src = blank_out_mdbook_helpers(files.source(file_id));
+        let labels: Vec<String> = reference_definitions(&src)
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
 
-        let (src, byte_index_map) = if cfg.latex_support {
-            filter_out_latex(src)
+        let (src, byte_index_map) = if cfg.latex_support.is_enabled() {
+            filter_out_latex(&src, cfg.latex_support.delimiters())
         } else {
-            (src.clone(), ByteIndexMap::new())
+            (src.into_owned(), ByteIndexMap::new())
         };
 
         log::debug!("Scanning {}", files.name(file_id).to_string_lossy());
@@ -41,7 +58,7 @@ where
         };
 
         links.extend(
-            scan_links(file_id, &src, &mut |broken_link| {
+            scan_links(file_id, &src, cfg.check_titles, &mut |broken_link| {
                 let BrokenLink {
                     reference, span, ..
                 } = broken_link;
@@ -61,26 +78,263 @@ where
                     reference: broken_link.reference.to_string(),
                     span,
                     file: file_id,
+                    suggestion: closest_label(reference, &labels),
                 });
                 None
             })
             .map(|link| Link::new(link.href, mapspan(link.span), link.file)),
         );
+
+        if cfg.check_code_blocks {
+            links.extend(scan_code_blocks(file_id, &src).map(|link| {
+                Link::new(link.href, mapspan(link.span), link.file)
+            }));
+        }
     }
 
     (links, broken_links.into_inner())
 }
 
+/// Is this link an image (e.g. `![diagram](./diagram.png)`) rather than a
+/// hyperlink?
+pub(crate) fn is_image(link: &Link, files: &Files<String>) -> bool {
+    files
+        .source(link.file)
+        .get(link.span.start().to_usize()..)
+        .map(|rest| rest.starts_with("!["))
+        .unwrap_or(false)
+}
+
+/// Get the location of every blockquote in a chapter. Nested blockquotes are
+/// part of the outermost one, so they aren't listed separately.
+pub(crate) fn blockquotes(src: &str) -> Vec<Range<usize>> {
+    let mut depth = 0;
+    let mut ranges = Vec::new();
+
+    for (event, range) in Parser::new(src).into_offset_iter() {
+        match event {
+            Event::Start(Tag::BlockQuote) => {
+                if depth == 0 {
+                    ranges.push(range);
+                }
+                depth += 1;
+            },
+            Event::End(Tag::BlockQuote) => depth -= 1,
+            _ => {},
+        }
+    }
+
+    ranges
+}
+
+/// Find link reference definitions (e.g. `[foo]: https://example.com/`) which
+/// use a label that was already defined earlier in the same file.
+pub fn find_duplicate_definitions<I>(
+    cfg: &Config,
+    target_files: I,
+    files: &Files<String>,
+) -> Vec<DuplicateDefinition>
+where
+    I: IntoIterator<Item = FileId>,
+{
+    let mut duplicates = Vec::new();
+
+    for file_id in target_files {
+        if cfg.should_skip_file(Path::new(files.name(file_id))) {
+            continue;
+        }
+
+        let src = blank_out_mdbook_helpers(files.source(file_id));
+        let mut first_definitions: HashMap<String, Span> = HashMap::new();
+
+        for (reference, range) in reference_definitions(&src) {
+            let span = Span::new(range.start as u32, range.end as u32);
+
+            match first_definitions.get(&normalize_label(&reference)) {
+                Some(&original) => duplicates.push(DuplicateDefinition {
+                    reference,
+                    file: file_id,
+                    original,
+                    duplicate: span,
+                }),
+                None => {
+                    first_definitions.insert(normalize_label(&reference), span);
+                },
+            }
+        }
+    }
+
+    duplicates
+}
+
+/// Get the label and location of every link reference definition, ignoring
+/// anything inside a code block.
+fn reference_definitions(src: &str) -> Vec<(String, Range<usize>)> {
+    let definition =
+        Regex::new(r"(?m)^ {0,3}\[([^\]^][^\]]*)\]:[ \t]*\S[^\n]*").unwrap();
+    let code_blocks: Vec<Range<usize>> = Parser::new(src)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect();
+
+    definition
+        .captures_iter(src)
+        .map(|caps| {
+            let whole = caps.get(0).unwrap();
+            (caps[1].to_string(), whole.start()..whole.end())
+        })
+        .filter(|(_, range)| {
+            !code_blocks.iter().any(|block| block.contains(&range.start))
+        })
+        .collect()
+}
+
+/// Find the defined label a reference was probably meant to be, if any of
+/// them are close enough.
+fn closest_label(reference: &str, labels: &[String]) -> Option<String> {
+    let normalized = normalize_label(reference);
+    // anything further away than this is probably a different reference
+    let max_distance = (normalized.chars().count() / 3).max(1);
+
+    labels
+        .iter()
+        .map(|label| {
+            (edit_distance(&normalized, &normalize_label(label)), label)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, label)| label.clone())
+}
+
+/// Labels are matched case-insensitively, treating consecutive whitespace as
+/// a single space.
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Replace any `mdbook` helpers (e.g. `{{#playground file.rs}}`) which
+/// survived preprocessing with whitespace so they can't be mistaken for links.
+///
+/// The replacement has the same length as the original text so spans are
+/// unaffected.
+fn blank_out_mdbook_helpers(src: &str) -> Cow<'_, str> {
+    let helper = Regex::new(r"\{\{#[^}]*\}\}").unwrap();
+
+    helper.replace_all(src, |caps: &Captures<'_>| " ".repeat(caps[0].len()))
+}
+
+/// The extensions `mdbook` enables when rendering, so links in tables (for
+/// example) are found in the same place they'll end up in the book.
+fn markdown_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+}
+
 fn scan_links<'a, F>(
     file_id: FileId,
     src: &'a str,
+    check_titles: bool,
     cb: &'a mut F,
 ) -> impl Iterator<Item = Link> + 'a
 where
     F: FnMut(BrokenLink<'_>) -> Option<(CowStr<'a>, CowStr<'a>)> + 'a,
 {
-    linkcheck::scanners::markdown_with_broken_link_callback(src, Some(cb))
-        .map(move |(link, span)| Link::new(link, span, file_id))
+    Parser::new_with_broken_link_callback(src, markdown_options(), Some(cb))
+        .into_offset_iter()
+        .flat_map(move |(event, range)| match event {
+            Event::Start(Tag::Link(_, dest, title))
+            | Event::Start(Tag::Image(_, dest, title)) => {
+                let span = Span::new(range.start as u32, range.end as u32);
+                let mut links =
+                    vec![Link::new(dest.to_string(), span, file_id)];
+
+                if check_titles {
+                    links.extend(scan_title(file_id, src, range, &title));
+                }
+
+                links
+            },
+            _ => Vec::new(),
+        })
+}
+
+/// Find bare URLs in a link or image's title (e.g. the `"..."` in
+/// `[text](url "See https://example.com/")`). Each URL is pointed at where
+/// it appears in the link's source text, falling back to the whole link when
+/// the title is somewhere else (e.g. in a reference definition).
+fn scan_title(
+    file_id: FileId,
+    src: &str,
+    link: Range<usize>,
+    title: &str,
+) -> Vec<Link> {
+    let text = &src[link.clone()];
+
+    bare_urls(title)
+        .into_iter()
+        .map(|(_, href)| {
+            let span = match text.find(href) {
+                Some(offset) => {
+                    let start = link.start + offset;
+                    Span::new(start as u32, (start + href.len()) as u32)
+                },
+                None => Span::new(link.start as u32, link.end as u32),
+            };
+            Link::new(href, span, file_id)
+        })
+        .collect()
+}
+
+/// Find bare URLs (e.g. `https://example.com/`) in some text, along with the
+/// byte offset each one starts at. Trailing punctuation is assumed to be part
+/// of the surrounding sentence rather than the URL.
+fn bare_urls(text: &str) -> Vec<(usize, &str)> {
+    let url = Regex::new(r#"https?://[^\s<>"'`()\[\]{}]+"#).unwrap();
+
+    url.find_iter(text)
+        .map(|m| {
+            let href = m.as_str().trim_end_matches(|c| ".,;:!?".contains(c));
+            (m.start(), href)
+        })
+        .collect()
+}
+
+/// Find bare URLs (e.g. in comments) inside fenced and indented code blocks.
+fn scan_code_blocks(
+    file_id: FileId,
+    src: &str,
+) -> impl Iterator<Item = Link> + '_ {
+    let mut in_code_block = false;
+    let mut links = Vec::new();
+
+    for (event, range) in Parser::new(src).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(_) if in_code_block => {
+                let text = &src[range.clone()];
+
+                for (offset, href) in bare_urls(text) {
+                    let start = range.start + offset;
+                    let span =
+                        Span::new(start as u32, (start + href.len()) as u32);
+                    links.push(Link::new(href, span, file_id));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    links.into_iter()
 }
 
 /// A potential link that has a broken reference (e.g `[foo]` when there is no
@@ -93,4 +347,22 @@ pub struct IncompleteLink {
     pub file: FileId,
     /// Where this incomplete link occurred in the source text.
     pub span: Span,
+    /// The most similar label defined in the same file, if any are close
+    /// enough to be a typo (e.g. `foos` when the link was `[foo]`).
+    pub suggestion: Option<String>,
+}
+
+/// A link reference definition (e.g. `[foo]: https://example.com/`) whose
+/// label was already defined earlier in the same file. Only the first
+/// definition is ever used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateDefinition {
+    /// The reference's label (e.g. the `foo` in `[foo]: ...`).
+    pub reference: String,
+    /// Which file were the definitions found in?
+    pub file: FileId,
+    /// The first definition, which is the one that actually gets used.
+    pub original: Span,
+    /// The later definition, which is ignored.
+    pub duplicate: Span,
 }
@@ -0,0 +1,179 @@
+//! Remembering which local files links resolved to, so rebuilding a book
+//! (e.g. with `mdbook serve`) doesn't need to search the file system for the
+//! same files every time.
+
+use crate::{Cache, Config, LocalFileEntry};
+use codespan::Files;
+use linkcheck::Link;
+use reqwest::Url;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// A link to a file on disk.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LocalTarget<'a> {
+    /// The directory containing the file the link was written in.
+    pub(crate) current_dir: PathBuf,
+    /// The path part of the link.
+    pub(crate) path: &'a str,
+    /// The fragment (without the leading `#`), if there was one.
+    pub(crate) fragment: Option<&'a str>,
+    /// The path that was linked to, used as the cache key.
+    pub(crate) key: PathBuf,
+}
+
+/// Looks up local files in the [`Cache`], only trusting an entry while the
+/// directory containing the file hasn't changed.
+#[derive(Debug)]
+pub(crate) struct LocalFiles<'a> {
+    src_dir: &'a Path,
+    settings: String,
+    /// When each directory was last modified. Many links point into the same
+    /// directory, so each one is only looked at once.
+    directories: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl<'a> LocalFiles<'a> {
+    pub(crate) fn new(src_dir: &'a Path, cfg: &Config) -> Self {
+        LocalFiles {
+            src_dir,
+            settings: cfg.resolution_fingerprint(),
+            directories: HashMap::new(),
+        }
+    }
+
+    /// Work out which local file a link points to, if it points to one.
+    pub(crate) fn target<'l>(
+        &self,
+        link: &'l Link,
+        files: &Files<String>,
+    ) -> Option<LocalTarget<'l>> {
+        let href = link.href.as_str();
+
+        if href.parse::<Url>().is_ok()
+            || crate::web::is_protocol_relative(href)
+            || href.contains('?')
+        {
+            return None;
+        }
+
+        let (path, fragment) = match href.find('#') {
+            Some(ix) => (&href[..ix], Some(&href[ix + 1..])),
+            None => (href, None),
+        };
+
+        if path.is_empty() {
+            return None;
+        }
+
+        let mut current_dir = self.src_dir.join(files.name(link.file));
+        current_dir.pop();
+        // absolute links are relative to the book's source directory
+        let key = match path.strip_prefix('/') {
+            Some(relative_to_root) => self.src_dir.join(relative_to_root),
+            None => current_dir.join(path),
+        };
+
+        Some(LocalTarget {
+            current_dir,
+            path,
+            fragment,
+            key,
+        })
+    }
+
+    /// Get the file a link resolved to last time, as long as nothing has
+    /// been added to or removed from its directory since then.
+    pub(crate) fn lookup(
+        &mut self,
+        cache: &Cache,
+        target: &LocalTarget<'_>,
+    ) -> Option<PathBuf> {
+        let entry = cache.lookup_local_file(&target.key)?;
+
+        if entry.settings != self.settings {
+            return None;
+        }
+
+        let directory = entry.resolved.parent()?;
+
+        if self.directory_modified(directory)? == entry.directory_modified {
+            Some(entry.resolved.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Remember which file a link resolved to.
+    pub(crate) fn remember(
+        &mut self,
+        cache: &mut Cache,
+        target: &LocalTarget<'_>,
+        resolved: PathBuf,
+    ) {
+        let directory_modified = match resolved
+            .parent()
+            .and_then(|directory| self.directory_modified(directory))
+        {
+            Some(modified) => modified,
+            None => return,
+        };
+
+        cache.insert_local_file(
+            target.key.clone(),
+            LocalFileEntry {
+                resolved,
+                directory_modified,
+                settings: self.settings.clone(),
+            },
+        );
+    }
+
+    fn directory_modified(&mut self, directory: &Path) -> Option<SystemTime> {
+        *self
+            .directories
+            .entry(directory.to_path_buf())
+            .or_insert_with(|| {
+                directory.metadata().and_then(|meta| meta.modified()).ok()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan::Span;
+
+    #[test]
+    fn find_the_file_a_link_points_to() {
+        let src_dir = Path::new("/book/src");
+        let local_files = LocalFiles::new(src_dir, &Config::default());
+        let mut files = Files::new();
+        let file = files.add("nested/chapter_1.md", String::new());
+        let target = |href: &str| {
+            let link = Link::new(href, Span::default(), file);
+            local_files
+                .target(&link, &files)
+                .map(|target| (target.key, target.fragment.map(String::from)))
+        };
+
+        assert_eq!(
+            target("./chapter_2.md#intro"),
+            Some((
+                PathBuf::from("/book/src/nested/./chapter_2.md"),
+                Some(String::from("intro"))
+            ))
+        );
+        assert_eq!(
+            target("/README.md"),
+            Some((PathBuf::from("/book/src/README.md"), None))
+        );
+        assert_eq!(target("#intro"), None);
+        assert_eq!(target("https://example.com/"), None);
+        assert_eq!(target("mailto:someone@example.com"), None);
+        assert_eq!(target("//cdn.example.com/lib.js"), None);
+    }
+}
@@ -0,0 +1,204 @@
+//! Checking `mailto:` and `tel:` links.
+
+use crate::{Config, LinkClassification};
+use linkcheck::{
+    validation::{InvalidLink, Outcomes, Reason},
+    Link,
+};
+use regex::Regex;
+use std::{
+    fmt::{self, Display, Formatter},
+    io,
+};
+
+/// Is this a `mailto:` or `tel:` link?
+pub(crate) fn is_contact_link(href: &str) -> bool {
+    scheme_body(href, "mailto:").is_some()
+        || scheme_body(href, "tel:").is_some()
+}
+
+/// Check `mailto:` and `tel:` links, or ignore them if
+/// [`Config::check_mailto`] isn't set.
+pub(crate) fn validate(links: Vec<Link>, cfg: &Config) -> Outcomes {
+    let mut outcomes = Outcomes::default();
+
+    for link in links {
+        let skipped = cfg.should_skip(&link.href)
+            && cfg.classify(&link) != Some(LinkClassification::Checked);
+
+        if skipped || !cfg.check_mailto {
+            outcomes.ignored.push(link);
+            continue;
+        }
+
+        match check(&link.href) {
+            Ok(()) => outcomes.valid.push(link),
+            Err(malformed) => outcomes.invalid.push(InvalidLink {
+                link,
+                reason: Reason::Io(io::Error::new(
+                    io::ErrorKind::Other,
+                    malformed,
+                )),
+            }),
+        }
+    }
+
+    outcomes
+}
+
+fn check(href: &str) -> Result<(), MalformedLink> {
+    let malformed = |value: &str, expected| MalformedLink {
+        href: href.to_string(),
+        value: value.to_string(),
+        expected,
+    };
+
+    if let Some(address) = scheme_body(href, "mailto:") {
+        if !is_valid_mailto(address) {
+            return Err(malformed(address, "email address"));
+        }
+    } else if let Some(number) = scheme_body(href, "tel:") {
+        if !is_valid_tel(number) {
+            return Err(malformed(number, "phone number"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Get everything after the scheme, ignoring case (`MAILTO:` is fine too).
+fn scheme_body<'a>(href: &'a str, scheme: &str) -> Option<&'a str> {
+    let prefix = href.get(..scheme.len())?;
+
+    if prefix.eq_ignore_ascii_case(scheme) {
+        Some(&href[scheme.len()..])
+    } else {
+        None
+    }
+}
+
+/// Check the `local@domain` addresses (comma-separated) and any
+/// `?key=value&...` parameters in a `mailto:` link.
+fn is_valid_mailto(body: &str) -> bool {
+    let address = Regex::new(
+        r"^[A-Za-z0-9.!#$%&'*+/=^_`{|}~-]+@[A-Za-z0-9](?:[A-Za-z0-9-]*[A-Za-z0-9])?(?:\.[A-Za-z0-9](?:[A-Za-z0-9-]*[A-Za-z0-9])?)*$",
+    )
+    .unwrap();
+
+    let (addresses, query) = match body.find('?') {
+        Some(ix) => (&body[..ix], Some(&body[ix + 1..])),
+        None => (body, None),
+    };
+
+    let addresses_are_valid = !addresses.is_empty()
+        && addresses
+            .split(',')
+            .all(|addr| address.is_match(&addr.replace("%40", "@")));
+    let query_is_valid = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .all(|param| param.is_empty() || param.contains('='));
+
+    addresses_are_valid && query_is_valid
+}
+
+/// Check a `tel:` link only contains digits and the usual visual separators,
+/// optionally followed by `;param=value` parameters (e.g. `;ext=123`).
+fn is_valid_tel(body: &str) -> bool {
+    let number = body.split(';').next().unwrap_or_default();
+    let number = number.replace("%20", " ");
+    let pattern = Regex::new(r"^\+?[0-9\-.() ]+$").unwrap();
+
+    pattern.is_match(&number) && number.chars().any(|c| c.is_ascii_digit())
+}
+
+/// An error emitted when a `mailto:` or `tel:` link is malformed.
+#[derive(Debug)]
+pub struct MalformedLink {
+    /// The original link.
+    pub href: String,
+    /// The part after the scheme.
+    pub value: String,
+    /// What we expected to find (e.g. `"email address"`).
+    pub expected: &'static str,
+}
+
+impl Display for MalformedLink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\" isn't a valid {} in \"{}\"",
+            self.value, self.expected, self.href
+        )
+    }
+}
+
+impl std::error::Error for MalformedLink {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::most_specific_error_message;
+    use codespan::{Files, Span};
+
+    fn check_links(hrefs: &[&str], check_mailto: bool) -> Outcomes {
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = hrefs
+            .iter()
+            .map(|href| Link::new(*href, Span::default(), file))
+            .collect();
+        let cfg = Config {
+            check_mailto,
+            ..Default::default()
+        };
+
+        validate(links, &cfg)
+    }
+
+    #[test]
+    fn well_formed_mailto_links_are_valid() {
+        let got = check_links(
+            &[
+                "mailto:someone@example.com",
+                "MAILTO:first@example.com,second@example.org",
+                "mailto:someone@example.com?subject=Hello%20World&body=Hi",
+                "tel:+1-555-555-0100",
+                "tel:(03)%209876%205432;ext=123",
+            ],
+            true,
+        );
+
+        assert_eq!(got.valid.len(), 5, "{:?}", got.invalid);
+        assert!(got.invalid.is_empty());
+    }
+
+    #[test]
+    fn malformed_mailto_links_are_invalid() {
+        let got = check_links(
+            &[
+                "mailto:someone.example.com",
+                "mailto:someone@",
+                "mailto:?subject=Hello",
+                "tel:call-me-maybe",
+                "tel:",
+            ],
+            true,
+        );
+
+        assert!(got.valid.is_empty());
+        assert_eq!(got.invalid.len(), 5);
+        assert_eq!(
+            most_specific_error_message(&got.invalid[0]),
+            "\"someone.example.com\" isn't a valid email address in \"mailto:someone.example.com\""
+        );
+    }
+
+    #[test]
+    fn contact_links_are_ignored_by_default() {
+        let got = check_links(&["mailto:not-an-email", "tel:+61"], false);
+
+        assert_eq!(got.ignored.len(), 2);
+        assert!(got.unknown_category.is_empty());
+    }
+}
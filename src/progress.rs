@@ -0,0 +1,67 @@
+use indicatif::ProgressBar;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How often (in links) progress is logged when there is no progress bar.
+const LOG_INTERVAL: usize = 50;
+
+/// Keeps track of how many links have been checked so far so the user knows
+/// we haven't hung.
+#[derive(Debug)]
+pub(crate) struct Progress {
+    total: usize,
+    checked: AtomicUsize,
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize, show_progress_bar: bool) -> Self {
+        let bar = if show_progress_bar {
+            Some(ProgressBar::new(total as u64))
+        } else {
+            None
+        };
+
+        Progress {
+            total,
+            checked: AtomicUsize::new(0),
+            bar,
+        }
+    }
+
+    /// Record that another `count` links have been checked.
+    pub(crate) fn checked(&self, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        let before = self.checked.fetch_add(count, Ordering::SeqCst);
+        let after = before + count;
+
+        match self.bar {
+            Some(ref bar) => bar.inc(count as u64),
+            None if before / LOG_INTERVAL != after / LOG_INTERVAL
+                || after == self.total =>
+            {
+                log::info!("Checked {} of {} links", after, self.total)
+            },
+            None => {},
+        }
+    }
+
+    /// Call `f` with the progress bar hidden.
+    pub(crate) fn suspend<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        match self.bar {
+            Some(ref bar) => bar.suspend(f),
+            None => f(),
+        }
+    }
+
+    pub(crate) fn finish(&self) {
+        if let Some(ref bar) = self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,497 @@
+//! Machine-readable reports, so the results from several runs (e.g. CI jobs
+//! which each check a different set of files with `--files`) can be merged
+//! into one.
+
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::{Diagnostic, LabelStyle, Severity};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::File,
+    io::Write,
+    path::Path,
+};
+
+/// The problems found by one or more runs of the link checker.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// Every problem that was found, sorted by location.
+    pub problems: Vec<Problem>,
+    /// How many links were checked, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counts: Option<LinkCounts>,
+}
+
+impl Report {
+    /// Create a [`Report`] from the diagnostics emitted for a set of files.
+    pub fn from_diagnostics(
+        files: &Files<String>,
+        diags: &[Diagnostic<FileId>],
+    ) -> Self {
+        let mut report = Report {
+            problems: diags
+                .iter()
+                .map(|diag| Problem::from_diagnostic(files, diag))
+                .collect(),
+            counts: None,
+        };
+        report.normalize();
+
+        report
+    }
+
+    /// Merge several reports into one, removing any duplicates.
+    pub fn merge<I>(reports: I) -> Self
+    where
+        I: IntoIterator<Item = Report>,
+    {
+        let mut merged = Report::default();
+
+        for report in reports {
+            merged.extend(report);
+        }
+
+        merged
+    }
+
+    /// Add the problems from another [`Report`] to this one.
+    ///
+    /// Problems found by both reports (e.g. because two runs checked the
+    /// same file) are only kept once, so the links behind them are only
+    /// counted once too.
+    pub fn extend(&mut self, other: Report) {
+        let mut counts = other.counts;
+
+        if let Some(ref mut counts) = counts {
+            for problem in &other.problems {
+                if self.problems.binary_search(problem).is_ok() {
+                    counts.remove_duplicate(problem);
+                }
+            }
+        }
+
+        self.problems.extend(other.problems);
+        self.normalize();
+
+        if let Some(counts) = counts {
+            self.counts
+                .get_or_insert_with(LinkCounts::default)
+                .add(counts);
+        }
+    }
+
+    /// Did any of the problems in this report fail the link check?
+    pub fn has_errors(&self) -> bool {
+        self.problems.iter().any(|problem| {
+            problem.severity == "error" || problem.severity == "bug"
+        })
+    }
+
+    /// Make every problem's file relative to `dir` (e.g. so paths are
+    /// relative to the book's root instead of its `src/` directory).
+    pub fn with_prefix(&self, dir: &Path) -> Report {
+        let problems = self
+            .problems
+            .iter()
+            .map(|problem| Problem {
+                file: dir.join(&problem.file).display().to_string(),
+                ..problem.clone()
+            })
+            .collect();
+
+        Report {
+            problems,
+            counts: self.counts,
+        }
+    }
+
+    /// Read a report that was previously saved with [`Report::save()`].
+    pub fn load(filename: &Path) -> Result<Self, Error> {
+        let f = File::open(filename).with_context(|| {
+            format!("Unable to open \"{}\"", filename.display())
+        })?;
+
+        serde_json::from_reader(f).with_context(|| {
+            format!("Unable to parse the report in \"{}\"", filename.display())
+        })
+    }
+
+    /// Save the report as JSON.
+    pub fn save(&self, filename: &Path) -> Result<(), Error> {
+        if let Some(parent) = filename.parent() {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("Unable to create \"{}\"", parent.display())
+            })?;
+        }
+
+        let f = File::create(filename).with_context(|| {
+            format!("Unable to create \"{}\"", filename.display())
+        })?;
+        serde_json::to_writer_pretty(f, self)?;
+
+        Ok(())
+    }
+
+    /// Write the report in the given format.
+    pub fn write<W: Write>(
+        &self,
+        mut writer: W,
+        format: ReportFormat,
+    ) -> Result<(), Error> {
+        match format {
+            ReportFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writeln!(writer)?;
+            },
+            ReportFormat::Text => {
+                for problem in &self.problems {
+                    writeln!(
+                        writer,
+                        "{}:{}:{}: {}: {}",
+                        problem.file,
+                        problem.line,
+                        problem.column,
+                        problem.severity,
+                        problem.message
+                    )?;
+                }
+            },
+            ReportFormat::Rdjson => {
+                serde_json::to_writer_pretty(&mut writer, &self.to_rdjson())?;
+                writeln!(writer)?;
+            },
+            ReportFormat::Github => {
+                for problem in &self.problems {
+                    writeln!(
+                        writer,
+                        "::{} file={},line={},col={}::{}",
+                        github_command(&problem.severity),
+                        escape_github_property(&problem.file),
+                        problem.line,
+                        problem.column,
+                        escape_github_data(&problem.message)
+                    )?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Convert the report to [reviewdog's diagnostic format][rdjson].
+    ///
+    /// [rdjson]: https://github.com/reviewdog/reviewdog/tree/master/proto/rdf
+    fn to_rdjson(&self) -> serde_json::Value {
+        let diagnostics: Vec<_> = self
+            .problems
+            .iter()
+            .map(|problem| {
+                serde_json::json!({
+                    "message": problem.message,
+                    "location": {
+                        "path": problem.file,
+                        "range": {
+                            "start": {
+                                "line": problem.line,
+                                "column": problem.column,
+                            },
+                        },
+                    },
+                    "severity": rdjson_severity(&problem.severity),
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "source": {
+                "name": "mdbook-linkcheck",
+                "url": env!("CARGO_PKG_REPOSITORY"),
+            },
+            "diagnostics": diagnostics,
+        })
+    }
+
+    fn normalize(&mut self) {
+        self.problems.sort();
+        self.problems.dedup();
+    }
+}
+
+/// How many links were checked, and what happened to them.
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct LinkCounts {
+    /// The total number of links (not including incomplete links).
+    pub total: usize,
+    /// Links which are fine.
+    pub valid: usize,
+    /// Links which weren't checked (e.g. because they matched
+    /// [`Config::exclude`]).
+    ///
+    /// [`Config::exclude`]: crate::Config::exclude
+    pub ignored: usize,
+    /// Links which are broken.
+    pub broken: usize,
+    /// Reference-style links which were never defined.
+    pub incomplete: usize,
+}
+
+impl LinkCounts {
+    /// Add the counts from another run to these ones.
+    pub fn add(&mut self, other: LinkCounts) {
+        self.total += other.total;
+        self.valid += other.valid;
+        self.ignored += other.ignored;
+        self.broken += other.broken;
+        self.incomplete += other.incomplete;
+    }
+
+    /// Stop counting the link behind a problem which was already reported.
+    fn remove_duplicate(&mut self, problem: &Problem) {
+        if problem.message == crate::validate::INCOMPLETE_LINK {
+            self.incomplete = self.incomplete.saturating_sub(1);
+        } else if problem.severity == "error" {
+            self.broken = self.broken.saturating_sub(1);
+            self.total = self.total.saturating_sub(1);
+        }
+    }
+}
+
+impl Display for LinkCounts {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Checked {} links: {} valid, {} ignored, {} broken, {} incomplete",
+            self.total, self.valid, self.ignored, self.broken, self.incomplete
+        )
+    }
+}
+
+/// A single problem in a [`Report`].
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct Problem {
+    /// The file the problem was found in, relative to the book's source
+    /// directory.
+    pub file: String,
+    /// The (1-based) line number.
+    pub line: usize,
+    /// The (1-based) column number.
+    pub column: usize,
+    /// How severe the problem is (e.g. `"error"` or `"warning"`).
+    pub severity: String,
+    /// A human-friendly description of the problem.
+    pub message: String,
+}
+
+impl Problem {
+    fn from_diagnostic(
+        files: &Files<String>,
+        diag: &Diagnostic<FileId>,
+    ) -> Self {
+        let label = diag
+            .labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .or_else(|| diag.labels.first());
+
+        let (file, line, column) = match label {
+            Some(label) => {
+                let file = files.name(label.file_id).to_string_lossy();
+                match files.location(label.file_id, label.range.start as u32) {
+                    Ok(location) => (
+                        file.into_owned(),
+                        location.line.to_usize() + 1,
+                        location.column.to_usize() + 1,
+                    ),
+                    Err(_) => (file.into_owned(), 0, 0),
+                }
+            },
+            None => (String::new(), 0, 0),
+        };
+
+        Problem {
+            file,
+            line,
+            column,
+            severity: severity_name(diag.severity).to_string(),
+            message: diag.message.clone(),
+        }
+    }
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    }
+}
+
+fn rdjson_severity(severity: &str) -> &'static str {
+    match severity {
+        "error" | "bug" => "ERROR",
+        "warning" => "WARNING",
+        _ => "INFO",
+    }
+}
+
+/// The [workflow command] GitHub Actions uses to annotate a problem.
+///
+/// [workflow command]: https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+fn github_command(severity: &str) -> &'static str {
+    match severity {
+        "error" | "bug" => "error",
+        "warning" => "warning",
+        _ => "notice",
+    }
+}
+
+fn escape_github_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_github_property(text: &str) -> String {
+    escape_github_data(text)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// The formats a [`Report`] can be written in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReportFormat {
+    /// One `file:line:column: severity: message` line per problem.
+    Text,
+    /// The [`Report`] serialized as JSON.
+    Json,
+    /// [reviewdog's diagnostic format][rdjson], for posting problems as
+    /// review comments.
+    ///
+    /// [rdjson]: https://github.com/reviewdog/reviewdog/tree/master/proto/rdf
+    Rdjson,
+    /// [GitHub Actions workflow commands][commands] (e.g.
+    /// `::error file=src/intro.md,line=1,col=1::File not found`), so problems
+    /// are shown inline on pull requests.
+    ///
+    /// [commands]: https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+    Github,
+}
+
+/// Merge every report matching a glob pattern (e.g. `reports/*.json`).
+pub fn merge_reports(pattern: &str) -> Result<Report, Error> {
+    let paths = glob::glob(pattern)
+        .with_context(|| format!("\"{}\" isn't a valid glob", pattern))?;
+    let mut reports = Vec::new();
+
+    for path in paths {
+        let path = path?;
+        log::debug!("Merging the report from \"{}\"", path.display());
+        reports.push(Report::load(&path)?);
+    }
+
+    log::info!("Merged {} reports", reports.len());
+
+    Ok(Report::merge(reports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_two_partial_reports() {
+        let first = r#"{
+            "problems": [
+                { "file": "b.md", "line": 3, "column": 1, "severity": "error", "message": "File not found: ./x.md" },
+                { "file": "a.md", "line": 10, "column": 5, "severity": "warning", "message": "Potential incomplete link" }
+            ]
+        }"#;
+        let second = r#"{
+            "problems": [
+                { "file": "a.md", "line": 10, "column": 5, "severity": "warning", "message": "Potential incomplete link" },
+                { "file": "a.md", "line": 2, "column": 1, "severity": "error", "message": "File not found: ./y.md" }
+            ]
+        }"#;
+        let first: Report = serde_json::from_str(first).unwrap();
+        let second: Report = serde_json::from_str(second).unwrap();
+
+        let got = Report::merge(vec![first, second]);
+
+        let files: Vec<_> = got
+            .problems
+            .iter()
+            .map(|p| (p.file.as_str(), p.line))
+            .collect();
+        assert_eq!(files, vec![("a.md", 2), ("a.md", 10), ("b.md", 3)]);
+        assert!(got.has_errors());
+
+        let mut json = Vec::new();
+        got.write(&mut json, ReportFormat::Json).unwrap();
+        let round_tripped: Report = serde_json::from_slice(&json).unwrap();
+        assert_eq!(round_tripped, got);
+    }
+
+    #[test]
+    fn problems_found_by_both_reports_are_only_counted_once() {
+        let first = r#"{
+            "problems": [
+                { "file": "a.md", "line": 2, "column": 1, "severity": "error", "message": "File not found: ./y.md" },
+                { "file": "a.md", "line": 10, "column": 5, "severity": "warning", "message": "Potential incomplete link" }
+            ],
+            "counts": { "total": 3, "valid": 2, "ignored": 0, "broken": 1, "incomplete": 1 }
+        }"#;
+        let second = r#"{
+            "problems": [
+                { "file": "a.md", "line": 2, "column": 1, "severity": "error", "message": "File not found: ./y.md" },
+                { "file": "a.md", "line": 10, "column": 5, "severity": "warning", "message": "Potential incomplete link" },
+                { "file": "b.md", "line": 3, "column": 1, "severity": "error", "message": "File not found: ./x.md" }
+            ],
+            "counts": { "total": 5, "valid": 3, "ignored": 0, "broken": 2, "incomplete": 1 }
+        }"#;
+        let first: Report = serde_json::from_str(first).unwrap();
+        let second: Report = serde_json::from_str(second).unwrap();
+
+        let got = Report::merge(vec![first, second]);
+
+        assert_eq!(got.problems.len(), 3);
+        assert_eq!(
+            got.counts,
+            Some(LinkCounts {
+                total: 7,
+                valid: 5,
+                ignored: 0,
+                broken: 2,
+                incomplete: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn escape_github_annotations() {
+        let report = Report {
+            problems: vec![Problem {
+                file: String::from("a,b.md"),
+                line: 1,
+                column: 2,
+                severity: String::from("warning"),
+                message: String::from("100% broken\nsee: here"),
+            }],
+            counts: None,
+        };
+
+        let mut buffer = Vec::new();
+        report.write(&mut buffer, ReportFormat::Github).unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "::warning file=a%2Cb.md,line=1,col=2::100%25 broken%0Asee: here\n"
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! A tiny HTTP server for tests, which answers each request by calling a
+//! closure.
+//!
+//! This module is also pulled into the integration tests with `#[path]`, so
+//! it can only use `std`.
+
+// not every test binary uses every helper
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// A request received by a [`TestServer`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    pub(crate) headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Look up a header, ignoring case.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A HTTP server running in the background, which keeps track of every
+/// request it receives.
+#[derive(Debug)]
+pub(crate) struct TestServer {
+    url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    /// Start a server which sends back whatever `respond` returns. Each
+    /// connection is handled on its own thread, so `respond` may block.
+    pub(crate) fn start<F>(respond: F) -> TestServer
+    where
+        F: Fn(&Request) -> Vec<u8> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+        let respond = Arc::new(respond);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let requests = Arc::clone(&requests);
+                let respond = Arc::clone(&respond);
+
+                thread::spawn(move || {
+                    if let Some(request) = read_request(&stream) {
+                        requests.lock().unwrap().push(request.clone());
+                        let response = respond(&request);
+                        // the client is allowed to hang up on us
+                        let _ = (&stream).write_all(&response);
+                    }
+                });
+            }
+        });
+
+        TestServer {
+            url,
+            requests: recorded,
+        }
+    }
+
+    /// Start a server which accepts requests but never responds.
+    pub(crate) fn unresponsive() -> TestServer {
+        TestServer::start(|_| loop {
+            thread::park();
+        })
+    }
+
+    /// The server's base URL (e.g. `http://127.0.0.1:1234/`).
+    pub(crate) fn url(&self) -> &str { &self.url }
+
+    /// Every request received so far.
+    pub(crate) fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// How many requests have been received so far.
+    pub(crate) fn request_count(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+}
+
+/// Create a response with the provided status line (e.g. `"200 OK"`), extra
+/// headers, and body.
+pub(crate) fn response(status: &str, headers: &[&str], body: &str) -> Vec<u8> {
+    let mut response = format!("HTTP/1.1 {}\r\n", status);
+    for header in headers {
+        response.push_str(header);
+        response.push_str("\r\n");
+    }
+    response.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    ));
+
+    response.into_bytes()
+}
+
+/// An empty `200 OK` response.
+pub(crate) fn ok() -> Vec<u8> { response("200 OK", &[], "") }
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+
+    let mut words = line.split_whitespace();
+    let method = words.next()?.to_string();
+    let path = words.next()?.to_string();
+    let mut headers = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? <= 2 {
+            break;
+        }
+        if let Some(colon) = line.find(':') {
+            headers.push((
+                line[..colon].trim().to_string(),
+                line[colon + 1..].trim().to_string(),
+            ));
+        }
+    }
+
+    Some(Request {
+        method,
+        path,
+        headers,
+    })
+}
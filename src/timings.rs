@@ -0,0 +1,64 @@
+use codespan::FileId;
+use std::{cmp::Reverse, collections::HashMap, time::Duration};
+
+/// The number of hosts/files listed when the time budget is exceeded.
+const SLOWEST_COUNT: usize = 5;
+
+/// Keeps track of how much time was spent checking links, broken down by host
+/// and by the file the links came from.
+#[derive(Debug, Default)]
+pub(crate) struct Timings {
+    by_host: HashMap<String, Duration>,
+    by_file: HashMap<FileId, Duration>,
+}
+
+impl Timings {
+    pub(crate) fn record(&mut self, host: &str, file: FileId, time: Duration) {
+        *self.by_host.entry(host.to_string()).or_default() += time;
+        *self.by_file.entry(file).or_default() += time;
+    }
+
+    /// Check whether the link checker took longer than it was allowed to.
+    pub(crate) fn over_budget(
+        &self,
+        elapsed: Duration,
+        budget: Duration,
+    ) -> Option<OverBudget> {
+        if elapsed <= budget {
+            return None;
+        }
+
+        Some(OverBudget {
+            elapsed,
+            budget,
+            slowest_hosts: slowest(&self.by_host),
+            slowest_files: slowest(&self.by_file),
+        })
+    }
+}
+
+fn slowest<K: Clone>(times: &HashMap<K, Duration>) -> Vec<(K, Duration)> {
+    let mut times: Vec<_> = times
+        .iter()
+        .map(|(key, time)| (key.clone(), *time))
+        .collect();
+    times.sort_by_key(|(_, time)| Reverse(*time));
+    times.truncate(SLOWEST_COUNT);
+
+    times
+}
+
+/// Details about a run which took longer than [`Config::time_budget`].
+///
+/// [`Config::time_budget`]: crate::Config::time_budget
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverBudget {
+    /// How long it took to check every link.
+    pub elapsed: Duration,
+    /// How long we were allowed to take.
+    pub budget: Duration,
+    /// The hosts which took the longest to check, slowest first.
+    pub slowest_hosts: Vec<(String, Duration)>,
+    /// The files whose links took the longest to check, slowest first.
+    pub slowest_files: Vec<(FileId, Duration)>,
+}
@@ -0,0 +1,154 @@
+//! Noticing patterns in `book.toml` which never matched a link, usually
+//! because the URL they were written for has since changed.
+
+use crate::{config::HeaderPattern, Config, ValidationOutcome, WarningPolicy};
+use codespan::FileId;
+use codespan_reporting::diagnostic::Diagnostic;
+
+/// A pattern from [`Config::exclude`] or [`Config::http_headers`] which
+/// didn't match any link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnusedPattern {
+    /// The setting the pattern came from (e.g. `"exclude"`).
+    pub setting: &'static str,
+    /// The pattern itself.
+    pub pattern: String,
+}
+
+/// Every link in the outcome, regardless of whether it was valid.
+pub(crate) fn hrefs(outcome: &ValidationOutcome) -> impl Iterator<Item = &str> {
+    outcome
+        .valid_links
+        .iter()
+        .chain(outcome.invalid_links.iter().map(|invalid| &invalid.link))
+        .chain(outcome.invalid_images.iter().map(|invalid| &invalid.link))
+        .chain(&outcome.ignored)
+        .chain(&outcome.unknown_category)
+        .chain(&outcome.absolute_fs_paths)
+        .chain(&outcome.timed_out)
+        .map(|link| link.href.as_str())
+}
+
+/// Find the patterns which didn't match any of the `hrefs`.
+pub(crate) fn find_unused_patterns<'a, I>(
+    cfg: &Config,
+    hrefs: I,
+) -> Vec<UnusedPattern>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let hrefs: Vec<&str> = hrefs.into_iter().collect();
+    let web_links: Vec<_> = hrefs
+        .iter()
+        .filter_map(|href| crate::web::web_url(href))
+        .collect();
+
+    let unused_excludes = cfg
+        .exclude
+        .iter()
+        .filter(|pattern| !hrefs.iter().any(|href| pattern.is_match(href)))
+        .map(|pattern| UnusedPattern {
+            setting: "exclude",
+            pattern: pattern.string.clone(),
+        });
+
+    // headers are only ever sent with web requests
+    let mut unused_headers: Vec<_> = cfg
+        .http_headers
+        .iter()
+        .filter(|(pattern, set)| {
+            let pattern = HeaderPattern {
+                pattern: (*pattern).clone(),
+                match_full_url: set.match_full_url,
+            };
+            !web_links.iter().any(|url| pattern.matches(url))
+        })
+        .map(|(pattern, _)| UnusedPattern {
+            setting: "http-headers",
+            pattern: pattern.string.clone(),
+        })
+        .collect();
+    unused_headers.sort_by(|left, right| left.pattern.cmp(&right.pattern));
+
+    unused_excludes.chain(unused_headers).collect()
+}
+
+pub(crate) fn diagnostics(
+    unused: &[UnusedPattern],
+    warning_policy: WarningPolicy,
+) -> Vec<Diagnostic<FileId>> {
+    let severity = match warning_policy.severity() {
+        Some(severity) => severity,
+        None => return Vec::new(),
+    };
+
+    unused
+        .iter()
+        .map(|UnusedPattern { setting, pattern }| {
+            Diagnostic::new(severity)
+                .with_message(format!(
+                    "The \"{}\" pattern in `{}` didn't match any links",
+                    pattern, setting
+                ))
+                .with_notes(vec![format!(
+                    "hint: remove it from `output.linkcheck.{}` in book.toml if it is no longer needed",
+                    setting
+                )])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashedRegex, HeaderSet};
+    use codespan_reporting::diagnostic::Severity;
+    use std::{collections::HashMap, iter::FromIterator};
+
+    #[test]
+    fn report_exclude_patterns_which_never_match() {
+        let cfg = Config {
+            exclude: vec![
+                HashedRegex::new(r"crates\.io").unwrap(),
+                HashedRegex::new(r"old-domain\.example").unwrap(),
+            ],
+            http_headers: HashMap::from_iter(vec![(
+                HashedRegex::new(r"crates\.io").unwrap(),
+                HeaderSet::default(),
+            )]),
+            ..Default::default()
+        };
+        let hrefs = vec!["./chapter_1.md", "https://crates.io/crates/mdbook"];
+
+        let got = find_unused_patterns(&cfg, hrefs);
+
+        assert_eq!(
+            got,
+            vec![UnusedPattern {
+                setting: "exclude",
+                pattern: String::from(r"old-domain\.example"),
+            }]
+        );
+        let diags = diagnostics(&got, WarningPolicy::Warn);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert!(diags[0].message.contains(r"old-domain\.example"));
+        assert!(diagnostics(&got, WarningPolicy::Ignore).is_empty());
+    }
+
+    #[test]
+    fn header_patterns_only_match_web_links() {
+        let cfg = Config {
+            http_headers: HashMap::from_iter(vec![(
+                HashedRegex::new("chapter").unwrap(),
+                HeaderSet::default(),
+            )]),
+            ..Default::default()
+        };
+
+        let got = find_unused_patterns(&cfg, vec!["./chapter_1.md"]);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].setting, "http-headers");
+    }
+}
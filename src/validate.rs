@@ -1,124 +1,623 @@
-use crate::{Config, Context, IncompleteLink, WarningPolicy};
-use anyhow::Error;
-use codespan::{FileId, Files};
+use crate::{
+    case_sensitivity::CaseMismatch,
+    local_files::LocalFiles,
+    progress::Progress,
+    timings::{OverBudget, Timings},
+    web::{Redirect, SmallBody, Soft404, WebOutcomes},
+    Cache, Config, Context, DuplicateDefinition, IncompleteLink,
+    LinkClassification, LinkCounts, WarningPolicy,
+};
+use anyhow::{Context as _, Error};
+use codespan::{FileId, Files, Span};
 use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
 use linkcheck::{
-    validation::{Cache, InvalidLink, Options, Outcomes, Reason},
-    Link,
+    validation::{Context as _, InvalidLink, Options, Outcomes, Reason},
+    Category, Link,
 };
+use pulldown_cmark::{Event, Parser, Tag};
+use rayon::prelude::*;
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
     ffi::{OsStr, OsString},
     fmt::{self, Display, Formatter},
+    ops::Range,
     path::{Component, Path, PathBuf},
-    sync::Mutex,
+    sync::{atomic::AtomicUsize, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
-use tokio::runtime::Builder;
+use tokio::runtime::{Builder, Runtime};
 
-fn lc_validate(
-    links: &[Link],
-    cfg: &Config,
-    src_dir: &Path,
-    cache: &mut Cache,
-    files: &Files<String>,
-    file_ids: &[FileId],
-) -> Outcomes {
-    let file_names = file_ids
-        .iter()
-        .map(|id| files.name(*id).to_os_string())
-        .collect();
+/// Checks a file that a link resolved to, and the fragment it points at.
+type CheckFile = dyn Fn(&Path, Option<&str>) -> Result<(), Reason>;
 
-    let options = Options::default()
-        .with_root_directory(src_dir)
-        .expect("The source directory doesn't exist?")
-        .set_alternate_extensions(vec![(
-            "html".to_string(),
-            vec!["md".to_string()],
-        )])
-        .set_links_may_traverse_the_root_directory(
-            cfg.traverse_parent_directories,
-        )
-        // take into account the `index` preprocessor which rewrites `README.md`
-        // to `index.md` (which tne gets rendered as `index.html`)
-        .set_default_file("README.md")
-        .set_custom_validation(ensure_included_in_book(src_dir, file_names));
+/// Checks links in batches (e.g. local links before web links, so problems
+/// with local links are reported without waiting on web servers). Every batch
+/// shares the same HTTP client, runtime, and progress bar, and the time budget
+/// and deadline cover all of them.
+pub(crate) struct Validator<'a> {
+    ctx: Context<'a>,
+    cache: &'a mut Cache,
+    file_ids: &'a [FileId],
+    anchors: Option<HashMap<OsString, Vec<String>>>,
+    check_cached_file: Box<CheckFile>,
+    local_files: LocalFiles<'a>,
+    runtime: &'a Runtime,
+    started: Instant,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Validator<'a> {
+    /// Get ready to check `link_count` links on the `runtime`. A progress bar
+    /// may be shown instead of periodically logging how many links have been
+    /// checked, and any links which haven't been checked by the `deadline` are
+    /// skipped.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        cfg: &'a Config,
+        src_dir: &'a Path,
+        cache: &'a mut Cache,
+        files: &'a Files<String>,
+        file_ids: &'a [FileId],
+        runtime: &'a Runtime,
+        link_count: usize,
+        show_progress_bar: bool,
+        deadline: Option<Instant>,
+    ) -> Self {
+        let file_names: Vec<OsString> = file_ids
+            .iter()
+            .map(|id| files.name(*id).to_os_string())
+            .collect();
 
-    let interpolated_headers = cfg.interpolate_headers(cfg.warning_policy);
+        let anchors = if cfg.check_fragments {
+            let mut anchors: HashMap<OsString, Vec<String>> = file_ids
+                .iter()
+                .map(|id| {
+                    let name = files.name(*id).to_os_string();
+                    (name, crate::anchors::anchors(files.source(*id)))
+                })
+                .collect();
 
-    let ctx = Context {
-        client: cfg.client(),
-        filesystem_options: options,
-        cfg,
-        src_dir,
-        cache: Mutex::new(cache.clone()),
-        files,
-        interpolated_headers,
-    };
-    let links = collate_links(links, src_dir, files);
+            for (path, generated) in &cfg.generated_anchors {
+                anchors
+                    .entry(path.clone().into_os_string())
+                    .or_default()
+                    .extend(generated.iter().cloned());
+            }
+
+            Some(anchors)
+        } else {
+            None
+        };
+
+        let options = Options::default()
+            .with_root_directory(src_dir)
+            .expect("The source directory doesn't exist?")
+            .set_alternate_extensions(vec![(
+                "html".to_string(),
+                cfg.markdown_extensions.clone(),
+            )])
+            .set_links_may_traverse_the_root_directory(
+                cfg.traverse_parent_directories,
+            )
+            // take into account the `index` preprocessor which rewrites
+            // `README.md` to `index.md` (which tne gets rendered as
+            // `index.html`)
+            .set_default_file(&cfg.default_index_file)
+            .set_custom_validation(ensure_included_in_book(
+                src_dir,
+                file_names.clone(),
+                anchors.clone(),
+                cfg.markdown_extensions.clone(),
+                cfg.additional_summary_files.clone(),
+                cfg.check_included_in_summary,
+                cfg.not_in_summary_message.clone(),
+            ));
+        let check_cached_file = ensure_included_in_book(
+            src_dir,
+            file_names,
+            anchors.clone(),
+            cfg.markdown_extensions.clone(),
+            cfg.additional_summary_files.clone(),
+            cfg.check_included_in_summary,
+            cfg.not_in_summary_message.clone(),
+        );
+
+        let interpolated_headers = cfg.interpolate_headers(cfg.warning_policy);
+        let user_agents = cfg.interpolate_user_agents(cfg.warning_policy);
+
+        let ctx = Context {
+            client: cfg.client(),
+            filesystem_options: options,
+            cfg,
+            src_dir,
+            cache: Mutex::new(cache.clone()),
+            files,
+            interpolated_headers,
+            progress: Progress::new(link_count, show_progress_bar),
+            user_agents,
+            next_user_agent: AtomicUsize::new(0),
+            timings: Mutex::new(Timings::default()),
+            run_started: SystemTime::now(),
+            responder: None,
+        };
+
+        Validator {
+            ctx,
+            cache,
+            file_ids,
+            anchors,
+            check_cached_file: Box::new(check_cached_file),
+            local_files: LocalFiles::new(src_dir, cfg),
+            runtime,
+            started: Instant::now(),
+            deadline,
+        }
+    }
+
+    /// Check a batch of links.
+    pub(crate) fn check(
+        &mut self,
+        links: &[Link],
+        incomplete_links: Vec<IncompleteLink>,
+    ) -> ValidationOutcome {
+        let cfg = self.ctx.cfg;
+        let src_dir = self.ctx.src_dir;
+        let files = self.ctx.files;
+
+        // there's no point checking paths on the author's computer because
+        // readers will never have those files
+        let (absolute_fs_paths, links): (Vec<Link>, Vec<Link>) =
+            links.iter().cloned().partition(|link| {
+                cfg.absolute_fs_path_policy != WarningPolicy::Ignore
+                    && is_absolute_fs_path(&link.href)
+            });
+
+        // links are checked after applying any rewrites and, on a book served
+        // from `/docs/`, links like `/docs/intro.md` are checked as
+        // `/intro.md`. Either way they're reported using the original href
+        let site_path = cfg.site_path();
+        let mut original_hrefs = HashMap::new();
+        let links: Vec<Link> = links
+            .into_iter()
+            .map(|mut link| {
+                let rewritten = match cfg.rewrite_link(&link.href) {
+                    Cow::Owned(href) => {
+                        log::debug!(
+                            "Rewrote \"{}\" as \"{}\"",
+                            link.href,
+                            href
+                        );
+                        Some(href)
+                    },
+                    Cow::Borrowed(_) => None,
+                };
+                let href = rewritten.as_deref().unwrap_or(&link.href);
+                let within_site = site_path
+                    .as_deref()
+                    .and_then(|site_path| strip_site_path(site_path, href))
+                    .map(String::from)
+                    .or(rewritten);
+
+                if let Some(href) = within_site {
+                    let original = std::mem::replace(&mut link.href, href);
+                    original_hrefs.insert((link.file, link.span), original);
+                }
+
+                link
+            })
+            .collect();
+
+        let mut got = self.run_checks(&links);
+        restore_hrefs(&mut got, &original_hrefs);
+
+        let mut outcome = merge_outcomes(got.outcomes, incomplete_links);
+        let (invalid_images, invalid_links) = outcome
+            .invalid_links
+            .into_iter()
+            .partition(|invalid| crate::links::is_image(&invalid.link, files));
+        outcome.invalid_links = invalid_links;
+        outcome.invalid_images = invalid_images;
+        suggest_other_extensions(&mut outcome.invalid_links, src_dir, files);
+        suggest_other_extensions(&mut outcome.invalid_images, src_dir, files);
+        outcome.redirects = got.redirects;
+        outcome.moved_links = got.moved_links;
+        outcome.small_bodies = got.small_bodies;
+        outcome.soft_404s = got.soft_404s;
+        outcome.timed_out = got.timed_out;
+        outcome.absolute_fs_paths = absolute_fs_paths;
+        outcome.absolute_links = find_absolute_links(
+            &outcome.valid_links,
+            site_path.as_deref(),
+            cfg,
+        );
+
+        if cfg.check_fragments {
+            outcome.ambiguous_fragments = find_ambiguous_fragments(
+                &outcome.valid_links,
+                files,
+                self.file_ids,
+            );
+        }
+
+        if cfg.warn_on_insecure_links {
+            outcome.insecure_links = find_insecure_links(&outcome, cfg);
+        }
+
+        if cfg.warn_on_case_mismatch {
+            outcome.case_mismatches = crate::case_sensitivity::find_mismatches(
+                &outcome.valid_links,
+                src_dir,
+                files,
+            );
+        }
+
+        outcome
+    }
+
+    /// Call `f` (e.g. to print diagnostics) without it being drawn over by the
+    /// progress bar.
+    pub(crate) fn suspend<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        self.ctx.progress.suspend(f)
+    }
+
+    /// Save what we learned in the cache, returning the slowest hosts and
+    /// files if checking links took longer than the time budget.
+    pub(crate) fn finish(self) -> Option<OverBudget> {
+        self.ctx.progress.finish();
+
+        let over_budget = self.ctx.cfg.time_budget.and_then(|budget| {
+            self.ctx.lock_timings().over_budget(
+                self.started.elapsed(),
+                Duration::from_secs(budget),
+            )
+        });
+
+        // move the cache out of ctx. We'd get a borrowing error if anything
+        // was using it
+        *self.cache = self
+            .ctx
+            .cache
+            .into_inner()
+            .expect("We statically know this isn't used");
+
+        over_budget
+    }
+
+    fn run_checks(&mut self, all_links: &[Link]) -> WebOutcomes {
+        let ctx = &self.ctx;
+        let cfg = ctx.cfg;
+        let files = ctx.files;
+        let src_dir = ctx.src_dir;
+        let local_files = &mut self.local_files;
+
+        // the link classifier (if there is one) gets the final say on each
+        // link, otherwise links in blockquotes may be ignored and the rest are
+        // checked
+        let mut classified = Outcomes::default();
+        let mut links = Vec::new();
+        let mut blockquotes: HashMap<FileId, Vec<Range<usize>>> =
+            HashMap::new();
+        let mut in_blockquote = |link: &Link| {
+            let start = link.span.start().to_usize();
+
+            cfg.ignore_in_blockquotes
+                && blockquotes
+                    .entry(link.file)
+                    .or_insert_with(|| {
+                        crate::links::blockquotes(files.source(link.file))
+                    })
+                    .iter()
+                    .any(|quote| quote.contains(&start))
+        };
+
+        for link in all_links {
+            match cfg.classify(link) {
+                Some(LinkClassification::Valid) => {
+                    classified.valid.push(link.clone())
+                },
+                Some(LinkClassification::Ignored) => {
+                    classified.ignored.push(link.clone())
+                },
+                None if in_blockquote(link) => {
+                    classified.ignored.push(link.clone())
+                },
+                Some(LinkClassification::Checked) | None => {
+                    links.push(link.clone())
+                },
+            }
+        }
+        ctx.progress
+            .checked(classified.valid.len() + classified.ignored.len());
+
+        // linkcheck doesn't look at links to somewhere else in the same
+        // chapter (e.g. `#installation`), so we check those against its
+        // headings
+        let same_page = match self.anchors {
+            Some(ref anchors) => {
+                let (same_page, others): (Vec<Link>, Vec<Link>) =
+                    links.into_iter().partition(|link| {
+                        link.href.starts_with('#')
+                            && anchors.contains_key(files.name(link.file))
+                            && !ctx.should_ignore(link)
+                    });
+                links = others;
+                ctx.progress.checked(same_page.len());
+                validate_same_page_links(same_page, anchors, files)
+            },
+            None => Outcomes::default(),
+        };
+
+        // linkcheck doesn't know what to do with mailto: and tel: links, so we
+        // check those ourselves
+        let (contact_links, links): (Vec<Link>, Vec<Link>) = links
+            .into_iter()
+            .partition(|link| crate::mailto::is_contact_link(&link.href));
+        // web links are checked separately so we can control how requests are
+        // made
+        let (web_links, links): (Vec<Link>, Vec<Link>) =
+            links.iter().cloned().partition(|link| {
+                !ctx.should_ignore(link)
+                    && crate::web::web_url(&link.href).is_some()
+            });
+        // linkcheck would try to fetch any other URL over the network, so
+        // links with a scheme we don't know how to check are reported instead
+        let (unknown, links): (Vec<Link>, Vec<Link>) =
+            links.into_iter().partition(|link| {
+                !ctx.should_ignore(link)
+                    && matches!(link.category(), Some(Category::Url(_)))
+            });
+        ctx.progress.checked(unknown.len());
+        // links to files which were found last time don't need to be looked
+        // for again, as long as their directory hasn't changed
+        let mut cached = Outcomes::default();
+        let mut unchecked = Vec::new();
+
+        for link in links {
+            let target = Some(&link)
+                .filter(|link| !ctx.should_ignore(link))
+                .and_then(|link| local_files.target(link, files));
+            let resolved = target.as_ref().and_then(|target| {
+                local_files.lookup(&ctx.lock_cache(), target)
+            });
+
+            match (target, resolved) {
+                (Some(target), Some(resolved)) => {
+                    ctx.lock_cache().record_local_hit();
+                    match (self.check_cached_file)(&resolved, target.fragment) {
+                        Ok(_) => cached.valid.push(link.clone()),
+                        Err(reason) => cached.invalid.push(InvalidLink {
+                            link: link.clone(),
+                            reason,
+                        }),
+                    }
+                    ctx.progress.checked(1);
+                },
+                _ => unchecked.push(link),
+            }
+        }
+        let (local_links, unchecked) = partition_local_links(unchecked, ctx);
+        let links = collate_links(&unchecked, src_dir, files);
+
+        let mut got = WebOutcomes {
+            outcomes: check_local_links(local_links, ctx, src_dir, files),
+            ..Default::default()
+        };
+        got.outcomes.unknown_category.extend(unknown);
+
+        let checks = async {
+            crate::web::validate(web_links, ctx, &mut got).await;
+            got.outcomes
+                .merge(crate::mailto::validate(contact_links, ctx.cfg));
+
+            for (current_dir, links) in links {
+                let count = links.len();
+                got.outcomes
+                    .merge(linkcheck::validate(&current_dir, links, ctx).await);
+                ctx.progress.checked(count);
+            }
+        };
+        // results are recorded as they come in, so anything checked before the
+        // deadline is kept when the remaining checks are cancelled
+        let deadline = self.deadline;
+        let finished = self.runtime.block_on(async {
+            match deadline {
+                Some(deadline) => {
+                    let deadline = tokio::time::Instant::from_std(deadline);
+                    tokio::time::timeout_at(deadline, checks).await.is_ok()
+                },
+                None => {
+                    checks.await;
+                    true
+                },
+            }
+        });
+        got.redirects
+            .sort_by_key(|redirect| (redirect.link.file, redirect.link.span));
+        got.moved_links
+            .sort_by_key(|redirect| (redirect.link.file, redirect.link.span));
+        got.small_bodies
+            .sort_by_key(|small| (small.link.file, small.link.span));
+        got.soft_404s
+            .sort_by_key(|soft_404| (soft_404.link.file, soft_404.link.span));
+
+        for link in &got.outcomes.valid {
+            if let Some(target) = local_files.target(link, files) {
+                let resolved = ctx
+                    .filesystem_options
+                    .canonicalize(&target.current_dir, Path::new(target.path));
+
+                if let Ok(resolved) = resolved {
+                    local_files.remember(
+                        &mut ctx.lock_cache(),
+                        &target,
+                        resolved,
+                    );
+                }
+            }
+        }
+        got.outcomes.merge(cached);
+        got.outcomes.merge(classified);
+        got.outcomes.merge(same_page);
+
+        if !finished {
+            got.timed_out = unchecked_links(all_links, &got.outcomes);
+        }
+
+        got
+    }
+}
 
-    let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
-    let got = runtime.block_on(async {
-        let mut outcomes = Outcomes::default();
+/// Check links like `#installation` against the anchors in the chapter they
+/// were written in.
+fn validate_same_page_links(
+    links: Vec<Link>,
+    anchors: &HashMap<OsString, Vec<String>>,
+    files: &Files<String>,
+) -> Outcomes {
+    let mut outcomes = Outcomes::default();
+
+    for link in links {
+        let chapter = files.name(link.file);
+        let chapter_anchors = anchors.get(chapter).into_iter().flatten();
+        let fragment = &link.href[1..];
 
-        for (current_dir, links) in links {
-            outcomes
-                .merge(linkcheck::validate(&current_dir, links, &ctx).await);
+        if fragment.is_empty()
+            || chapter_anchors.clone().any(|anchor| anchor == fragment)
+        {
+            outcomes.valid.push(link);
+            continue;
         }
 
-        outcomes
-    });
+        let missing = MissingFragment {
+            path: PathBuf::from(chapter),
+            fragment: fragment.to_string(),
+            suggestion: crate::anchors::closest_anchor(
+                fragment,
+                chapter_anchors,
+            )
+            .map(String::from),
+        };
+        outcomes.invalid.push(InvalidLink {
+            link,
+            reason: Reason::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                missing,
+            )),
+        });
+    }
 
-    // move the cache out of ctx. We'd get a borrowing error if anything was
-    // using it
-    let updated_cache = ctx.cache;
+    outcomes
+}
+
+/// Find the links which don't have a result yet.
+fn unchecked_links(links: &[Link], outcomes: &Outcomes) -> Vec<Link> {
+    let checked: HashSet<(FileId, Span)> = outcomes
+        .valid
+        .iter()
+        .chain(outcomes.invalid.iter().map(|invalid| &invalid.link))
+        .chain(&outcomes.ignored)
+        .chain(&outcomes.unknown_category)
+        .map(|link| (link.file, link.span))
+        .collect();
 
-    *cache = updated_cache
-        .into_inner()
-        .expect("We statically know this isn't used");
-    got
+    links
+        .iter()
+        .filter(|link| !checked.contains(&(link.file, link.span)))
+        .cloned()
+        .collect()
 }
 
 fn ensure_included_in_book(
     src_dir: &Path,
     file_names: Vec<OsString>,
+    anchors: Option<HashMap<OsString, Vec<String>>>,
+    markdown_extensions: Vec<String>,
+    additional_summary_files: Vec<String>,
+    check_included_in_summary: bool,
+    not_in_summary_message: Option<String>,
 ) -> impl Fn(&Path, Option<&str>) -> Result<(), Reason> {
     let src_dir = src_dir.to_path_buf();
 
-    move |resolved_link, _| {
+    move |resolved_link, fragment| {
         let resolved_link = match resolved_link.strip_prefix(&src_dir) {
             Ok(path) => path,
             // Not part of the book.
             Err(_) => return Ok(()),
         };
-        let was_included_in_summary =
-            file_names.iter().any(|summary_path| {
-                let summary_path = Path::new(summary_path);
-                if summary_path.parent() != resolved_link.parent() {
-                    return false;
-                }
-                match (summary_path.file_name(), resolved_link.file_name()) {
-                    (a, b) if a == b => true,
-                    (Some(summary), Some(resolved)) => {
-                        // index preprocessor rewrites summary paths before we get to them.
-                        summary == Path::new("index.md") && resolved == Path::new("README.md")
-                    }
-                    _ => false,
-                }
-            });
-        let ext = resolved_link.extension();
-        let is_markdown = ext == Some(OsStr::new("md"));
+        let summary_path = file_names.iter().find(|summary_path| {
+            let summary_path = Path::new(summary_path);
+            if summary_path.parent() != resolved_link.parent() {
+                return false;
+            }
+            match (summary_path.file_name(), resolved_link.file_name()) {
+                (a, b) if a == b => true,
+                (Some(summary), Some(resolved)) => {
+                    // index preprocessor rewrites summary paths before we get
+                    // to them.
+                    summary == Path::new("index.md")
+                        && resolved == Path::new("README.md")
+                },
+                _ => false,
+            }
+        });
+        let is_markdown = match resolved_link.extension() {
+            Some(ext) => markdown_extensions
+                .iter()
+                .any(|markdown| OsStr::new(markdown) == ext),
+            None => false,
+        };
 
-        if was_included_in_summary || !is_markdown {
-            Ok(())
-        } else {
-            use std::io::{Error, ErrorKind};
+        use std::io::{Error, ErrorKind};
 
-            Err(Reason::Io(Error::new(
-                ErrorKind::Other,
-                NotInSummary {
-                    path: resolved_link.to_path_buf(),
-                },
-            )))
+        match (summary_path, fragment, &anchors) {
+            (None, _, _)
+                if check_included_in_summary
+                    && is_markdown
+                    && !crate::config::matches_any_glob(
+                        &additional_summary_files,
+                        resolved_link,
+                    ) =>
+            {
+                Err(Reason::Io(Error::new(
+                    ErrorKind::Other,
+                    NotInSummary {
+                        path: resolved_link.to_path_buf(),
+                        message: not_in_summary_message.clone(),
+                    },
+                )))
+            },
+            (Some(summary_path), Some(fragment), Some(anchors))
+                if !fragment.is_empty() =>
+            {
+                let anchors = anchors.get(summary_path);
+                let found = anchors
+                    .into_iter()
+                    .flatten()
+                    .any(|anchor| anchor == fragment);
+
+                if found {
+                    Ok(())
+                } else {
+                    let suggestion = crate::anchors::closest_anchor(
+                        fragment,
+                        anchors.into_iter().flatten(),
+                    );
+
+                    Err(Reason::Io(Error::new(
+                        ErrorKind::Other,
+                        MissingFragment {
+                            path: resolved_link.to_path_buf(),
+                            fragment: fragment.to_string(),
+                            suggestion: suggestion.map(String::from),
+                        },
+                    )))
+                }
+            },
+            _ => Ok(()),
         }
     }
 }
@@ -129,19 +628,92 @@ fn ensure_included_in_book(
 pub struct NotInSummary {
     /// The file's full path.
     pub path: PathBuf,
+    /// A custom message to show instead of the usual one (see
+    /// [`Config::not_in_summary_message`]).
+    pub message: Option<String>,
 }
 
 impl Display for NotInSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.message {
+            Some(ref message) => f.write_str(
+                &message.replace("{path}", &self.path.display().to_string()),
+            ),
+            None => write!(
+                f,
+                "It looks like \"{}\" wasn't included in SUMMARY.md",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NotInSummary {}
+
+/// An error that is emitted when a link's fragment (e.g. `#installation`)
+/// doesn't match any of the anchors in the file it points to.
+#[derive(Debug)]
+pub struct MissingFragment {
+    /// The file being linked to.
+    pub path: PathBuf,
+    /// The fragment, without the leading `#`.
+    pub fragment: String,
+    /// The anchor the fragment was probably meant to be (e.g. `my-heading`
+    /// for `#my_heading`), if any were close enough.
+    pub suggestion: Option<String>,
+}
+
+impl Display for MissingFragment {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "It looks like \"{}\" wasn't included in SUMMARY.md",
+            "There is no \"#{}\" anchor in \"{}\"",
+            self.fragment,
             self.path.display()
         )
     }
 }
 
-impl std::error::Error for NotInSummary {}
+impl std::error::Error for MissingFragment {}
+
+/// An error that is emitted when a link points to a file which doesn't exist,
+/// but there is a file with the same name and a different extension (e.g.
+/// `guide.markdown` instead of `guide.md`).
+#[derive(Debug)]
+pub struct WrongExtension {
+    /// The file being linked to.
+    pub path: PathBuf,
+    /// The name of the file which does exist.
+    pub suggestion: String,
+}
+
+impl Display for WrongExtension {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\"{}\" doesn't exist, but \"{}\" does",
+            self.path.display(),
+            self.suggestion
+        )
+    }
+}
+
+impl std::error::Error for WrongExtension {}
+
+/// A link whose fragment (e.g. `#setup`) is shared by several headings in
+/// the chapter it points to, so it will always go to the first one.
+#[derive(Debug, Clone)]
+pub struct AmbiguousFragment {
+    /// The original link.
+    pub link: Link,
+    /// The chapter being linked to, relative to the source directory.
+    pub path: PathBuf,
+    /// The fragment, without the leading `#`.
+    pub fragment: String,
+    /// The anchors `mdbook` generates for each of the headings (e.g.
+    /// `setup`, `setup-1`, `setup-2`).
+    pub candidates: Vec<String>,
+}
 
 fn collate_links<'a>(
     links: &'a [Link],
@@ -163,6 +735,85 @@ fn collate_links<'a>(
     links_by_directory.into_iter()
 }
 
+/// A link to a file on disk, split into its path and fragment.
+#[derive(Debug)]
+struct LocalLink {
+    link: Link,
+    path: PathBuf,
+    fragment: Option<String>,
+}
+
+/// Pull out the links to files on disk which need to be checked, leaving
+/// everything else (ignored links, unknown schemes, etc.) for
+/// [`linkcheck::validate()`].
+fn partition_local_links(
+    links: Vec<Link>,
+    ctx: &Context<'_>,
+) -> (Vec<LocalLink>, Vec<Link>) {
+    let mut local_links = Vec::new();
+    let mut others = Vec::new();
+
+    for link in links {
+        match link.category() {
+            Some(Category::FileSystem { path, fragment })
+                if !ctx.should_ignore(&link) =>
+            {
+                local_links.push(LocalLink {
+                    link,
+                    path,
+                    fragment,
+                })
+            },
+            _ => others.push(link),
+        }
+    }
+
+    (local_links, others)
+}
+
+/// Look for the files being linked to. Each lookup is independent, so large
+/// books are checked in parallel.
+fn check_local_links(
+    links: Vec<LocalLink>,
+    ctx: &Context<'_>,
+    src_dir: &Path,
+    files: &Files<String>,
+) -> Outcomes {
+    let results: Vec<(Link, Result<(), Reason>)> = links
+        .into_par_iter()
+        .map(
+            |LocalLink {
+                 link,
+                 path,
+                 fragment,
+             }| {
+                let mut current_dir = src_dir.join(files.name(link.file));
+                current_dir.pop();
+                let result = linkcheck::validation::check_filesystem(
+                    &current_dir,
+                    &path,
+                    fragment.as_deref(),
+                    ctx,
+                );
+                ctx.progress.checked(1);
+
+                (link, result)
+            },
+        )
+        .collect();
+
+    let mut outcomes = Outcomes::default();
+
+    for (link, result) in results {
+        match result {
+            Ok(_) => outcomes.valid.push(link),
+            Err(reason) => outcomes.invalid.push(InvalidLink { link, reason }),
+        }
+    }
+
+    outcomes
+}
+
 fn merge_outcomes(
     outcomes: Outcomes,
     incomplete_links: Vec<IncompleteLink>,
@@ -187,13 +838,57 @@ fn merge_outcomes(
 
     ValidationOutcome {
         invalid_links: sorted(outcomes.invalid, |l| &l.link),
+        invalid_images: Vec::new(),
         ignored: sorted_link(outcomes.ignored),
         valid_links: sorted_link(outcomes.valid),
         unknown_category: sorted_link(outcomes.unknown_category),
         incomplete_links,
+        duplicate_definitions: Vec::new(),
+        redirects: Vec::new(),
+        moved_links: Vec::new(),
+        small_bodies: Vec::new(),
+        soft_404s: Vec::new(),
+        ambiguous_fragments: Vec::new(),
+        case_mismatches: Vec::new(),
+        insecure_links: Vec::new(),
+        over_budget: None,
+        timed_out: Vec::new(),
+        absolute_links: Vec::new(),
+        absolute_fs_paths: Vec::new(),
+        summary: None,
+        resolved_paths: Vec::new(),
     }
 }
 
+/// Find the links which are absolute (e.g. `/index.md`), skipping those which
+/// are intentional because they match one of the
+/// [`Config::allow_absolute_patterns`] or start with the book's `site_path`.
+fn find_absolute_links(
+    valid_links: &[Link],
+    site_path: Option<&str>,
+    cfg: &Config,
+) -> Vec<Link> {
+    valid_links
+        .iter()
+        .filter(|link| {
+            link.href.starts_with("/")
+                && !crate::web::is_protocol_relative(&link.href)
+                && site_path
+                    .and_then(|path| strip_site_path(path, &link.href))
+                    .is_none()
+                && !cfg
+                    .allow_absolute_patterns
+                    .iter()
+                    .any(|pattern| pattern.is_match(&link.href))
+        })
+        .cloned()
+        .collect()
+}
+
+/// The message used for every [`IncompleteLink`], so reports can tell which
+/// problems they are.
+pub(crate) const INCOMPLETE_LINK: &str = "Potential incomplete link";
+
 /// Try to validate the provided [`Link`]s.
 pub fn validate(
     links: &[Link],
@@ -204,8 +899,241 @@ pub fn validate(
     file_ids: &[FileId],
     incomplete_links: Vec<IncompleteLink>,
 ) -> Result<ValidationOutcome, Error> {
-    let got = lc_validate(links, cfg, src_dir, cache, files, file_ids);
-    Ok(merge_outcomes(got, incomplete_links))
+    let runtime = runtime()?;
+    let mut validator = Validator::new(
+        cfg,
+        src_dir,
+        cache,
+        files,
+        file_ids,
+        &runtime,
+        links.len(),
+        false,
+        None,
+    );
+    let mut outcome = validator.check(links, incomplete_links);
+    outcome.over_budget = validator.finish();
+
+    Ok(outcome)
+}
+
+/// Start the runtime that links are checked on.
+pub(crate) fn runtime() -> Result<Runtime, Error> {
+    Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Unable to start the async runtime")
+}
+
+/// Look for a file with the same name but a different extension (e.g.
+/// `guide.markdown` for `guide.md`) next to each missing file. The directory
+/// is only read for links which are already known to be broken.
+fn suggest_other_extensions(
+    invalid_links: &mut [InvalidLink],
+    src_dir: &Path,
+    files: &Files<String>,
+) {
+    for invalid in invalid_links
+        .iter_mut()
+        .filter(|invalid| invalid.reason.file_not_found())
+    {
+        let path = match resolve_in_book(&invalid.link, files) {
+            Some((path, _)) => src_dir.join(path),
+            None => continue,
+        };
+
+        if let Some(suggestion) = sibling_with_other_extension(&path) {
+            invalid.reason = Reason::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                WrongExtension { path, suggestion },
+            ));
+        }
+    }
+}
+
+fn sibling_with_other_extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?;
+    let stem = path.file_stem()?;
+
+    let mut candidates: Vec<String> = path
+        .parent()?
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|candidate| {
+            candidate.file_stem() == Some(stem)
+                && candidate.file_name() != Some(name)
+                && candidate.is_file()
+        })
+        .filter_map(|candidate| {
+            candidate
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .collect();
+    candidates.sort();
+
+    candidates.into_iter().next()
+}
+
+/// Work out the full path each broken link to a local file was looked for
+/// at, so users can see where a link actually points.
+pub(crate) fn resolved_paths(
+    outcome: &ValidationOutcome,
+    src_dir: &Path,
+    cfg: &Config,
+    files: &Files<String>,
+) -> Vec<(Link, PathBuf)> {
+    let local_files = LocalFiles::new(src_dir, cfg);
+
+    outcome
+        .invalid_links
+        .iter()
+        .chain(&outcome.invalid_images)
+        .filter_map(|invalid| {
+            let target = local_files.target(&invalid.link, files)?;
+            Some((invalid.link.clone(), normalize(&target.key)))
+        })
+        .collect()
+}
+
+/// Remove any `.` and `..` components from a path without touching the file
+/// system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            },
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+/// Find the `http://` links which were valid, or which weren't checked
+/// because web links aren't being followed.
+fn find_insecure_links(outcome: &ValidationOutcome, cfg: &Config) -> Vec<Link> {
+    let mut insecure: Vec<Link> = outcome
+        .valid_links
+        .iter()
+        .chain(&outcome.ignored)
+        .filter(|link| !cfg.should_skip(&link.href))
+        .filter(|link| {
+            crate::web::web_url(&link.href)
+                .map(|url| url.scheme() == "http")
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect();
+    insecure.sort_by_key(|link| (link.file, link.span));
+
+    insecure
+}
+
+/// Find links to a heading whose anchor is shared with other headings in the
+/// same chapter.
+fn find_ambiguous_fragments(
+    links: &[Link],
+    files: &Files<String>,
+    file_ids: &[FileId],
+) -> Vec<AmbiguousFragment> {
+    let ambiguous: HashMap<PathBuf, HashMap<String, Vec<String>>> = file_ids
+        .iter()
+        .map(|id| {
+            let anchors = crate::anchors::ambiguous_anchors(files.source(*id));
+            (PathBuf::from(files.name(*id)), anchors)
+        })
+        .filter(|(_, anchors)| !anchors.is_empty())
+        .collect();
+
+    if ambiguous.is_empty() {
+        return Vec::new();
+    }
+
+    links
+        .iter()
+        .filter_map(|link| {
+            let (path, fragment) = linked_chapter(link, files)?;
+            let candidates = ambiguous.get(&path)?.get(fragment)?;
+
+            Some(AmbiguousFragment {
+                link: link.clone(),
+                path,
+                fragment: fragment.to_string(),
+                candidates: candidates.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Work out which chapter (relative to the source directory) and fragment a
+/// link points to.
+fn linked_chapter<'a>(
+    link: &'a Link,
+    files: &Files<String>,
+) -> Option<(PathBuf, &'a str)> {
+    let (mut resolved, fragment) = resolve_in_book(link, files)?;
+    let fragment = fragment?;
+
+    if resolved.extension() == Some(OsStr::new("html")) {
+        resolved.set_extension("md");
+    }
+
+    Some((resolved, fragment))
+}
+
+/// Resolve a link's path relative to the source directory, also returning
+/// its fragment (if any). Web links and links which leave the source
+/// directory are skipped.
+pub(crate) fn resolve_in_book<'a>(
+    link: &'a Link,
+    files: &Files<String>,
+) -> Option<(PathBuf, Option<&'a str>)> {
+    if link.href.parse::<reqwest::Url>().is_ok()
+        || crate::web::is_protocol_relative(&link.href)
+    {
+        return None;
+    }
+
+    let (path, fragment) = match link.href.find('#') {
+        Some(ix) => (&link.href[..ix], Some(&link.href[ix + 1..])),
+        None => (link.href.as_str(), None),
+    };
+    let current_file = Path::new(files.name(link.file));
+
+    let joined = if path.is_empty() {
+        current_file.to_path_buf()
+    } else if let Some(relative_to_root) = path.strip_prefix('/') {
+        PathBuf::from(relative_to_root)
+    } else {
+        current_file
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(path)
+    };
+
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return None;
+                }
+            },
+            other => resolved.push(other),
+        }
+    }
+
+    Some((resolved, fragment))
 }
 
 /// The outcome of validating a set of links.
@@ -215,6 +1143,8 @@ pub struct ValidationOutcome {
     pub valid_links: Vec<Link>,
     /// Links where validation failed.
     pub invalid_links: Vec<InvalidLink>,
+    /// Images (e.g. `![diagram](./diagram.png)`) where validation failed.
+    pub invalid_images: Vec<InvalidLink>,
     /// Links which have been ignored (e.g. due to
     /// [`Config::follow_web_links`]).
     pub ignored: Vec<Link>,
@@ -222,81 +1152,590 @@ pub struct ValidationOutcome {
     pub unknown_category: Vec<Link>,
     /// Potentially incomplete links.
     pub incomplete_links: Vec<IncompleteLink>,
+    /// Link reference definitions whose label was already defined earlier in
+    /// the same file.
+    pub duplicate_definitions: Vec<DuplicateDefinition>,
+    /// Web links which were redirected somewhere else (only populated when
+    /// [`Config::redirect_policy`] is [`RedirectPolicy::Warn`]).
+    ///
+    /// [`RedirectPolicy::Warn`]: crate::RedirectPolicy::Warn
+    pub redirects: Vec<Redirect>,
+    /// Web links which still work, but were redirected somewhere else (only
+    /// populated when [`Config::report_redirects`] is set and the redirects
+    /// aren't already being warned about).
+    pub moved_links: Vec<Redirect>,
+    /// Web links whose pages were smaller than [`Config::min_body_bytes`].
+    pub small_bodies: Vec<SmallBody>,
+    /// Web links whose pages matched one of the
+    /// [`Config::soft_404_patterns`].
+    pub soft_404s: Vec<Soft404>,
+    /// Links to a heading whose anchor is shared by other headings in the
+    /// same chapter (only populated when [`Config::check_fragments`] is set).
+    pub ambiguous_fragments: Vec<AmbiguousFragment>,
+    /// Links whose case doesn't match the file on disk (only populated when
+    /// [`Config::warn_on_case_mismatch`] is set).
+    pub case_mismatches: Vec<CaseMismatch>,
+    /// Links to `http://` URLs (only populated when
+    /// [`Config::warn_on_insecure_links`] is set).
+    pub insecure_links: Vec<Link>,
+    /// Set when checking links took longer than [`Config::time_budget`].
+    pub over_budget: Option<OverBudget>,
+    /// Links which were skipped because the run took longer than its
+    /// maximum duration (see [`RunOptions::max_duration`]).
+    ///
+    /// [`RunOptions::max_duration`]: crate::RunOptions::max_duration
+    pub timed_out: Vec<Link>,
+    /// Valid links which are absolute (e.g. `/index.md`), and so won't work
+    /// when the book is viewed from the file system. Links matching
+    /// [`Config::allow_absolute_patterns`] or starting with the
+    /// [`Config::site_path()`] are intentional, so they aren't included.
+    pub absolute_links: Vec<Link>,
+    /// Links to absolute paths on the author's computer (e.g.
+    /// `/home/user/notes.md`).
+    pub absolute_fs_paths: Vec<Link>,
+    /// The book's `SUMMARY.md`, if it was loaded into [`Files`] (see
+    /// [`load_summary_into_memory()`]). Used to suggest where chapters which
+    /// aren't part of the book could be added.
+    ///
+    /// [`load_summary_into_memory()`]: crate::load_summary_into_memory
+    pub summary: Option<FileId>,
+    /// Where each broken link to a local file was looked for (only populated
+    /// when [`RunOptions::explain`] is set).
+    ///
+    /// [`RunOptions::explain`]: crate::RunOptions::explain
+    pub resolved_paths: Vec<(Link, PathBuf)>,
 }
 
 impl ValidationOutcome {
+    /// Add the results from checking another batch of links from the same
+    /// book.
+    pub(crate) fn merge(&mut self, other: ValidationOutcome) {
+        let ValidationOutcome {
+            valid_links,
+            invalid_links,
+            invalid_images,
+            ignored,
+            unknown_category,
+            incomplete_links,
+            duplicate_definitions,
+            redirects,
+            moved_links,
+            small_bodies,
+            soft_404s,
+            ambiguous_fragments,
+            case_mismatches,
+            insecure_links,
+            over_budget,
+            timed_out,
+            absolute_links,
+            absolute_fs_paths,
+            summary,
+            resolved_paths,
+        } = other;
+
+        self.valid_links.extend(valid_links);
+        self.invalid_links.extend(invalid_links);
+        self.invalid_images.extend(invalid_images);
+        self.ignored.extend(ignored);
+        self.unknown_category.extend(unknown_category);
+        self.incomplete_links.extend(incomplete_links);
+        self.duplicate_definitions.extend(duplicate_definitions);
+        self.redirects.extend(redirects);
+        self.moved_links.extend(moved_links);
+        self.small_bodies.extend(small_bodies);
+        self.soft_404s.extend(soft_404s);
+        self.ambiguous_fragments.extend(ambiguous_fragments);
+        self.case_mismatches.extend(case_mismatches);
+        self.insecure_links.extend(insecure_links);
+        self.over_budget = self.over_budget.take().or(over_budget);
+        self.timed_out.extend(timed_out);
+        self.absolute_links.extend(absolute_links);
+        self.absolute_fs_paths.extend(absolute_fs_paths);
+        self.summary = self.summary.or(summary);
+        self.resolved_paths.extend(resolved_paths);
+    }
+
+    /// Count how many links ended up in each category.
+    ///
+    /// Links to absolute paths on the author's computer count as broken, and
+    /// links we don't know how to handle or didn't get around to checking
+    /// count as ignored.
+    pub fn counts(&self) -> LinkCounts {
+        let valid = self.valid_links.len();
+        let ignored = self.ignored.len()
+            + self.unknown_category.len()
+            + self.timed_out.len();
+        let broken = self.invalid_links.len()
+            + self.invalid_images.len()
+            + self.absolute_fs_paths.len();
+
+        LinkCounts {
+            total: valid + ignored + broken,
+            valid,
+            ignored,
+            broken,
+            incomplete: self.incomplete_links.len(),
+        }
+    }
+
     /// Generate a list of [`Diagnostic`] messages from this
-    /// [`ValidationOutcome`].
+    /// [`ValidationOutcome`], using the warning policies from `cfg`.
     pub fn generate_diagnostics(
         &self,
         files: &Files<String>,
-        warning_policy: WarningPolicy,
+        cfg: &Config,
     ) -> Vec<Diagnostic<FileId>> {
         let mut diags = Vec::new();
 
-        self.add_invalid_link_diagnostics(&mut diags);
-        self.add_incomplete_link_diagnostics(warning_policy, &mut diags);
-        self.warn_on_absolute_links(warning_policy, &mut diags, files);
+        let warning_policy = cfg.warning_policy;
+        let overrides = &cfg.warning_policy_overrides;
+        let severity = |policy: Option<WarningPolicy>| {
+            policy.unwrap_or(warning_policy).severity()
+        };
+
+        self.add_invalid_link_diagnostics(
+            overrides.not_in_summary.unwrap_or(WarningPolicy::Error),
+            cfg.deduplicate_reports,
+            &mut diags,
+            files,
+        );
+        if let Some(severity) = overrides
+            .broken_image
+            .unwrap_or(WarningPolicy::Error)
+            .severity()
+        {
+            self.add_invalid_image_diagnostics(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.incomplete_link) {
+            self.add_incomplete_link_diagnostics(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.duplicate_definition) {
+            self.warn_on_duplicate_definitions(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.ambiguous_fragment) {
+            self.warn_on_ambiguous_fragments(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.absolute_link) {
+            self.warn_on_absolute_links(severity, &mut diags, files);
+        }
+        if let Some(severity) = severity(overrides.case_mismatch) {
+            self.warn_on_case_mismatches(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.insecure_link) {
+            self.warn_on_insecure_links(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.redirect) {
+            self.warn_on_redirects(severity, &mut diags);
+        }
+        self.add_moved_link_notes(&mut diags);
+        if let Some(severity) = severity(overrides.small_body) {
+            self.warn_on_small_bodies(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.soft_404) {
+            self.warn_on_soft_404s(severity, &mut diags);
+        }
+        if let Some(severity) = severity(overrides.over_budget) {
+            self.warn_when_over_budget(severity, &mut diags, files);
+        }
+        if let Some(severity) = cfg.absolute_fs_path_policy.severity() {
+            self.add_absolute_fs_path_diagnostics(severity, &mut diags);
+        }
+        self.warn_on_timed_out_links(&mut diags);
+        if let Some(severity) = overrides
+            .unknown_link
+            .unwrap_or(WarningPolicy::Ignore)
+            .severity()
+        {
+            self.warn_on_unknown_links(severity, &mut diags);
+        }
 
         diags
     }
 
-    fn add_incomplete_link_diagnostics(
+    fn warn_on_redirects(
         &self,
-        warning_policy: WarningPolicy,
+        severity: Severity,
         diags: &mut Vec<Diagnostic<FileId>>,
     ) {
-        let severity = match warning_policy {
-            WarningPolicy::Error => Severity::Error,
-            WarningPolicy::Warn => Severity::Warning,
-            WarningPolicy::Ignore => return,
-        };
+        for Redirect { link, final_url } in &self.redirects {
+            let msg = format!("\"{}\" redirects to another page", link.href);
+            let note =
+                format!("hint: update the link to point to \"{}\"", final_url);
+
+            let diag = Diagnostic::new(severity)
+                .with_message(msg)
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Redirected")])
+                .with_notes(vec![note]);
+            diags.push(diag);
+        }
+    }
+
+    /// Links which were redirected still work, so this is only ever a note.
+    fn add_moved_link_notes(&self, diags: &mut Vec<Diagnostic<FileId>>) {
+        for Redirect { link, final_url } in &self.moved_links {
+            let diag = Diagnostic::note()
+                .with_message(format!(
+                    "\"{}\" redirects to \"{}\"",
+                    link.href, final_url
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Redirected")])
+                .with_notes(vec![format!(
+                    "hint: update the link to point to \"{}\"",
+                    final_url
+                )]);
+            diags.push(diag);
+        }
+    }
+
+    fn warn_on_small_bodies(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for SmallBody { link, body_bytes } in &self.small_bodies {
+            let msg = format!(
+                "\"{}\" responded with a suspiciously small page",
+                link.href
+            );
+            let label = Label::primary(link.file, link.span)
+                .with_message(format!("Only {} bytes", body_bytes));
+            let note = String::from(
+                "hint: placeholder pages and \"soft 404s\" often return \
+                 200 OK with an empty body",
+            );
+
+            let diag = Diagnostic::new(severity)
+                .with_message(msg)
+                .with_labels(vec![label])
+                .with_notes(vec![note]);
+            diags.push(diag);
+        }
+    }
+
+    fn warn_on_soft_404s(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for Soft404 { link, pattern } in &self.soft_404s {
+            let msg = format!("\"{}\" might be a missing page", link.href);
+            let label = Label::primary(link.file, link.span)
+                .with_message(format!("The page matched \"{}\"", pattern));
+            let note = String::from(
+                "hint: some sites respond with 200 OK and a \"Page not \
+                 found\" page instead of a 404 (a \"soft 404\")",
+            );
+
+            let diag = Diagnostic::new(severity)
+                .with_message(msg)
+                .with_labels(vec![label])
+                .with_notes(vec![note]);
+            diags.push(diag);
+        }
+    }
+
+    fn warn_on_duplicate_definitions(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for duplicate in &self.duplicate_definitions {
+            let DuplicateDefinition {
+                ref reference,
+                file,
+                original,
+                duplicate,
+            } = *duplicate;
+
+            let diag = Diagnostic::new(severity)
+                .with_message(format!(
+                    "`[{}]` is defined more than once",
+                    reference
+                ))
+                .with_labels(vec![
+                    Label::primary(file, duplicate)
+                        .with_message("This definition is ignored"),
+                    Label::secondary(file, original)
+                        .with_message("because it was already defined here"),
+                ])
+                .with_notes(vec![String::from(
+                    "hint: only the first definition of a link reference is used",
+                )]);
+            diags.push(diag);
+        }
+    }
+
+    fn warn_on_case_mismatches(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for CaseMismatch { link, suggestion } in &self.case_mismatches {
+            let msg = format!(
+                "The case of \"{}\" doesn't match the file on disk",
+                link.href
+            );
+            let note = format!(
+                "hint: this only works on case-insensitive file systems (e.g. \
+                 Windows and macOS), so change the link to \"{}\"",
+                suggestion
+            );
+
+            let diag = Diagnostic::new(severity)
+                .with_message(msg)
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Case mismatch")])
+                .with_notes(vec![note]);
+            diags.push(diag);
+        }
+    }
 
+    fn warn_on_insecure_links(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for link in &self.insecure_links {
+            let msg = format!("\"{}\" doesn't use HTTPS", link.href);
+            let upgraded =
+                crate::web::web_url(&link.href).and_then(|mut url| {
+                    url.set_scheme("https").ok()?;
+                    Some(url)
+                });
+            let notes = upgraded
+                .map(|url| format!("hint: link to \"{}\" instead", url))
+                .into_iter()
+                .collect();
+
+            let diag = Diagnostic::new(severity)
+                .with_message(msg)
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Insecure link")])
+                .with_notes(notes);
+            diags.push(diag);
+        }
+    }
+
+    fn warn_on_unknown_links(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for link in &self.unknown_category {
+            let diag = Diagnostic::new(severity)
+                .with_message(format!("Unable to classify link: {}", link.href))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Unknown link")]);
+            diags.push(diag);
+        }
+    }
+
+    fn warn_on_ambiguous_fragments(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for ambiguous in &self.ambiguous_fragments {
+            let AmbiguousFragment {
+                ref link,
+                ref path,
+                ref fragment,
+                ref candidates,
+            } = *ambiguous;
+
+            let msg = format!(
+                "\"#{}\" matches {} headings in \"{}\"",
+                fragment,
+                candidates.len(),
+                path.display()
+            );
+            let others: Vec<String> = candidates
+                .iter()
+                .filter(|candidate| *candidate != fragment)
+                .map(|candidate| format!("\"#{}\"", candidate))
+                .collect();
+            let note = format!(
+                "hint: this always goes to the first one. mdbook gives the \
+                 others the anchors {}, so link to those instead",
+                others.join(", ")
+            );
+
+            let diag = Diagnostic::new(severity)
+                .with_message(msg)
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Ambiguous fragment")])
+                .with_notes(vec![note]);
+            diags.push(diag);
+        }
+    }
+
+    fn add_incomplete_link_diagnostics(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
         for incomplete in &self.incomplete_links {
             let IncompleteLink {
                 ref reference,
                 file,
                 span,
+                ref suggestion,
             } = incomplete;
 
             let msg =
                 format!("Did you forget to define a URL for `{0}`?", reference);
             let label = Label::primary(*file, *span).with_message(msg);
-            let note = format!(
+            let mut notes = Vec::new();
+            if let Some(suggestion) = suggestion {
+                notes.push(format!("hint: did you mean `[{}]`?", suggestion));
+            }
+            notes.push(format!(
                 "hint: declare the link's URL. For example: `[{}]: http://example.com/`",
                 reference
-            );
+            ));
+
+            let diag = Diagnostic::new(severity)
+                .with_message(INCOMPLETE_LINK)
+                .with_labels(vec![label])
+                .with_notes(notes);
+            diags.push(diag)
+        }
+    }
+
+    fn add_invalid_link_diagnostics(
+        &self,
+        not_in_summary_policy: WarningPolicy,
+        deduplicate: bool,
+        diags: &mut Vec<Diagnostic<FileId>>,
+        files: &Files<String>,
+    ) {
+        // where the diagnostic for each (href, message) pair ended up, when
+        // deduplicating
+        let mut reported: HashMap<(&str, String), usize> = HashMap::new();
+
+        for broken_link in &self.invalid_links {
+            let link = &broken_link.link;
+            let msg = most_specific_error_message(&broken_link);
+            let mut labels =
+                vec![Label::primary(link.file, link.span)
+                    .with_message(msg.clone())];
+            let mut notes = hints(broken_link);
+            notes.extend(self.resolved_path_note(link));
+            let mut severity = Severity::Error;
+
+            if let Some(missing) = not_in_summary(broken_link) {
+                severity = match not_in_summary_policy.severity() {
+                    Some(severity) => severity,
+                    None => continue,
+                };
+                let (label, note) =
+                    self.summary_suggestion(files, &missing.path);
+                labels.extend(label);
+                notes.push(note);
+            }
+
+            if deduplicate {
+                let key = (link.href.as_str(), msg.clone());
+
+                if let Some(&ix) = reported.get(&key) {
+                    let existing = &mut diags[ix];
+
+                    if existing.severity == severity {
+                        existing.labels.push(
+                            Label::secondary(link.file, link.span)
+                                .with_message("also used here"),
+                        );
+                        for note in notes {
+                            if !existing.notes.contains(&note) {
+                                existing.notes.push(note);
+                            }
+                        }
+                        continue;
+                    }
+                }
+
+                reported.insert(key, diags.len());
+            }
 
             let diag = Diagnostic::new(severity)
-                .with_message("Potential incomplete link")
-                .with_labels(vec![label])
-                .with_notes(vec![note]);
-            diags.push(diag)
+                .with_message(msg)
+                .with_labels(labels)
+                .with_notes(notes);
+            diags.push(diag);
         }
     }
 
-    fn add_invalid_link_diagnostics(
+    fn add_invalid_image_diagnostics(
         &self,
+        severity: Severity,
         diags: &mut Vec<Diagnostic<FileId>>,
     ) {
-        for broken_link in &self.invalid_links {
-            let link = &broken_link.link;
-            let msg = most_specific_error_message(&broken_link);
-            let diag = Diagnostic::error()
+        for broken_image in &self.invalid_images {
+            let link = &broken_image.link;
+            let msg = most_specific_error_message(broken_image);
+            let mut notes = hints(broken_image);
+            notes.extend(self.resolved_path_note(link));
+
+            let diag = Diagnostic::new(severity)
                 .with_message(msg.clone())
                 .with_labels(vec![
                     Label::primary(link.file, link.span).with_message(msg)
-                ]);
+                ])
+                .with_notes(notes);
             diags.push(diag);
         }
     }
 
+    fn resolved_path_note(&self, link: &Link) -> Option<String> {
+        self.resolved_paths
+            .iter()
+            .find(|(resolved, _)| resolved == link)
+            .map(|(_, path)| {
+                format!("note: the link resolved to \"{}\"", path.display())
+            })
+    }
+
+    /// Suggest where a chapter could be added to `SUMMARY.md`.
+    fn summary_suggestion(
+        &self,
+        files: &Files<String>,
+        path: &Path,
+    ) -> (Option<Label<FileId>>, String) {
+        let related = self.summary.and_then(|summary| {
+            related_summary_entry(files.source(summary), path)
+                .map(|(range, entry)| (summary, range, entry))
+        });
+
+        match related {
+            Some((summary, range, entry)) => (
+                Some(
+                    Label::secondary(summary, range)
+                        .with_message("a related chapter"),
+                ),
+                format!(
+                    "hint: add \"{}\" to SUMMARY.md, perhaps next to \"{}\"",
+                    path.display(),
+                    entry
+                ),
+            ),
+            None => (
+                None,
+                format!(
+                    "hint: add \"{}\" to SUMMARY.md so it becomes part of the book",
+                    path.display()
+                ),
+            ),
+        }
+    }
+
     /// As shown in https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues/33
     /// absolute links are actually a bit of a foot gun when the document is
     /// being read directly from the filesystem.
     fn warn_on_absolute_links(
         &self,
-        warning_policy: WarningPolicy,
+        severity: Severity,
         diags: &mut Vec<Diagnostic<FileId>>,
         files: &Files<String>,
     ) {
@@ -310,28 +1749,17 @@ This warning helps avoid the situation where everything will seem to work
 fine when viewed using a web server (e.g. GitHub Pages or `mdbook serve`),
 but users viewing the book from the file system may encounter broken links.
 
-To ignore this warning, you can edit `book.toml` and set the warning policy to
-"ignore".
+To ignore this warning, you can edit `book.toml` and set the warning policy for
+absolute links to "ignore".
 
-    [output.linkcheck]
-    warning-policy = "ignore"
+    [output.linkcheck.warning-policy-overrides]
+    absolute-link = "ignore"
 
 For more details, see https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues/33
 "#;
-        let severity = match warning_policy {
-            WarningPolicy::Error => Severity::Error,
-            WarningPolicy::Warn => Severity::Warning,
-            WarningPolicy::Ignore => return,
-        };
-
-        let absolute_links = self
-            .valid_links
-            .iter()
-            .filter(|link| link.href.starts_with("/"));
-
         let mut reasoning_emitted = false;
 
-        for link in absolute_links {
+        for link in &self.absolute_links {
             let mut notes = Vec::new();
 
             if !reasoning_emitted {
@@ -357,6 +1785,79 @@ For more details, see https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues
             diags.push(diag);
         }
     }
+
+    fn add_absolute_fs_path_diagnostics(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+    ) {
+        for link in &self.absolute_fs_paths {
+            let msg = format!(
+                "\"{}\" is an absolute path on the author's computer",
+                link.href
+            );
+            let diag = Diagnostic::new(severity)
+                .with_message(msg.clone())
+                .with_labels(vec![
+                    Label::primary(link.file, link.span).with_message(msg)
+                ])
+                .with_notes(vec![String::from(
+                    "hint: readers won't have this file, link to something inside the book with a relative path instead",
+                )]);
+            diags.push(diag);
+        }
+    }
+
+    /// Links which were skipped because of a timeout aren't broken, so they
+    /// are always warnings. [`run()`] uses a separate exit status instead.
+    ///
+    /// [`run()`]: crate::run
+    fn warn_on_timed_out_links(&self, diags: &mut Vec<Diagnostic<FileId>>) {
+        for link in &self.timed_out {
+            let diag = Diagnostic::warning()
+                .with_message(format!(
+                    "\"{}\" was skipped due to timeout",
+                    link.href
+                ))
+                .with_labels(vec![Label::primary(link.file, link.span)
+                    .with_message("Not checked")]);
+            diags.push(diag);
+        }
+    }
+
+    fn warn_when_over_budget(
+        &self,
+        severity: Severity,
+        diags: &mut Vec<Diagnostic<FileId>>,
+        files: &Files<String>,
+    ) {
+        let over_budget = match self.over_budget {
+            Some(ref over_budget) => over_budget,
+            None => return,
+        };
+
+        let mut slowest_hosts = String::from("Slowest hosts:");
+        for (host, time) in &over_budget.slowest_hosts {
+            slowest_hosts.push_str(&format!("\n  {}: {:.1?}", host, time));
+        }
+
+        let mut slowest_files = String::from("Slowest files:");
+        for (file, time) in &over_budget.slowest_files {
+            slowest_files.push_str(&format!(
+                "\n  {}: {:.1?}",
+                files.name(*file).to_string_lossy(),
+                time
+            ));
+        }
+
+        let diag = Diagnostic::new(severity)
+            .with_message(format!(
+                "Checking links took {:.1?}, which is over the {:?} time budget",
+                over_budget.elapsed, over_budget.budget
+            ))
+            .with_notes(vec![slowest_hosts, slowest_files]);
+        diags.push(diag);
+    }
 }
 
 // Path diffing, copied from https://crates.io/crates/pathdiff with some tweaks
@@ -416,9 +1917,194 @@ where
     Some(path.display().to_string().replace('\\', "/"))
 }
 
-fn most_specific_error_message(link: &InvalidLink) -> String {
+/// Does this link point to an absolute path on the author's machine (e.g.
+/// `/home/user/notes.md` or `C:\Users\user\notes.md`)?
+///
+/// Links starting with `/` are normally relative to the book's root, so only
+/// directories which only make sense on a real filesystem are detected.
+/// If `href` starts with the path the book is served from (see
+/// [`Config::site_path()`]), get the same link relative to the root of the
+/// book (e.g. `/docs/intro.md` becomes `/intro.md`).
+fn strip_site_path<'a>(site_path: &str, href: &'a str) -> Option<&'a str> {
+    // "//example.com/..." is a protocol-relative URL, not a path
+    if href.starts_with(site_path) && !href.starts_with("//") {
+        Some(&href[site_path.len() - 1..])
+    } else {
+        None
+    }
+}
+
+fn restore_hrefs(
+    got: &mut WebOutcomes,
+    original_hrefs: &HashMap<(FileId, Span), String>,
+) {
+    if original_hrefs.is_empty() {
+        return;
+    }
+
+    let outcomes = &mut got.outcomes;
+    let links = outcomes
+        .valid
+        .iter_mut()
+        .chain(outcomes.invalid.iter_mut().map(|invalid| &mut invalid.link))
+        .chain(outcomes.ignored.iter_mut())
+        .chain(outcomes.unknown_category.iter_mut())
+        .chain(got.timed_out.iter_mut());
+
+    for link in links {
+        if let Some(original) = original_hrefs.get(&(link.file, link.span)) {
+            link.href = original.clone();
+        }
+    }
+}
+
+fn is_absolute_fs_path(href: &str) -> bool {
+    const UNIX_ROOTS: &[&str] = &[
+        "/home/", "/Users/", "/root/", "/tmp/", "/var/", "/etc/", "/usr/",
+        "/opt/", "/mnt/", "/media/",
+    ];
+
+    let bytes = href.as_bytes();
+    let windows_drive = bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes[2] == b'\\' || bytes[2] == b'/');
+    let unc_path = href.starts_with("\\\\");
+
+    windows_drive
+        || unc_path
+        || UNIX_ROOTS.iter().any(|root| href.starts_with(root))
+}
+
+/// Extra notes which may help the user fix a broken link.
+fn hints(link: &InvalidLink) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    if let Reason::Web(ref web) = link.reason {
+        let requested = crate::web::web_url(&link.link.href);
+
+        if let Some(url) = web.url() {
+            if requested.as_ref() != Some(url) {
+                notes.push(format!(
+                    "note: the link was redirected to \"{}\"",
+                    url
+                ));
+            }
+        }
+    }
+
+    let missing_fragment = match link.reason {
+        Reason::Io(ref io) => io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<MissingFragment>()),
+        _ => None,
+    };
+
+    if let Some(MissingFragment {
+        fragment,
+        suggestion: Some(suggestion),
+        ..
+    }) = missing_fragment
+    {
+        notes.push(format!(
+            "No heading \"{}\"; did you mean \"{}\"?",
+            fragment, suggestion
+        ));
+    }
+
+    match missing_fragment {
+        Some(missing)
+            if crate::anchors::looks_like_section_number(&missing.fragment) =>
+        {
+            notes.push(String::from(
+                "hint: mdbook doesn't generate anchors from section numbers. \
+                 A heading's anchor comes from its text (e.g. \
+                 \"## Getting Started\" becomes \"#getting-started\"), so \
+                 link to that instead",
+            ))
+        },
+        _ => {},
+    }
+
+    notes
+}
+
+/// Did this link fail because it points to a file that isn't in
+/// `SUMMARY.md`?
+pub(crate) fn is_not_in_summary(link: &InvalidLink) -> bool {
+    not_in_summary(link).is_some()
+}
+
+fn not_in_summary(link: &InvalidLink) -> Option<&NotInSummary> {
+    match link.reason {
+        Reason::Io(ref io) => io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<NotInSummary>()),
+        _ => None,
+    }
+}
+
+fn wrong_extension(link: &InvalidLink) -> Option<&WrongExtension> {
+    match link.reason {
+        Reason::Io(ref io) => io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<WrongExtension>()),
+        _ => None,
+    }
+}
+
+/// Find the entry in `SUMMARY.md` which looks most closely related to `path`
+/// (i.e. the last chapter from the same directory, or failing that, the
+/// closest parent directory).
+fn related_summary_entry(
+    summary: &str,
+    path: &Path,
+) -> Option<(Range<usize>, String)> {
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut best: Option<(usize, Range<usize>, String)> = None;
+
+    for (event, range) in Parser::new(summary).into_offset_iter() {
+        let entry = match event {
+            Event::Start(Tag::Link(_, ref dest, _)) => {
+                dest.trim_start_matches("./").to_string()
+            },
+            _ => continue,
+        };
+        let entry_parent =
+            Path::new(&entry).parent().unwrap_or_else(|| Path::new(""));
+
+        let common_directories = parent
+            .components()
+            .zip(entry_parent.components())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let score = if entry_parent == parent {
+            common_directories + 1
+        } else {
+            common_directories
+        };
+
+        let is_better = match best {
+            Some((best_score, _, _)) => score >= best_score,
+            None => score > 0,
+        };
+        if is_better {
+            best = Some((score, range, entry));
+        }
+    }
+
+    best.map(|(_, range, entry)| (range, entry))
+}
+
+pub(crate) fn most_specific_error_message(link: &InvalidLink) -> String {
     if link.reason.file_not_found() {
-        return format!("File not found: {}", link.link.href);
+        return match wrong_extension(link) {
+            Some(wrong) => format!(
+                "File not found: {} (did you mean {}?)",
+                link.link.href, wrong.suggestion
+            ),
+            None => format!("File not found: {}", link.link.href),
+        };
     }
 
     match link.reason {
@@ -431,19 +2117,13 @@ fn most_specific_error_message(link: &InvalidLink) -> String {
                 .url()
                 .expect("Response::error_for_status() always contains a URL");
 
-            match status.canonical_reason() {
-                Some(reason) => format!(
-                    "Server returned {} {} for {}",
-                    status.as_u16(),
-                    reason,
-                    url
-                ),
-                None => {
-                    format!("Server returned {} for {}", status.as_u16(), url)
-                },
-            }
+            crate::web::server_returned(
+                status.as_u16(),
+                status.canonical_reason(),
+                url,
+            )
         },
-        Reason::Web(ref web) => web.to_string(),
+        Reason::Web(ref web) => crate::web::describe_request_error(web),
         // fall back to the Reason's Display impl
         _ => link.reason.to_string(),
     }
@@ -452,6 +2132,206 @@ fn most_specific_error_message(link: &InvalidLink) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{HashedRegex, LinkRewrite, WarningPolicyOverrides};
+    use codespan::Span;
+    use codespan_reporting::diagnostic::LabelStyle;
+
+    #[test]
+    fn override_the_warning_policy_for_some_categories() {
+        let mut files = Files::new();
+        let file =
+            files.add("chapter_1.md", String::from("[foo] [bar](/x.md)"));
+        let link = Link::new("/x.md", Span::new(6, 18), file);
+        let outcome = ValidationOutcome {
+            valid_links: vec![link.clone()],
+            absolute_links: vec![link],
+            incomplete_links: vec![IncompleteLink {
+                reference: String::from("foo"),
+                file,
+                span: Span::new(0, 5),
+                suggestion: None,
+            }],
+            ..Default::default()
+        };
+        let cfg = Config {
+            warning_policy_overrides: WarningPolicyOverrides {
+                incomplete_link: Some(WarningPolicy::Error),
+                absolute_link: Some(WarningPolicy::Ignore),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let got = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].severity, Severity::Error);
+        assert_eq!(got[0].message, "Potential incomplete link");
+
+        // without overrides, both use the normal warning policy
+        let got = outcome.generate_diagnostics(&files, &Config::default());
+
+        assert_eq!(got.len(), 2);
+        assert!(got.iter().all(|diag| diag.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn some_absolute_links_can_be_allowed() {
+        let mut files = Files::new();
+        let file = files.add(
+            "chapter_1.md",
+            String::from("[api](/api/index.html) [intro](/intro.md)"),
+        );
+        let valid_links = vec![
+            Link::new("/api/index.html", Span::new(0, 22), file),
+            Link::new("/intro.md", Span::new(23, 41), file),
+        ];
+        let cfg = Config {
+            allow_absolute_patterns: vec![HashedRegex::new("^/api/").unwrap()],
+            ..Default::default()
+        };
+        let outcome = ValidationOutcome {
+            absolute_links: find_absolute_links(&valid_links, None, &cfg),
+            valid_links,
+            ..Default::default()
+        };
+
+        let got = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].message, "Absolute link should be made relative");
+        assert_eq!(got[0].labels[0].range, 23..41);
+    }
+
+    #[test]
+    fn suggest_a_similar_reference_for_incomplete_links() {
+        let mut files = Files::new();
+        let file = files.add(
+            "chapter_1.md",
+            String::from(
+                "See [foo] and [unrelated].\n\n[foos]: https://example.com/\n",
+            ),
+        );
+        let (_, incomplete_links) =
+            crate::extract_links(&Config::default(), vec![file], &files);
+        let outcome = ValidationOutcome {
+            incomplete_links,
+            ..Default::default()
+        };
+
+        let got = outcome.generate_diagnostics(&files, &Config::default());
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].notes[0], "hint: did you mean `[foos]`?");
+        assert!(!got[1]
+            .notes
+            .iter()
+            .any(|note| note.contains("did you mean")));
+    }
+
+    #[test]
+    fn report_unknown_links_when_asked() {
+        let mut files = Files::new();
+        let file = files.add(
+            "chapter_1.md",
+            String::from("See [the docs](htps://example.com) for more."),
+        );
+        let check = |cfg: &Config| {
+            let (links, incomplete_links) =
+                crate::extract_links(cfg, vec![file], &files);
+            let outcome = validate(
+                &links,
+                cfg,
+                &std::env::current_dir().unwrap(),
+                &mut Cache::default(),
+                &files,
+                &[file],
+                incomplete_links,
+            )
+            .unwrap();
+            assert!(outcome.invalid_links.is_empty());
+            assert_eq!(outcome.unknown_category.len(), 1);
+
+            outcome.generate_diagnostics(&files, cfg)
+        };
+        let mut cfg = Config {
+            warning_policy: WarningPolicy::Error,
+            ..Default::default()
+        };
+
+        // unknown links are ignored by default
+        assert!(check(&cfg).is_empty());
+
+        cfg.warning_policy = WarningPolicy::Warn;
+        cfg.warning_policy_overrides.unknown_link = Some(WarningPolicy::Error);
+        let got = check(&cfg);
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].severity, Severity::Error);
+        assert_eq!(
+            got[0].message,
+            "Unable to classify link: htps://example.com"
+        );
+    }
+
+    #[test]
+    fn the_same_broken_link_can_be_reported_once() {
+        let url = "https://example.com/gone";
+        let src = format!("[gone]({})", url);
+        let mut files = Files::new();
+        let first = files.add("chapter_1.md", src.clone());
+        let second = files.add("chapter_2.md", src.clone());
+        let broken = |file| InvalidLink {
+            link: Link::new(url, Span::new(0, src.len() as u32), file),
+            reason: Reason::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Server returned 404 Not Found",
+            )),
+        };
+        let outcome = ValidationOutcome {
+            invalid_links: vec![broken(first), broken(second)],
+            ..Default::default()
+        };
+        let mut cfg = Config::default();
+
+        let got = outcome.generate_diagnostics(&files, &cfg);
+        assert_eq!(got.len(), 2);
+
+        cfg.deduplicate_reports = true;
+        let got = outcome.generate_diagnostics(&files, &cfg);
+
+        assert_eq!(got.len(), 1);
+        let labels: Vec<_> = got[0]
+            .labels
+            .iter()
+            .map(|label| (label.file_id, label.style))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                (first, LabelStyle::Primary),
+                (second, LabelStyle::Secondary)
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_absolute_fs_paths() {
+        let inputs = vec![
+            ("/home/user/notes.md", true),
+            ("/Users/user/notes.md", true),
+            ("C:\\Users\\user\\notes.md", true),
+            ("d:/books/notes.md", true),
+            ("\\\\server\\share\\notes.md", true),
+            ("/chapter_1.md", false),
+            ("./home/notes.md", false),
+            ("https://example.com/home/", false),
+        ];
+
+        for (href, should_be) in inputs {
+            assert_eq!(is_absolute_fs_path(href), should_be, "{}", href);
+        }
+    }
 
     #[test]
     fn check_some_simple_relative_paths() {
@@ -466,4 +2346,365 @@ mod tests {
             assert_eq!(got, should_be);
         }
     }
+
+    #[test]
+    fn remember_local_files_between_runs() {
+        let temp = tempfile::tempdir().unwrap();
+        let src_dir = dunce::canonicalize(temp.path()).unwrap();
+        std::fs::write(src_dir.join("chapter_1.md"), "").unwrap();
+        std::fs::write(src_dir.join("chapter_2.md"), "").unwrap();
+        let mut files = Files::new();
+        let src = "[next](./chapter_2.md)";
+        let chapter_1 = files.add("chapter_1.md", String::from(src));
+        let chapter_2 = files.add("chapter_2.md", String::new());
+        let file_ids = vec![chapter_1, chapter_2];
+        let links =
+            vec![Link::new("./chapter_2.md", Span::new(0, 22), chapter_1)];
+        let cfg = Config::default();
+        let mut cache = Cache::default();
+        let check = |cache: &mut Cache| {
+            cache.reset_stats();
+            validate(
+                &links,
+                &cfg,
+                &src_dir,
+                cache,
+                &files,
+                &file_ids,
+                Vec::new(),
+            )
+            .unwrap()
+        };
+
+        let first = check(&mut cache);
+        assert_eq!(first.valid_links.len(), 1);
+        assert_eq!(cache.stats().local_hits, 0);
+
+        // the second run doesn't need to look for chapter_2.md
+        let second = check(&mut cache);
+        assert_eq!(second.valid_links.len(), 1);
+        assert_eq!(cache.stats().local_hits, 1);
+
+        // deleting the file changes its directory, so it is looked for again
+        std::fs::remove_file(src_dir.join("chapter_2.md")).unwrap();
+        let third = check(&mut cache);
+        assert_eq!(third.invalid_links.len(), 1);
+        assert_eq!(cache.stats().local_hits, 0);
+    }
+
+    #[test]
+    fn check_lots_of_local_links_in_parallel() {
+        let temp = tempfile::tempdir().unwrap();
+        let src_dir = dunce::canonicalize(temp.path()).unwrap();
+        let mut files = Files::new();
+        let mut file_ids = Vec::new();
+        let mut links = Vec::new();
+        let mut should_be_broken = Vec::new();
+
+        for i in 0..100 {
+            let name = format!("chapter_{}.md", i);
+            std::fs::write(src_dir.join(&name), "").unwrap();
+            let file = files.add(name, String::new());
+            file_ids.push(file);
+
+            for j in 0..20 {
+                // every 7th link points to a chapter that doesn't exist
+                let href = if (i * 20 + j) % 7 == 0 {
+                    format!("./missing_{}.md", j)
+                } else {
+                    format!("./chapter_{}.md", (i + j) % 100)
+                };
+                let span = Span::new(j, j + 1);
+                if href.contains("missing") {
+                    should_be_broken.push((file, span, href.clone()));
+                }
+                links.push(Link::new(href, span, file));
+            }
+        }
+
+        let outcome = validate(
+            &links,
+            &Config::default(),
+            &src_dir,
+            &mut Cache::default(),
+            &files,
+            &file_ids,
+            Vec::new(),
+        )
+        .unwrap();
+
+        let broken: Vec<_> = outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| {
+                (
+                    invalid.link.file,
+                    invalid.link.span,
+                    invalid.link.href.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(broken, should_be_broken);
+        assert!(outcome
+            .invalid_links
+            .iter()
+            .all(|invalid| invalid.reason.file_not_found()));
+        assert_eq!(
+            outcome.valid_links.len(),
+            links.len() - should_be_broken.len()
+        );
+    }
+
+    #[test]
+    fn explain_where_broken_links_resolved_to() {
+        let temp = tempfile::tempdir().unwrap();
+        let src_dir = dunce::canonicalize(temp.path()).unwrap();
+        std::fs::create_dir(src_dir.join("nested")).unwrap();
+        let mut files = Files::new();
+        let src = "[setup](../guide/setup.md)";
+        let chapter = files
+            .add(Path::new("nested").join("chapter_1.md"), String::from(src));
+        let links =
+            vec![Link::new("../guide/setup.md", Span::new(0, 26), chapter)];
+        let cfg = Config::default();
+        let mut outcome = validate(
+            &links,
+            &cfg,
+            &src_dir,
+            &mut Cache::default(),
+            &files,
+            &[chapter],
+            Vec::new(),
+        )
+        .unwrap();
+
+        outcome.resolved_paths =
+            resolved_paths(&outcome, &src_dir, &cfg, &files);
+
+        let diags = outcome.generate_diagnostics(&files, &Config::default());
+        assert_eq!(diags.len(), 1);
+        let expected = src_dir.join("guide").join("setup.md");
+        assert!(
+            diags[0].notes.contains(&format!(
+                "note: the link resolved to \"{}\"",
+                expected.display()
+            )),
+            "{:?}",
+            diags[0].notes
+        );
+    }
+
+    #[test]
+    fn suggest_a_file_with_a_different_extension() {
+        let temp = tempfile::tempdir().unwrap();
+        let src_dir = dunce::canonicalize(temp.path()).unwrap();
+        std::fs::write(src_dir.join("chapter_1.md"), "").unwrap();
+        std::fs::write(src_dir.join("guide.markdown"), "").unwrap();
+        let mut files = Files::new();
+        let src = "[guide](./guide.md) [other](./other.md)";
+        let chapter_1 = files.add("chapter_1.md", String::from(src));
+        let links = vec![
+            Link::new("./guide.md", Span::new(0, 19), chapter_1),
+            Link::new("./other.md", Span::new(20, 39), chapter_1),
+        ];
+
+        let outcome = validate(
+            &links,
+            &Config::default(),
+            &src_dir,
+            &mut Cache::default(),
+            &files,
+            &[chapter_1],
+            Vec::new(),
+        )
+        .unwrap();
+
+        let messages: Vec<_> = outcome
+            .invalid_links
+            .iter()
+            .map(most_specific_error_message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "File not found: ./guide.md (did you mean guide.markdown?)",
+                "File not found: ./other.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn suggest_https_for_insecure_links() {
+        let cfg = Config {
+            warn_on_insecure_links: true,
+            ..Default::default()
+        };
+        let src = "[docs](http://example.com/docs) and [secure](https://example.com/)";
+
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+
+        assert_eq!(outcome.insecure_links.len(), 1);
+        assert_eq!(outcome.insecure_links[0].href, "http://example.com/docs");
+        let files = Files::new();
+        let diags = outcome.generate_diagnostics(&files, &Config::default());
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert_eq!(
+            diags[0].notes,
+            vec![String::from(
+                "hint: link to \"https://example.com/docs\" instead"
+            )]
+        );
+    }
+
+    #[test]
+    fn let_the_link_classifier_decide_how_links_are_handled() {
+        let cfg = Config {
+            exclude: vec![crate::HashedRegex::new("missing").unwrap()],
+            link_classifier: Some(crate::LinkClassifier::new(|link| {
+                if link.href.starts_with("slack://") {
+                    Some(LinkClassification::Valid)
+                } else if link.href.contains("skip") {
+                    Some(LinkClassification::Ignored)
+                } else if link.href.contains("missing") {
+                    Some(LinkClassification::Checked)
+                } else {
+                    None
+                }
+            })),
+            ..Default::default()
+        };
+        let src = "[chat](slack://channel?id=C123) [skip](./skip.md) [missing](./missing.md)";
+
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+
+        let hrefs = |links: &[Link]| {
+            links
+                .iter()
+                .map(|link| link.href.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            hrefs(&outcome.valid_links),
+            vec!["slack://channel?id=C123"]
+        );
+        assert_eq!(hrefs(&outcome.ignored), vec!["./skip.md"]);
+        assert!(outcome.unknown_category.is_empty());
+        // the classifier takes precedence over `exclude`
+        assert_eq!(outcome.invalid_links.len(), 1);
+        assert_eq!(outcome.invalid_links[0].link.href, "./missing.md");
+    }
+
+    #[test]
+    fn skip_links_inside_blockquotes() {
+        let cfg = Config {
+            ignore_in_blockquotes: true,
+            ..Default::default()
+        };
+        let src = "> Note: see [the example](./example.md)\n\
+                   >\n\
+                   > > [nested](./nested.md)\n\n\
+                   [outside](./outside.md)";
+
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+
+        let ignored: Vec<_> =
+            outcome.ignored.iter().map(|link| &link.href).collect();
+        assert_eq!(ignored, vec!["./example.md", "./nested.md"]);
+        assert_eq!(outcome.invalid_links.len(), 1);
+        assert_eq!(outcome.invalid_links[0].link.href, "./outside.md");
+    }
+
+    #[test]
+    fn ignore_links_with_unsupported_schemes() {
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+        let src =
+            "![dot](data:image/png;base64,iVBORw0KGgo=) [next](./next.md)";
+
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+
+        let ignored: Vec<_> =
+            outcome.ignored.iter().map(|link| &link.href).collect();
+        assert_eq!(ignored, vec!["data:image/png;base64,iVBORw0KGgo="]);
+        assert!(outcome.unknown_category.is_empty());
+        assert_eq!(outcome.invalid_links.len(), 1);
+        assert_eq!(outcome.invalid_links[0].link.href, "./next.md");
+    }
+
+    #[test]
+    fn ignore_links_to_private_networks_when_asked() {
+        // nothing should be listening on port 1, so the link is broken if we
+        // actually try to check it
+        let src = "[dev server](http://localhost:1/)";
+        let mut cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+        assert!(outcome.ignored.is_empty());
+        assert_eq!(outcome.invalid_links.len(), 1);
+
+        cfg.ignore_private_networks = true;
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+        assert_eq!(outcome.ignored.len(), 1);
+        assert!(outcome.invalid_links.is_empty());
+    }
+
+    #[test]
+    fn rewritten_links_are_checked_using_the_new_href() {
+        // nothing should be listening on port 1, so the link is broken
+        // unless it gets rewritten
+        let src =
+            "# Installation\n\n[install](http://localhost:1/#installation)";
+        let mut cfg = Config {
+            follow_web_links: true,
+            check_fragments: true,
+            ..Default::default()
+        };
+
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+        assert_eq!(outcome.invalid_links.len(), 1);
+
+        cfg.link_rewrites = vec![LinkRewrite {
+            pattern: HashedRegex::new("^http://localhost:1/").unwrap(),
+            replacement: String::new(),
+        }];
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+        assert!(outcome.invalid_links.is_empty());
+        assert_eq!(outcome.valid_links.len(), 1);
+        assert_eq!(
+            outcome.valid_links[0].href,
+            "http://localhost:1/#installation"
+        );
+    }
+
+    #[test]
+    fn check_links_to_headings_in_the_same_chapter() {
+        let cfg = Config {
+            check_fragments: true,
+            ..Default::default()
+        };
+        let src = "# Installation\n\n## Usage\n\n## Usage\n\n\
+                   [a](#installation) [b](#usage-1) [c](#instalation) [d](#usage-2)";
+
+        let outcome = crate::check_markdown(src, &cfg).unwrap();
+
+        let valid: Vec<_> =
+            outcome.valid_links.iter().map(|link| &link.href).collect();
+        assert_eq!(valid, vec!["#installation", "#usage-1"]);
+        let invalid: Vec<_> = outcome
+            .invalid_links
+            .iter()
+            .map(|invalid| invalid.link.href.as_str())
+            .collect();
+        assert_eq!(invalid, vec!["#instalation", "#usage-2"]);
+        assert_eq!(
+            outcome.invalid_links[0].reason.to_string(),
+            "There is no \"#instalation\" anchor in \"input.md\""
+        );
+    }
 }
@@ -0,0 +1,1911 @@
+//! Validation of links to things on the internet.
+//!
+//! We do this ourselves instead of deferring to [`linkcheck::validate()`] so
+//! we have control over how requests are made (e.g. the number of requests in
+//! flight) and what gets cached between runs.
+
+use crate::{
+    cache::CacheEntry, CheckMode, Config, Context, HashedRegex, RedirectPolicy,
+};
+use futures::stream::{FuturesUnordered, StreamExt};
+use http::{
+    header::{CONTENT_TYPE, RETRY_AFTER, USER_AGENT},
+    HeaderMap, Method, StatusCode,
+};
+use linkcheck::{
+    validation::{Context as _, InvalidLink, Outcomes, Reason},
+    Link,
+};
+use reqwest::{Response, ResponseBuilderExt, Url};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    io,
+    net::IpAddr,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::Semaphore;
+
+/// Get the [`Url`] for a link, if it points to something on the web.
+///
+/// Protocol-relative links (e.g. `//cdn.example.com/lib.js`) use whichever
+/// scheme the page was served with, so they are checked using HTTPS.
+pub(crate) fn web_url(href: &str) -> Option<Url> {
+    let url: Url = if is_protocol_relative(href) {
+        format!("https:{}", href).parse().ok()?
+    } else {
+        href.parse().ok()?
+    };
+
+    match url.scheme() {
+        "http" | "https" => Some(url),
+        _ => None,
+    }
+}
+
+/// Is this a link like `//cdn.example.com/lib.js`, which points to another
+/// host without specifying a scheme?
+pub(crate) fn is_protocol_relative(href: &str) -> bool {
+    href.starts_with("//")
+}
+
+/// Does the URL point to an address on a private network (loopback,
+/// link-local or one of the private ranges) or a `*.localhost` name?
+///
+/// This only looks at the URL itself, so hosts like `localhost` are found by
+/// [`resolves_to_private_network()`] instead.
+pub(crate) fn is_private_network(url: &Url) -> bool {
+    let host = match url.host_str() {
+        Some(host) => host.trim_start_matches('[').trim_end_matches(']'),
+        None => return false,
+    };
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) => is_private_ip(ip),
+        Err(_) => {
+            // not every resolver knows these are reserved for loopback
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host.ends_with(".localhost")
+        },
+    }
+}
+
+/// Does the URL's host resolve to any addresses on a private network? Hosts
+/// which can't be resolved are left for the request to report.
+pub(crate) async fn resolves_to_private_network(url: &Url) -> bool {
+    if is_private_network(url) {
+        return true;
+    }
+
+    let host = match url.domain() {
+        Some(host) => host,
+        None => return false,
+    };
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    match tokio::net::lookup_host((host, port)).await {
+        Ok(mut addresses) => {
+            addresses.any(|address| is_private_ip(address.ip()))
+        },
+        Err(e) => {
+            log::debug!("Unable to resolve \"{}\": {}", host, e);
+            false
+        },
+    }
+}
+
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+        },
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            let link_local = first & 0xffc0 == 0xfe80;
+            let unique_local = first & 0xfe00 == 0xfc00;
+
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || link_local
+                || unique_local
+        },
+    }
+}
+
+/// Check every web link, never letting more than [`Config::max_concurrency`]
+/// requests be in flight at a time.
+///
+/// Each result is added to `got` as soon as its request finishes, so nothing
+/// is lost if the future is cancelled part way through.
+///
+/// [`Config::max_concurrency`]: crate::Config::max_concurrency
+pub(crate) async fn validate(
+    links: Vec<Link>,
+    ctx: &Context<'_>,
+    got: &mut WebOutcomes,
+) {
+    let semaphore = Semaphore::new(ctx.cfg.max_concurrency.max(1));
+    let semaphore = &semaphore;
+
+    let mut checks: FuturesUnordered<_> = links
+        .into_iter()
+        .map(|link| async move {
+            let _permit = semaphore
+                .acquire()
+                .await
+                .expect("The semaphore is never closed");
+            let url = web_url(&link.href)
+                .expect("Only web links should be passed in");
+
+            // the host needs to be resolved to know whether it's private, so
+            // this can't be done up front with the other ignored links
+            if ctx.cfg.ignore_private_networks
+                && resolves_to_private_network(&url).await
+            {
+                ctx.progress.checked(1);
+                return (link, None);
+            }
+
+            let started = Instant::now();
+            let result = check_url(&url, ctx).await;
+            ctx.lock_timings().record(
+                url.host_str().unwrap_or_default(),
+                link.file,
+                started.elapsed(),
+            );
+            ctx.progress.checked(1);
+
+            (link, Some(result))
+        })
+        .collect();
+
+    while let Some((link, result)) = checks.next().await {
+        match result {
+            None => got.outcomes.ignored.push(link),
+            Some(Ok(checked)) => {
+                if let Some(body_bytes) = checked.body_bytes {
+                    if Some(body_bytes) < ctx.cfg.min_body_bytes {
+                        got.small_bodies.push(SmallBody {
+                            link: link.clone(),
+                            body_bytes,
+                        });
+                    }
+                }
+
+                if let Some(pattern) = checked.soft_404 {
+                    got.soft_404s.push(Soft404 {
+                        link: link.clone(),
+                        pattern,
+                    });
+                }
+
+                if let Some(final_url) = checked.redirected_to {
+                    let redirect = Redirect {
+                        link: link.clone(),
+                        final_url,
+                    };
+
+                    if ctx.cfg.redirect_policy == RedirectPolicy::Warn {
+                        got.redirects.push(redirect);
+                    } else if ctx.cfg.report_redirects {
+                        got.moved_links.push(redirect);
+                    }
+                }
+
+                got.outcomes.valid.push(link);
+            },
+            Some(Err(reason)) => {
+                got.outcomes.invalid.push(InvalidLink { link, reason })
+            },
+        }
+    }
+}
+
+/// The results of checking a set of web links.
+#[derive(Debug, Default)]
+pub(crate) struct WebOutcomes {
+    pub(crate) outcomes: Outcomes,
+    pub(crate) redirects: Vec<Redirect>,
+    pub(crate) moved_links: Vec<Redirect>,
+    pub(crate) small_bodies: Vec<SmallBody>,
+    pub(crate) soft_404s: Vec<Soft404>,
+    /// Links which weren't checked before the deadline.
+    pub(crate) timed_out: Vec<Link>,
+}
+
+/// What we learned from successfully checking a URL.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Checked {
+    /// Where the URL ended up after following redirects, if it was
+    /// redirected.
+    redirected_to: Option<String>,
+    /// The number of bytes read from the body, if [`Config::min_body_bytes`]
+    /// is set.
+    ///
+    /// [`Config::min_body_bytes`]: crate::Config::min_body_bytes
+    body_bytes: Option<u64>,
+    /// The first of the [`Config::soft_404_patterns`] which matched the
+    /// body, if any.
+    ///
+    /// [`Config::soft_404_patterns`]: crate::Config::soft_404_patterns
+    soft_404: Option<String>,
+}
+
+/// A web link which ended up somewhere else after following redirects.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    /// The original link.
+    pub link: Link,
+    /// The URL the redirects ended up at.
+    pub final_url: String,
+}
+
+/// A web link which responded successfully, but with a body smaller than
+/// [`Config::min_body_bytes`] (often a sign of a placeholder page or a "soft
+/// 404").
+///
+/// [`Config::min_body_bytes`]: crate::Config::min_body_bytes
+#[derive(Debug, Clone)]
+pub struct SmallBody {
+    /// The original link.
+    pub link: Link,
+    /// The size of the body, in bytes.
+    pub body_bytes: u64,
+}
+
+/// A web link which responded successfully, but whose body matched one of
+/// the [`Config::soft_404_patterns`] (e.g. a "Page not found" page served
+/// with `200 OK`).
+///
+/// [`Config::soft_404_patterns`]: crate::Config::soft_404_patterns
+#[derive(Debug, Clone)]
+pub struct Soft404 {
+    /// The original link.
+    pub link: Link,
+    /// The pattern which matched the body.
+    pub pattern: String,
+}
+
+async fn check_url(url: &Url, ctx: &Context<'_>) -> Result<Checked, Reason> {
+    let timeout = ctx.cfg.cache_timeout.for_url(url.as_str());
+
+    if let Some(result) = cached_result(url, ctx, timeout) {
+        ctx.lock_cache().record_hit();
+        return result;
+    }
+    ctx.lock_cache().record_miss();
+
+    log::debug!("Checking \"{}\" on the web", url);
+    let mut result = get(url, ctx).await;
+    let mut attempts = 0;
+
+    while attempts < ctx.cfg.retries && is_transient(&result) {
+        attempts += 1;
+        log::debug!(
+            "Retrying \"{}\" (attempt {} of {})",
+            url,
+            attempts,
+            ctx.cfg.retries
+        );
+        result = get(url, ctx).await;
+    }
+
+    let mut entry = match result {
+        Ok(ref checked) => CacheEntry {
+            redirected_to: checked.redirected_to.clone(),
+            body_bytes: checked.body_bytes,
+            soft_404: checked.soft_404.clone(),
+            ..CacheEntry::new(true)
+        },
+        Err(ref e) => match e.status() {
+            Some(status) => CacheEntry::failed(
+                Some(status.as_u16()),
+                status.canonical_reason().map(String::from),
+            ),
+            None => CacheEntry::failed(None, Some(e.to_string())),
+        },
+    };
+
+    let mut cache = ctx.lock_cache();
+    if !entry.successful {
+        entry.consecutive_failures += cache
+            .lookup(url.as_str())
+            .filter(|previous| !previous.successful)
+            .map(|previous| previous.consecutive_failures.max(1))
+            .unwrap_or(0);
+    }
+    let consecutive_failures = entry.consecutive_failures;
+    cache.insert(url.as_str(), entry);
+    drop(cache);
+
+    match result {
+        Err(e) if consecutive_failures < failure_threshold(ctx) => {
+            log::warn!(
+                "Checking \"{}\" failed ({} of {} consecutive failures before it is reported): {}",
+                url,
+                consecutive_failures,
+                failure_threshold(ctx),
+                e
+            );
+            Ok(Checked::default())
+        },
+        other => other.map_err(Failure::into_reason),
+    }
+}
+
+fn failure_threshold(ctx: &Context<'_>) -> u32 {
+    ctx.cfg.consecutive_failures_threshold.max(1)
+}
+
+/// Might checking the URL again give a different result?
+fn is_transient<T>(result: &Result<T, Failure>) -> bool {
+    match result {
+        Err(Failure::Web(e)) => {
+            e.is_timeout()
+                || e.status().map(|s| s.is_server_error()).unwrap_or(false)
+        },
+        _ => false,
+    }
+}
+
+async fn get(url: &Url, ctx: &Context<'_>) -> Result<Checked, Failure> {
+    let mut method = match ctx.cfg.check_mode_for(url.as_str()) {
+        CheckMode::Get => Method::GET,
+        CheckMode::Head => Method::HEAD,
+        CheckMode::DnsOnly => {
+            resolve(url).await?;
+            return Ok(Checked::default());
+        },
+    };
+    let mut response = fetch(url, &method, ctx).await?;
+
+    if method == Method::HEAD
+        && (response.status() == StatusCode::METHOD_NOT_ALLOWED
+            || response.status() == StatusCode::NOT_IMPLEMENTED)
+    {
+        log::debug!(
+            "\"{}\" doesn't allow HEAD requests, falling back to GET",
+            url
+        );
+        method = Method::GET;
+        response = fetch(url, &method, ctx).await?;
+    }
+
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        if let Some(delay) = retry_after(response.headers(), SystemTime::now())
+        {
+            let max_delay = Duration::from_secs(ctx.cfg.max_retry_after);
+            if delay > max_delay {
+                return Err(Failure::RetryAfter(RetryAfterTooLong {
+                    url: url.clone(),
+                    delay,
+                    max_delay,
+                }));
+            }
+
+            log::debug!(
+                "\"{}\" asked us to wait {}s before trying again",
+                url,
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+            response = fetch(url, &method, ctx).await?;
+        }
+    }
+
+    let mut response = response.error_for_status()?;
+
+    if let Some(expected) = ctx.cfg.expected_content_type_for(url.as_str()) {
+        let actual = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok());
+
+        if !content_type_matches(actual, expected) {
+            return Err(Failure::ContentType(UnexpectedContentType {
+                url: url.clone(),
+                expected: expected.to_string(),
+                actual: actual.map(String::from),
+            }));
+        }
+    }
+
+    let redirected_to = if response.url() == url {
+        None
+    } else {
+        Some(response.url().to_string())
+    };
+
+    // responses to a HEAD request never have a body
+    let (body_bytes, soft_404) =
+        if method == Method::GET && ctx.cfg.needs_body() {
+            let body = read_body(&mut response, body_limit(ctx.cfg)).await?;
+            // we don't know how big a page that was cut short really is
+            let truncated = ctx
+                .cfg
+                .max_response_bytes
+                .map(|max| body.len() as u64 >= max)
+                .unwrap_or(false);
+            (
+                ctx.cfg
+                    .min_body_bytes
+                    .filter(|_| !truncated)
+                    .map(|_| body.len() as u64),
+                soft_404_pattern(&body, &ctx.cfg.soft_404_patterns),
+            )
+        } else {
+            (None, None)
+        };
+
+    Ok(Checked {
+        redirected_to,
+        body_bytes,
+        soft_404,
+    })
+}
+
+async fn fetch(
+    url: &Url,
+    method: &Method,
+    ctx: &Context<'_>,
+) -> Result<Response, reqwest::Error> {
+    match ctx.responder {
+        Some(ref responder) => {
+            let (status, body) = responder.respond(url);
+            let response = http::Response::builder()
+                .status(status)
+                .url(url.clone())
+                .body(body)
+                .expect("The response is always valid");
+            Ok(Response::from(response))
+        },
+        None => send(url, method, ctx).await,
+    }
+}
+
+/// Check that the URL's host name resolves to at least one address.
+async fn resolve(url: &Url) -> Result<(), Failure> {
+    let host = url
+        .host_str()
+        .unwrap_or_default()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let error = match tokio::net::lookup_host((host, port)).await {
+        Ok(mut addresses) => {
+            if addresses.next().is_some() {
+                return Ok(());
+            }
+            io::Error::new(
+                io::ErrorKind::Other,
+                "it didn't resolve to any addresses",
+            )
+        },
+        Err(e) => e,
+    };
+
+    Err(Failure::Dns(UnresolvedHost {
+        url: url.clone(),
+        error,
+    }))
+}
+
+/// How long a `Retry-After` header asks us to wait, accepting both a number
+/// of seconds and a HTTP date.
+fn retry_after(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => {
+            let when = httpdate::parse_http_date(value).ok()?;
+            // a date in the past means we can try again straight away
+            Some(when.duration_since(now).unwrap_or_default())
+        },
+    }
+}
+
+async fn send(
+    url: &Url,
+    method: &Method,
+    ctx: &Context<'_>,
+) -> Result<Response, reqwest::Error> {
+    let mut request = ctx.client.request(method.clone(), url.clone());
+
+    if let Some(user_agent) = ctx.next_user_agent() {
+        request = request.header(USER_AGENT, user_agent);
+    }
+
+    request.headers(ctx.url_specific_headers(url)).send().await
+}
+
+/// Something which answers web requests instead of the network, so web links
+/// can be checked without any network access.
+pub(crate) trait HttpResponder: Debug + Send + Sync {
+    /// Get the status code and body to respond to a `GET` request for `url`
+    /// with.
+    fn respond(&self, url: &Url) -> (u16, Vec<u8>);
+}
+
+/// Read up to `limit` bytes from the response's body (possibly a little more,
+/// because it arrives in chunks).
+async fn read_body(
+    response: &mut Response,
+    limit: u64,
+) -> Result<Vec<u8>, reqwest::Error> {
+    let mut body = Vec::new();
+
+    while (body.len() as u64) < limit {
+        match response.chunk().await? {
+            Some(chunk) => body.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+
+    Ok(body)
+}
+
+/// How much of the body needs to be read to check both
+/// [`Config::min_body_bytes`] and [`Config::soft_404_patterns`], without
+/// going over [`Config::max_response_bytes`].
+fn body_limit(cfg: &Config) -> u64 {
+    let soft_404_limit = if cfg.soft_404_patterns.is_empty() {
+        0
+    } else {
+        Config::SOFT_404_BODY_LIMIT
+    };
+    let needed = cfg.min_body_bytes.unwrap_or(0).max(soft_404_limit);
+
+    match cfg.max_response_bytes {
+        Some(max) => needed.min(max),
+        None => needed,
+    }
+}
+
+/// Find the first pattern which matches the body, if any.
+fn soft_404_pattern(body: &[u8], patterns: &[HashedRegex]) -> Option<String> {
+    let text = String::from_utf8_lossy(body);
+
+    patterns
+        .iter()
+        .find(|pattern| pattern.is_match(&text))
+        .map(|pattern| pattern.string.clone())
+}
+
+/// Does a `Content-Type` header match the type we expected, ignoring
+/// parameters like `; charset=utf-8`?
+fn content_type_matches(actual: Option<&str>, expected: &str) -> bool {
+    match actual {
+        Some(actual) => actual
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .eq_ignore_ascii_case(expected.trim()),
+        None => false,
+    }
+}
+
+fn cached_result(
+    url: &Url,
+    ctx: &Context<'_>,
+    timeout: Duration,
+) -> Option<Result<Checked, Reason>> {
+    let cache = ctx.lock_cache();
+    let entry = cache.fresh(url.as_str(), timeout)?;
+
+    if entry.successful {
+        log::debug!("The cache says \"{}\" is still valid", url);
+        return Some(Ok(Checked {
+            redirected_to: entry.redirected_to.clone(),
+            body_bytes: entry.body_bytes,
+            soft_404: entry.soft_404.clone(),
+        }));
+    }
+
+    if entry.consecutive_failures.max(1) < failure_threshold(ctx) {
+        // the link hasn't failed enough times to be reported yet, so give it
+        // another chance (unless we've already checked it during this run)
+        return if entry.timestamp >= ctx.run_started {
+            Some(Ok(Checked::default()))
+        } else {
+            None
+        };
+    }
+
+    // we can only reproduce the original error when the server actually
+    // responded, and errors like "503 Service Unavailable" or "429 Too Many
+    // Requests" may have gone away since an earlier run
+    let status = entry.status?;
+    if entry.timestamp < ctx.run_started && !is_permanent_failure(status) {
+        return None;
+    }
+    log::debug!("The cache says \"{}\" returned {}", url, status);
+
+    Some(Err(Reason::Io(io::Error::new(
+        io::ErrorKind::Other,
+        CachedFailure {
+            url: url.clone(),
+            status,
+            reason: entry.reason.clone(),
+        },
+    ))))
+}
+
+/// Is a status code something we can expect to get again the next time we
+/// check the URL?
+fn is_permanent_failure(status: u16) -> bool {
+    match StatusCode::from_u16(status) {
+        Ok(StatusCode::REQUEST_TIMEOUT) | Ok(StatusCode::TOO_MANY_REQUESTS) => {
+            false
+        },
+        Ok(status) => status.is_client_error(),
+        Err(_) => false,
+    }
+}
+
+/// Why checking a URL failed.
+#[derive(Debug)]
+enum Failure {
+    Web(reqwest::Error),
+    RetryAfter(RetryAfterTooLong),
+    ContentType(UnexpectedContentType),
+    Dns(UnresolvedHost),
+}
+
+impl Failure {
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Failure::Web(e) => e.status(),
+            Failure::RetryAfter(_) => Some(StatusCode::TOO_MANY_REQUESTS),
+            Failure::ContentType(_) | Failure::Dns(_) => None,
+        }
+    }
+
+    fn into_reason(self) -> Reason {
+        match self {
+            Failure::Web(e) => Reason::Web(e),
+            Failure::RetryAfter(e) => {
+                Reason::Io(io::Error::new(io::ErrorKind::Other, e))
+            },
+            Failure::ContentType(e) => {
+                Reason::Io(io::Error::new(io::ErrorKind::InvalidData, e))
+            },
+            Failure::Dns(e) => {
+                Reason::Io(io::Error::new(io::ErrorKind::Other, e))
+            },
+        }
+    }
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(e: reqwest::Error) -> Failure { Failure::Web(e) }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Web(e) => Display::fmt(e, f),
+            Failure::RetryAfter(e) => Display::fmt(e, f),
+            Failure::ContentType(e) => Display::fmt(e, f),
+            Failure::Dns(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+/// The server responded with `429 Too Many Requests` and asked us to wait
+/// longer than [`Config::max_retry_after`] before trying again.
+///
+/// [`Config::max_retry_after`]: crate::Config::max_retry_after
+#[derive(Debug)]
+pub struct RetryAfterTooLong {
+    /// The URL that was checked.
+    pub url: Url,
+    /// How long the server asked us to wait.
+    pub delay: Duration,
+    /// The longest we were willing to wait.
+    pub max_delay: Duration,
+}
+
+impl Display for RetryAfterTooLong {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, and the requested delay of {}s is longer than max-retry-after ({}s)",
+            server_returned(429, Some("Too Many Requests"), &self.url),
+            self.delay.as_secs(),
+            self.max_delay.as_secs()
+        )
+    }
+}
+
+impl std::error::Error for RetryAfterTooLong {}
+
+/// A web link responded successfully, but not with the `Content-Type` given
+/// in [`Config::expected_content_type`].
+///
+/// [`Config::expected_content_type`]: crate::Config::expected_content_type
+#[derive(Debug)]
+pub struct UnexpectedContentType {
+    /// The URL that was checked.
+    pub url: Url,
+    /// The content type the link should have responded with.
+    pub expected: String,
+    /// The `Content-Type` header the server sent back, if there was one.
+    pub actual: Option<String>,
+}
+
+impl Display for UnexpectedContentType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.actual {
+            Some(ref actual) => write!(
+                f,
+                "Expected {} to be \"{}\", but it was \"{}\"",
+                self.url, self.expected, actual
+            ),
+            None => write!(
+                f,
+                "Expected {} to be \"{}\", but it had no content type",
+                self.url, self.expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnexpectedContentType {}
+
+/// The host name of a web link checked using [`CheckMode::DnsOnly`] couldn't
+/// be resolved.
+///
+/// [`CheckMode::DnsOnly`]: crate::CheckMode::DnsOnly
+#[derive(Debug)]
+pub struct UnresolvedHost {
+    /// The URL that was checked.
+    pub url: Url,
+    /// Why resolving the host name failed.
+    pub error: io::Error,
+}
+
+impl Display for UnresolvedHost {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unable to resolve the host for {} because {}",
+            self.url, self.error
+        )
+    }
+}
+
+impl std::error::Error for UnresolvedHost {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// The message used when a server responds with an error status code.
+pub(crate) fn server_returned(
+    status: u16,
+    reason: Option<&str>,
+    url: &Url,
+) -> String {
+    match reason {
+        Some(reason) => {
+            format!("Server returned {} {} for {}", status, reason, url)
+        },
+        None => format!("Server returned {} for {}", status, url),
+    }
+}
+
+/// A user-friendly message for errors where we never got a response (e.g.
+/// the connection was refused or the host name couldn't be resolved).
+pub(crate) fn describe_request_error(error: &reqwest::Error) -> String {
+    let url = match error.url() {
+        Some(url) => url,
+        None => return error.to_string(),
+    };
+    let host = url.host_str().unwrap_or_default();
+
+    if error.is_timeout() {
+        return format!("Timed out waiting for a response from {}", url);
+    } else if !error.is_connect() {
+        return error.to_string();
+    }
+
+    let causes: Vec<&(dyn std::error::Error + 'static)> =
+        std::iter::successors(
+            Some(error as &(dyn std::error::Error + 'static)),
+            |e| e.source(),
+        )
+        .collect();
+    let io_error = causes.iter().find_map(|e| e.downcast_ref::<io::Error>());
+    // hyper's connect errors aren't public, but a failed lookup is always
+    // reported as a "dns error"
+    let dns_error = causes
+        .iter()
+        .any(|e| e.to_string().starts_with("dns error"));
+
+    match io_error {
+        _ if dns_error => format!("Could not resolve host \"{}\"", host),
+        Some(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            format!(
+                "Connection refused by \"{}:{}\"",
+                host,
+                url.port_or_known_default().unwrap_or_default()
+            )
+        },
+        _ => error.to_string(),
+    }
+}
+
+/// An error from a previous run which was remembered by the [`Cache`] instead
+/// of checking the URL again.
+///
+/// [`Cache`]: crate::Cache
+#[derive(Debug)]
+pub struct CachedFailure {
+    /// The URL that was checked.
+    pub url: Url,
+    /// The HTTP status code the server responded with.
+    pub status: u16,
+    /// The status code's reason phrase (e.g. `"Not Found"`).
+    pub reason: Option<String>,
+}
+
+impl Display for CachedFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let msg =
+            server_returned(self.status, self.reason.as_deref(), &self.url);
+        f.write_str(&msg)
+    }
+}
+
+impl std::error::Error for CachedFailure {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        progress::Progress,
+        test_server::{self, TestServer},
+        timings::Timings,
+        validate::most_specific_error_message,
+        Cache, CheckMode, Config, HashedRegex, RedirectPolicy,
+        ValidationOutcome, WarningPolicy,
+    };
+    use codespan::{FileId, Files, Span};
+    use codespan_reporting::diagnostic::Severity;
+    use linkcheck::validation::Options;
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        iter::FromIterator,
+        net::TcpListener,
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::SystemTime,
+    };
+
+    /// Start a HTTP server which answers every request with a `200 OK` after
+    /// a short delay, keeping track of the most requests it was handling at
+    /// once.
+    fn counting_server() -> (TestServer, Arc<AtomicUsize>) {
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let max = Arc::clone(&max_in_flight);
+
+        let server = TestServer::start(move |_| {
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max_in_flight.fetch_max(current, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            test_server::ok()
+        });
+
+        (server, max)
+    }
+
+    /// Start a HTTP server which always sends back the same response.
+    fn respond_with(response: Vec<u8>) -> TestServer {
+        TestServer::start(move |_| response.clone())
+    }
+
+    /// Start a HTTP server which sends back each response in turn (repeating
+    /// the last one).
+    fn respond_in_turn(responses: Vec<Vec<u8>>) -> TestServer {
+        let requests = AtomicUsize::new(0);
+
+        TestServer::start(move |_| {
+            let ix = requests.fetch_add(1, Ordering::SeqCst);
+            responses[ix.min(responses.len() - 1)].clone()
+        })
+    }
+
+    /// Start a HTTP server which responds to `HEAD` requests with
+    /// `head_status` and everything else with `200 OK`.
+    fn method_server(head_status: &'static str) -> TestServer {
+        TestServer::start(move |request| {
+            let status = if request.method == "HEAD" {
+                head_status
+            } else {
+                "200 OK"
+            };
+            test_server::response(status, &[], "")
+        })
+    }
+
+    /// Start a HTTP server where `/a` redirects to `/b`, which redirects to
+    /// `/c`.
+    fn redirect_server() -> TestServer {
+        TestServer::start(|request| match request.path.as_str() {
+            "/a" => test_server::response(
+                "301 Moved Permanently",
+                &["Location: /b"],
+                "",
+            ),
+            "/b" => test_server::response(
+                "301 Moved Permanently",
+                &["Location: /c"],
+                "",
+            ),
+            _ => test_server::ok(),
+        })
+    }
+
+    fn context<'a>(
+        cfg: &'a Config,
+        files: &'a Files<String>,
+        links: &[Link],
+    ) -> Context<'a> {
+        Context {
+            client: cfg.client(),
+            filesystem_options: Options::default(),
+            cfg,
+            src_dir: Path::new("."),
+            cache: Mutex::new(Cache::default()),
+            files,
+            interpolated_headers: Vec::new(),
+            progress: Progress::new(links.len(), false),
+            user_agents: cfg.interpolate_user_agents(cfg.warning_policy),
+            next_user_agent: AtomicUsize::new(0),
+            timings: Mutex::new(Timings::default()),
+            run_started: SystemTime::now(),
+            responder: None,
+        }
+    }
+
+    fn links_to(base_url: &str, count: usize, file: FileId) -> Vec<Link> {
+        (0..count)
+            .map(|i| {
+                Link::new(format!("{}{}", base_url, i), Span::default(), file)
+            })
+            .collect()
+    }
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    async fn validate_all(links: Vec<Link>, ctx: &Context<'_>) -> WebOutcomes {
+        let mut got = WebOutcomes::default();
+        validate(links, ctx, &mut got).await;
+        got
+    }
+
+    #[test]
+    fn never_exceed_the_maximum_concurrency() {
+        let (server, max_in_flight) = counting_server();
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            max_concurrency: 1,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 5, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let outcomes = run(validate_all(links, &ctx)).outcomes;
+
+        assert_eq!(outcomes.valid.len(), 5);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn warn_when_the_time_budget_is_exceeded() {
+        // the server takes 50ms to respond to each request
+        let (server, _) = counting_server();
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            max_concurrency: 1,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("slow.md", String::new());
+        let links = links_to(base_url, 3, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let started = Instant::now();
+        run(validate_all(links, &ctx));
+        let over_budget = ctx
+            .lock_timings()
+            .over_budget(started.elapsed(), Duration::from_millis(100));
+
+        let over_budget = over_budget.unwrap();
+        assert_eq!(over_budget.slowest_hosts[0].0, "127.0.0.1");
+        assert_eq!(over_budget.slowest_files[0].0, file);
+        let outcome = ValidationOutcome {
+            over_budget: Some(over_budget),
+            ..Default::default()
+        };
+        let diags = outcome.generate_diagnostics(&files, &cfg);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert!(diags[0].message.contains("time budget"));
+        assert!(diags[0].notes[1].contains("slow.md"));
+
+        // it can be made an error like any other warning
+        let mut strict = cfg.clone();
+        strict.warning_policy_overrides.over_budget =
+            Some(WarningPolicy::Error);
+        let diags = outcome.generate_diagnostics(&files, &strict);
+        assert_eq!(diags[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn cached_failures_reproduce_the_original_error() {
+        let server =
+            respond_with(test_server::response("404 Not Found", &[], ""));
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let first = run(validate_all(links.clone(), &ctx)).outcomes;
+        let second = run(validate_all(links, &ctx)).outcomes;
+
+        assert_eq!(server.request_count(), 1);
+        let first = most_specific_error_message(&first.invalid[0]);
+        let second = most_specific_error_message(&second.invalid[0]);
+        assert_eq!(
+            first,
+            format!("Server returned 404 Not Found for {}0", base_url)
+        );
+        assert_eq!(second, first);
+        assert!(is_cached_failure(&ctx, base_url));
+    }
+
+    #[test]
+    fn transient_failures_from_earlier_runs_are_checked_again() {
+        let server = respond_with(test_server::response(
+            "503 Service Unavailable",
+            &[],
+            "",
+        ));
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+
+        let first_run = context(&cfg, &files, &links);
+        let first = run(validate_all(links.clone(), &first_run)).outcomes;
+        assert_eq!(first.invalid.len(), 1);
+
+        let second_run = Context {
+            cache: first_run.cache,
+            run_started: SystemTime::now() + Duration::from_secs(1),
+            ..context(&cfg, &files, &links)
+        };
+        let second = run(validate_all(links, &second_run)).outcomes;
+
+        assert_eq!(second.invalid.len(), 1);
+        assert_eq!(server.request_count(), 2);
+    }
+
+    fn check_redirect_chain(
+        cfg: Config,
+    ) -> (Outcomes, Vec<Redirect>, String, usize) {
+        let server = redirect_server();
+        let base_url = server.url().to_string();
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links =
+            vec![Link::new(format!("{}a", base_url), Span::default(), file)];
+        let ctx = context(&cfg, &files, &links);
+
+        let got = run(validate_all(links, &ctx));
+
+        (
+            got.outcomes,
+            got.redirects,
+            base_url,
+            server.request_count(),
+        )
+    }
+
+    #[test]
+    fn follow_redirects_by_default() {
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+
+        let (outcomes, redirects, _, requests) = check_redirect_chain(cfg);
+
+        assert_eq!(outcomes.valid.len(), 1);
+        assert!(redirects.is_empty());
+        assert_eq!(requests, 3);
+    }
+
+    #[test]
+    fn warn_about_redirects() {
+        let cfg = Config {
+            follow_web_links: true,
+            redirect_policy: RedirectPolicy::Warn,
+            ..Default::default()
+        };
+
+        let (outcomes, redirects, base_url, _) = check_redirect_chain(cfg);
+
+        assert_eq!(outcomes.valid.len(), 1);
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(redirects[0].final_url, format!("{}c", base_url));
+        let outcome = ValidationOutcome {
+            redirects,
+            ..Default::default()
+        };
+        let diags =
+            outcome.generate_diagnostics(&Files::new(), &Config::default());
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert!(diags[0].notes[0].contains(&format!("{}c", base_url)));
+    }
+
+    #[test]
+    fn report_links_which_were_redirected() {
+        let cfg = Config {
+            follow_web_links: true,
+            report_redirects: true,
+            ..Default::default()
+        };
+        let server = redirect_server();
+        let base_url = server.url();
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links =
+            vec![Link::new(format!("{}a", base_url), Span::default(), file)];
+        let ctx = context(&cfg, &files, &links);
+
+        let got = run(validate_all(links, &ctx));
+
+        assert_eq!(got.outcomes.valid.len(), 1);
+        assert!(got.redirects.is_empty());
+        let outcome = ValidationOutcome {
+            moved_links: got.moved_links,
+            ..Default::default()
+        };
+        let cfg = Config {
+            warning_policy: WarningPolicy::Error,
+            ..cfg.clone()
+        };
+        let diags = outcome.generate_diagnostics(&Files::new(), &cfg);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Note);
+        assert_eq!(
+            diags[0].message,
+            format!("\"{}a\" redirects to \"{}c\"", base_url, base_url)
+        );
+    }
+
+    #[test]
+    fn the_redirect_is_the_final_response_when_not_following() {
+        let cfg = Config {
+            follow_web_links: true,
+            redirect_policy: RedirectPolicy::None,
+            ..Default::default()
+        };
+
+        let (outcomes, redirects, _, requests) = check_redirect_chain(cfg);
+
+        assert_eq!(outcomes.valid.len(), 1);
+        assert!(redirects.is_empty());
+        assert_eq!(requests, 1);
+    }
+
+    #[test]
+    fn too_many_redirects_is_an_error() {
+        let cfg = Config {
+            follow_web_links: true,
+            max_redirects: 1,
+            ..Default::default()
+        };
+
+        let (outcomes, _, _, _) = check_redirect_chain(cfg);
+
+        assert!(outcomes.valid.is_empty());
+        assert_eq!(outcomes.invalid.len(), 1);
+    }
+
+    #[test]
+    fn only_report_links_after_enough_consecutive_failures() {
+        let server = respond_with(test_server::response(
+            "503 Service Unavailable",
+            &[],
+            "",
+        ));
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            consecutive_failures_threshold: 2,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+
+        // the first failure is suppressed
+        let first_run = context(&cfg, &files, &links);
+        let first = run(validate_all(links.clone(), &first_run)).outcomes;
+        assert_eq!(first.valid.len(), 1);
+        assert!(first.invalid.is_empty());
+
+        // but failing on the next run as well gets it reported
+        let second_run = Context {
+            cache: first_run.cache,
+            ..context(&cfg, &files, &links)
+        };
+        let second = run(validate_all(links, &second_run)).outcomes;
+        assert!(second.valid.is_empty());
+        assert_eq!(second.invalid.len(), 1);
+        assert_eq!(server.request_count(), 2);
+    }
+
+    #[test]
+    fn warn_about_suspiciously_small_pages() {
+        let server = respond_with(test_server::ok());
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            min_body_bytes: Some(1),
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let got = run(validate_all(links, &ctx));
+
+        assert_eq!(got.outcomes.valid.len(), 1);
+        assert_eq!(got.small_bodies.len(), 1);
+        assert_eq!(got.small_bodies[0].body_bytes, 0);
+        let outcome = ValidationOutcome {
+            small_bodies: got.small_bodies,
+            ..Default::default()
+        };
+        let diags = outcome.generate_diagnostics(&files, &Config::default());
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn stop_reading_large_pages_after_max_response_bytes() {
+        const BODY_BYTES: usize = 64 * 1024 * 1024;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let written = Arc::new(AtomicUsize::new(0));
+        let bytes_written = Arc::clone(&written);
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                BODY_BYTES
+            )
+            .unwrap();
+
+            // keep going until the client hangs up
+            let chunk = [b'a'; 64 * 1024];
+            while bytes_written.load(Ordering::SeqCst) < BODY_BYTES {
+                if stream.write_all(&chunk).is_err() {
+                    break;
+                }
+                bytes_written.fetch_add(chunk.len(), Ordering::SeqCst);
+            }
+        });
+        let cfg = Config {
+            follow_web_links: true,
+            min_body_bytes: Some(BODY_BYTES as u64 * 2),
+            max_response_bytes: Some(1024),
+            ..Default::default()
+        };
+
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(&base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let got = run(validate_all(links, &ctx));
+        server.join().unwrap();
+
+        assert_eq!(got.outcomes.valid.len(), 1);
+        // the page was cut short, so we can't say it's too small
+        assert!(got.small_bodies.is_empty());
+        assert!(written.load(Ordering::SeqCst) < BODY_BYTES);
+    }
+
+    #[test]
+    fn warn_about_possible_soft_404s() {
+        let server = respond_with(test_server::response(
+            "200 OK",
+            &[],
+            "<h1>404 Not Found</h1>",
+        ));
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            soft_404_patterns: vec![
+                HashedRegex::new("(?i)page not found").unwrap(),
+                HashedRegex::new("404 Not Found").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let got = run(validate_all(links, &ctx));
+
+        assert_eq!(got.outcomes.valid.len(), 1);
+        assert_eq!(got.soft_404s.len(), 1);
+        assert_eq!(got.soft_404s[0].pattern, "404 Not Found");
+        let outcome = ValidationOutcome {
+            soft_404s: got.soft_404s,
+            ..Default::default()
+        };
+        let diags = outcome.generate_diagnostics(&files, &Config::default());
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity, Severity::Warning);
+        assert!(diags[0].notes[0].contains("soft 404"));
+    }
+
+    #[test]
+    fn empty_pages_are_fine_without_a_minimum_body_size() {
+        let server = respond_with(test_server::ok());
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let got = run(validate_all(links, &ctx));
+
+        assert_eq!(got.outcomes.valid.len(), 1);
+        assert!(got.small_bodies.is_empty());
+    }
+
+    fn check_content_type_of(content_type: &str) -> Outcomes {
+        let server =
+            respond_with(test_server::response("200 OK", &[content_type], ""));
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            expected_content_type: HashMap::from_iter(vec![(
+                HashedRegex::new("/0$").unwrap(),
+                String::from("application/pdf"),
+            )]),
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        run(validate_all(links, &ctx)).outcomes
+    }
+
+    #[test]
+    fn links_must_respond_with_the_expected_content_type() {
+        let got = check_content_type_of("Content-Type: text/html");
+
+        assert!(got.valid.is_empty());
+        assert_eq!(got.invalid.len(), 1);
+        let msg = most_specific_error_message(&got.invalid[0]);
+        assert!(msg
+            .ends_with("to be \"application/pdf\", but it was \"text/html\""));
+    }
+
+    #[test]
+    fn content_type_parameters_are_ignored() {
+        let got =
+            check_content_type_of("Content-Type: Application/PDF; qs=0.9");
+
+        assert_eq!(got.valid.len(), 1);
+        assert!(got.invalid.is_empty());
+    }
+
+    fn check_with(base_url: &str, cfg: Config) -> Outcomes {
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        run(validate_all(links, &ctx)).outcomes
+    }
+
+    fn check_with_mode(base_url: &str, mode: CheckMode) -> Outcomes {
+        let cfg = Config {
+            follow_web_links: true,
+            check_mode: HashMap::from_iter(vec![(
+                HashedRegex::new(".*").unwrap(),
+                mode,
+            )]),
+            ..Default::default()
+        };
+
+        check_with(base_url, cfg)
+    }
+
+    fn requests_sent(head_status: &'static str, cfg: Config) -> Vec<String> {
+        let server = method_server(head_status);
+
+        let got = check_with(server.url(), cfg);
+
+        assert_eq!(got.valid.len(), 1);
+        server
+            .requests()
+            .into_iter()
+            .map(|request| request.method)
+            .collect()
+    }
+
+    #[test]
+    fn links_are_checked_with_a_head_request_by_default() {
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+
+        assert_eq!(requests_sent("200 OK", cfg), vec!["HEAD"]);
+    }
+
+    #[test]
+    fn fall_back_to_get_when_head_requests_arent_supported() {
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+
+        for status in &["405 Method Not Allowed", "501 Not Implemented"] {
+            assert_eq!(
+                requests_sent(status, cfg.clone()),
+                vec!["HEAD", "GET"],
+                "{}",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn always_use_get_skips_the_head_request() {
+        let cfg = Config {
+            follow_web_links: true,
+            always_use_get: true,
+            ..Default::default()
+        };
+
+        assert_eq!(requests_sent("200 OK", cfg), vec!["GET"]);
+    }
+
+    #[test]
+    fn the_check_mode_takes_precedence_over_the_default() {
+        let server = method_server("200 OK");
+
+        let got = check_with_mode(server.url(), CheckMode::Get);
+
+        assert_eq!(got.valid.len(), 1);
+        assert_eq!(server.requests()[0].method, "GET");
+        assert_eq!(server.request_count(), 1);
+    }
+
+    #[test]
+    fn a_get_request_is_needed_to_check_the_body_size() {
+        let cfg = Config {
+            follow_web_links: true,
+            min_body_bytes: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(requests_sent("200 OK", cfg), vec!["GET"]);
+    }
+
+    #[test]
+    fn dns_only_links_never_send_requests() {
+        let server = respond_with(test_server::response(
+            "500 Internal Server Error",
+            &[],
+            "",
+        ));
+        // use the host name instead of the IP address so it actually needs
+        // to be resolved
+        let base_url = server.url().replace("127.0.0.1", "localhost");
+
+        let got = check_with_mode(&base_url, CheckMode::DnsOnly);
+
+        assert_eq!(got.valid.len(), 1);
+        assert_eq!(server.request_count(), 0);
+    }
+
+    #[test]
+    fn dns_only_links_fail_when_the_host_doesnt_resolve() {
+        // the ".invalid" top-level domain is reserved, so it never resolves
+        let got = check_with_mode(
+            "http://does-not-exist.invalid/",
+            CheckMode::DnsOnly,
+        );
+
+        assert_eq!(got.invalid.len(), 1);
+        let msg = most_specific_error_message(&got.invalid[0]);
+        assert!(msg.starts_with(
+            "Unable to resolve the host for http://does-not-exist.invalid/0"
+        ));
+    }
+
+    /// Fails with a `500 Internal Server Error` the first time, then responds
+    /// with `200 OK`.
+    #[derive(Debug, Default)]
+    struct FlakyServer {
+        requests: AtomicUsize,
+    }
+
+    impl HttpResponder for FlakyServer {
+        fn respond(&self, _url: &Url) -> (u16, Vec<u8>) {
+            match self.requests.fetch_add(1, Ordering::SeqCst) {
+                0 => (500, Vec::new()),
+                _ => (200, b"Hello, World!".to_vec()),
+            }
+        }
+    }
+
+    fn check_flaky_link(retries: u32) -> (Outcomes, usize) {
+        let cfg = Config {
+            follow_web_links: true,
+            retries,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to("https://example.invalid/", 1, file);
+        let responder = Arc::new(FlakyServer::default());
+        let ctx = Context {
+            responder: Some(Arc::clone(&responder) as Arc<dyn HttpResponder>),
+            ..context(&cfg, &files, &links)
+        };
+
+        let outcomes = run(validate_all(links, &ctx)).outcomes;
+
+        (outcomes, responder.requests.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn retry_server_errors() {
+        let (outcomes, requests) = check_flaky_link(1);
+
+        assert_eq!(outcomes.valid.len(), 1);
+        assert!(outcomes.invalid.is_empty());
+        assert_eq!(requests, 2);
+    }
+
+    #[test]
+    fn server_errors_are_reported_without_retries() {
+        let (outcomes, requests) = check_flaky_link(0);
+
+        assert!(outcomes.valid.is_empty());
+        assert_eq!(outcomes.invalid.len(), 1);
+        assert_eq!(requests, 1);
+    }
+
+    /// Always responds with `200 OK`, remembering which URLs were requested.
+    #[derive(Debug, Default)]
+    struct RecordingServer {
+        requested: Mutex<Vec<Url>>,
+    }
+
+    impl HttpResponder for RecordingServer {
+        fn respond(&self, url: &Url) -> (u16, Vec<u8>) {
+            self.requested.lock().unwrap().push(url.clone());
+            (200, b"Hello, World!".to_vec())
+        }
+    }
+
+    #[test]
+    fn protocol_relative_links_are_checked_using_https() {
+        let cfg = Config {
+            follow_web_links: true,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = vec![Link::new(
+            "//cdn.example.invalid/lib.js",
+            Span::default(),
+            file,
+        )];
+        let responder = Arc::new(RecordingServer::default());
+        let ctx = Context {
+            responder: Some(Arc::clone(&responder) as Arc<dyn HttpResponder>),
+            ..context(&cfg, &files, &links)
+        };
+
+        let outcomes = run(validate_all(links, &ctx)).outcomes;
+
+        assert_eq!(outcomes.valid.len(), 1);
+        assert_eq!(
+            *responder.requested.lock().unwrap(),
+            vec![Url::parse("https://cdn.example.invalid/lib.js").unwrap()]
+        );
+    }
+
+    #[test]
+    fn protocol_relative_links_are_ignored_when_not_following_web_links() {
+        let src = "Load [the library](//cdn.example.invalid/lib.js)";
+
+        let outcome = crate::check_markdown(src, &Config::default()).unwrap();
+
+        assert_eq!(outcome.ignored.len(), 1);
+        assert!(outcome.unknown_category.is_empty());
+        assert!(outcome.valid_links.is_empty());
+    }
+
+    fn check_rate_limited_link(retry_after: &str) -> (Outcomes, usize) {
+        let retry_after = format!("Retry-After: {}", retry_after);
+        let server = respond_in_turn(vec![
+            test_server::response("429 Too Many Requests", &[&retry_after], ""),
+            test_server::ok(),
+        ]);
+        let base_url = server.url();
+        let cfg = Config {
+            follow_web_links: true,
+            max_retry_after: 5,
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 1, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let outcomes = run(validate_all(links, &ctx)).outcomes;
+
+        (outcomes, server.request_count())
+    }
+
+    #[test]
+    fn wait_as_long_as_the_server_asks_before_retrying() {
+        let started = Instant::now();
+
+        let (outcomes, requests) = check_rate_limited_link("1");
+
+        assert_eq!(outcomes.valid.len(), 1);
+        assert!(outcomes.invalid.is_empty());
+        assert_eq!(requests, 2);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn retry_after_longer_than_the_maximum_is_an_error() {
+        let (outcomes, requests) = check_rate_limited_link("120");
+
+        assert!(outcomes.valid.is_empty());
+        assert_eq!(outcomes.invalid.len(), 1);
+        assert_eq!(requests, 1);
+        let msg = most_specific_error_message(&outcomes.invalid[0]);
+        assert!(
+            msg.contains("Server returned 429 Too Many Requests"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("delay of 120s"), "{}", msg);
+    }
+
+    #[test]
+    fn parse_both_forms_of_retry_after() {
+        let now =
+            httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        let inputs = vec![
+            ("120", Some(Duration::from_secs(120))),
+            (" 0 ", Some(Duration::from_secs(0))),
+            (
+                "Wed, 21 Oct 2015 07:29:30 GMT",
+                Some(Duration::from_secs(90)),
+            ),
+            (
+                "Wed, 21 Oct 2015 07:00:00 GMT",
+                Some(Duration::from_secs(0)),
+            ),
+            ("soon", None),
+        ];
+
+        for (value, should_be) in inputs {
+            let mut headers = HeaderMap::new();
+            headers.insert(RETRY_AFTER, value.parse().unwrap());
+
+            assert_eq!(retry_after(&headers, now), should_be, "{}", value);
+        }
+        assert_eq!(retry_after(&HeaderMap::new(), now), None);
+    }
+
+    #[test]
+    fn detect_links_to_private_networks() {
+        let inputs = vec![
+            ("http://localhost:8080/", false),
+            ("http://docs.LOCALHOST/", true),
+            ("http://127.0.0.1/", true),
+            ("http://10.1.2.3/", true),
+            ("http://172.16.0.1/", true),
+            ("http://192.168.1.1/", true),
+            ("http://169.254.0.1/", true),
+            ("http://[::1]/", true),
+            ("http://[fe80::1]/", true),
+            ("http://[fd00::1]/", true),
+            ("https://example.com/", false),
+            ("https://8.8.8.8/", false),
+            ("https://172.32.0.1/", false),
+            ("https://[2001:db8::1]/", false),
+        ];
+
+        for (url, should_be) in inputs {
+            let url: Url = url.parse().unwrap();
+            assert_eq!(is_private_network(&url), should_be, "{}", url);
+        }
+    }
+
+    #[test]
+    fn resolve_hosts_to_detect_private_networks() {
+        // `localhost` is just a name, so we need to look it up to see that
+        // it points somewhere private
+        let url: Url = "http://localhost:8080/".parse().unwrap();
+        assert!(!is_private_network(&url));
+        assert!(run(resolves_to_private_network(&url)));
+
+        let url: Url = "http://127.0.0.1/".parse().unwrap();
+        assert!(run(resolves_to_private_network(&url)));
+
+        // hosts which can't be resolved are checked as normal
+        let url: Url = "http://does-not-exist.invalid/".parse().unwrap();
+        assert!(!run(resolves_to_private_network(&url)));
+    }
+
+    fn is_cached_failure(ctx: &Context<'_>, base_url: &str) -> bool {
+        let cache = ctx.lock_cache();
+        let entry = cache.lookup(&format!("{}0", base_url)).unwrap();
+
+        !entry.successful
+            && entry.status == Some(404)
+            && entry.reason.as_deref() == Some("Not Found")
+    }
+
+    #[test]
+    fn take_turns_using_each_user_agent() {
+        let server = TestServer::start(|_| test_server::ok());
+        let base_url = server.url();
+        std::env::set_var("LINKCHECK_BROWSER_VERSION", "42");
+        let cfg = Config {
+            follow_web_links: true,
+            max_concurrency: 1,
+            user_agents: vec![
+                String::from("first"),
+                String::from("second/$LINKCHECK_BROWSER_VERSION"),
+            ],
+            ..Default::default()
+        };
+        let mut files = Files::new();
+        let file = files.add("index.md", String::new());
+        let links = links_to(base_url, 4, file);
+        let ctx = context(&cfg, &files, &links);
+
+        let outcomes = run(validate_all(links, &ctx)).outcomes;
+
+        assert_eq!(outcomes.valid.len(), 4);
+        let mut got: Vec<_> = server
+            .requests()
+            .iter()
+            .filter_map(|request| request.header("User-Agent"))
+            .map(String::from)
+            .collect();
+        got.sort();
+        assert_eq!(got, vec!["first", "first", "second/42", "second/42"]);
+    }
+
+    /// Send a request which is expected to fail before getting a response.
+    fn request_error(url: &str) -> reqwest::Error {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        // the timeout needs to be set up inside the runtime
+        run(async { client.get(url).send().await }).unwrap_err()
+    }
+
+    #[test]
+    fn describe_connection_refused_errors() {
+        // nothing should be listening on port 1
+        let error = request_error("http://127.0.0.1:1/");
+
+        assert_eq!(
+            describe_request_error(&error),
+            "Connection refused by \"127.0.0.1:1\""
+        );
+    }
+
+    #[test]
+    fn describe_dns_errors() {
+        let error = request_error("http://does-not-exist.invalid/");
+
+        assert_eq!(
+            describe_request_error(&error),
+            "Could not resolve host \"does-not-exist.invalid\""
+        );
+    }
+
+    #[test]
+    fn other_connection_errors_use_the_original_message() {
+        // a plain HTTP response isn't a valid TLS handshake
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("https://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let _ = stream.unwrap().write_all(b"HTTP/1.1 200 OK\r\n\r\n");
+            }
+        });
+
+        let error = request_error(&url);
+
+        assert_eq!(describe_request_error(&error), error.to_string());
+    }
+
+    #[test]
+    fn describe_timeouts() {
+        let server = TestServer::unresponsive();
+
+        let error = request_error(server.url());
+
+        assert_eq!(
+            describe_request_error(&error),
+            format!("Timed out waiting for a response from {}", server.url())
+        );
+    }
+}
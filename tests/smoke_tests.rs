@@ -1,138 +1,1546 @@
 #[macro_use]
 extern crate pretty_assertions;
 
+#[path = "../src/test_server.rs"]
+mod test_server;
+
 use anyhow::Error;
 use codespan::{FileId, Files};
-use linkcheck::validation::{Cache, Reason};
+use codespan_reporting::{diagnostic::Severity, term::termcolor::ColorChoice};
+use linkcheck::validation::Reason;
 use mdbook::{
-    renderer::{RenderContext, Renderer},
+    book::{load_book, Book, Chapter},
+    renderer::{CmdRenderer, RenderContext, Renderer},
     MDBook,
 };
-use mdbook_linkcheck::{Config, HashedRegex, ValidationOutcome, WarningPolicy};
+use mdbook_linkcheck::{
+    Cache, CacheEntry, Config, HashedRegex, Issue, LatexDelimiter,
+    LatexSupport, LinkCounts, LinkScope, Report, ReportFormat, RunOptions,
+    ValidationOutcome, WarningPolicy, WarningPolicyOverrides,
+};
 use std::{
     cell::Cell,
     collections::HashMap,
     convert::TryInto,
     iter::FromIterator,
     path::{Path, PathBuf},
+    process::{Command, Stdio},
 };
+use test_server::TestServer;
 
 fn test_dir() -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
 }
 
 #[test]
-fn check_all_links_in_a_valid_book() {
-    let root = test_dir().join("all-green");
-    let expected_valid = &[
-        "../chapter_1.md",
-        "../chapter_1.md#Subheading",
-        "./chapter_1.html",
-        "./chapter_1.md",
-        "./sibling.md",
-        "/chapter_1.md",
-        "/chapter_1.md#Subheading",
-        "https://crates.io/crates/mdbook-linkcheck",
-        "https://www.google.com/",
-        "nested/",
-        "nested/README.md",
-        "sibling.md",
-    ];
+fn check_all_links_in_a_valid_book() {
+    let root = test_dir().join("all-green");
+    let expected_valid = &[
+        "../chapter_1.md",
+        "../chapter_1.md#Subheading",
+        "./chapter_1.html",
+        "./chapter_1.md",
+        "./sibling.md",
+        "/chapter_1.md",
+        "/chapter_1.md#Subheading",
+        "https://crates.io/crates/mdbook-linkcheck",
+        "https://www.google.com/",
+        "nested/",
+        "nested/README.md",
+        "sibling.md",
+    ];
+
+    let output = run_link_checker(&root).unwrap();
+
+    let valid_links: Vec<_> = output
+        .valid_links
+        .iter()
+        .map(|link| link.href.to_string())
+        .collect();
+    assert_same_links(expected_valid, valid_links);
+    assert!(
+        output.invalid_links.is_empty(),
+        "Found invalid links: {:?}",
+        output.invalid_links
+    );
+}
+
+#[test]
+fn correctly_find_broken_links() {
+    let root = test_dir().join("broken-links");
+    let expected = &[
+        "./foo/bar/baz.html",
+        "../../../../../../../../../../../../etc/shadow",
+        "./asdf.png",
+        "./chapter_1.md",
+        "./second/directory.md",
+        "http://this-doesnt-exist.com.au.nz.us/",
+        "latex_with_latex_support_disabled",
+        "sibling.md",
+    ];
+
+    let output = run_link_checker(&root).unwrap();
+
+    let broken: Vec<_> = output
+        .invalid_links
+        .iter()
+        .chain(&output.invalid_images)
+        .map(|invalid| invalid.link.href.to_string())
+        .collect();
+    assert_same_links(broken, expected);
+    // we also have three incomplete link (one normal, one latex)
+    assert_eq!(output.incomplete_links.len(), 2);
+    assert_eq!(output.incomplete_links[0].reference, "incomplete link");
+    assert_eq!(output.incomplete_links[1].reference, "math_var");
+}
+
+#[test]
+fn only_check_internal_links() {
+    let root = test_dir().join("broken-links");
+
+    let got = TestRun::new(root)
+        .with_scope(LinkScope::Internal)
+        .execute()
+        .unwrap();
+
+    let checked = got
+        .valid_links
+        .iter()
+        .chain(got.invalid_links.iter().map(|invalid| &invalid.link));
+    assert!(checked.clone().count() > 0);
+    assert!(checked.into_iter().all(|link| !is_web_link(&link.href)));
+    let ignored: Vec<_> =
+        got.ignored.iter().map(|link| link.href.as_str()).collect();
+    assert_same_links(
+        ignored,
+        vec![
+            "http://this-doesnt-exist.com.au.nz.us/",
+            "https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues/3#issuecomment-417400242",
+        ],
+    );
+}
+
+#[test]
+fn only_check_external_links() {
+    let root = test_dir().join("broken-links");
+
+    let got = TestRun::new(root)
+        .with_scope(LinkScope::External)
+        .execute()
+        .unwrap();
+
+    let checked: Vec<_> = got
+        .valid_links
+        .iter()
+        .chain(got.invalid_links.iter().map(|invalid| &invalid.link))
+        .map(|link| link.href.as_str())
+        .collect();
+    assert_same_links(
+        checked,
+        vec![
+            "http://this-doesnt-exist.com.au.nz.us/",
+            "https://github.com/Michael-F-Bryan/mdbook-linkcheck/issues/3#issuecomment-417400242",
+        ],
+    );
+    // file system checks were skipped, so nothing is missing from SUMMARY.md
+    assert!(got.ignored.iter().any(|link| link.href == "sibling.md"));
+    assert!(got.absolute_fs_paths.is_empty());
+}
+
+fn is_web_link(href: &str) -> bool { href.starts_with("http") }
+
+#[test]
+fn count_the_links_in_each_category() {
+    let root = test_dir().join("broken-links");
+
+    // web links are ignored so the counts don't depend on the network
+    let got = run_link_checker_with_config(&root, Config::default())
+        .unwrap()
+        .counts();
+
+    assert_eq!(
+        got,
+        LinkCounts {
+            total: 12,
+            valid: 3,
+            ignored: 2,
+            broken: 7,
+            incomplete: 2,
+        }
+    );
+    assert_eq!(
+        got.to_string(),
+        "Checked 12 links: 3 valid, 2 ignored, 7 broken, 2 incomplete"
+    );
+}
+
+#[test]
+fn correctly_find_links_with_latex() {
+    let root = test_dir().join("latex-support-links");
+    let expected = &[
+        "./foo/bar/baz.html",
+        "../../../../../../../../../../../../etc/shadow",
+        "./asdf.png",
+        "http://this-doesnt-exist.com.au.nz.us/",
+        "sibling.md",
+        "first_broken_link_nonlatex",
+        "second_broken_link_nonlatex",
+    ];
+
+    let config = Config {
+        follow_web_links: true,
+        latex_support: LatexSupport::Enabled(true),
+        ..Default::default()
+    };
+    let output = run_link_checker_with_config(&root, config).unwrap();
+
+    let broken: Vec<_> = output
+        .invalid_links
+        .iter()
+        .chain(&output.invalid_images)
+        .map(|invalid| invalid.link.href.to_string())
+        .collect();
+    assert_same_links(broken, expected);
+
+    // we also have two incomplete link
+    assert_eq!(output.incomplete_links.len(), 2);
+    assert_eq!(
+        output.incomplete_links[0].reference,
+        "this_incomplete_link_inside_nonlatex"
+    );
+    assert_eq!(output.incomplete_links[1].reference, "incomplete link");
+}
+
+#[test]
+fn remove_latex_using_custom_delimiters() {
+    let root = test_dir().join("latex-custom-delimiters");
+    let broken_links = |latex_support| {
+        let config = Config {
+            latex_support,
+            ..Default::default()
+        };
+        let output = run_link_checker_with_config(&root, config).unwrap();
+        let broken: Vec<_> = output
+            .invalid_links
+            .iter()
+            .map(|invalid| invalid.link.href.to_string())
+            .collect();
+        (broken, output.incomplete_links.len())
+    };
+
+    // the usual delimiters don't know about these fragments
+    let (broken, incomplete) = broken_links(LatexSupport::Enabled(true));
+    assert_same_links(broken, vec!["n", "b", "./missing.md"]);
+    assert!(incomplete > 0);
+
+    let custom = LatexSupport::Custom {
+        delimiters: vec![
+            LatexDelimiter::Pair {
+                start: String::from(r"\begin{equation}"),
+                end: String::from(r"\end{equation}"),
+            },
+            LatexDelimiter::Regex {
+                regex: HashedRegex::new(r"math!\{[^}]*\}").unwrap(),
+            },
+        ],
+    };
+    let (broken, incomplete) = broken_links(custom);
+    assert_same_links(broken, vec!["./missing.md"]);
+    assert_eq!(incomplete, 0);
+}
+
+#[test]
+fn detect_when_a_linked_file_isnt_in_summary_md() {
+    let root = test_dir().join("broken-links");
+
+    let output = run_link_checker(&root).unwrap();
+
+    let broken_link = output
+        .invalid_links
+        .iter()
+        .find(|invalid| invalid.link.href == "sibling.md")
+        .unwrap();
+
+    assert!(is_specific_error::<mdbook_linkcheck::NotInSummary>(
+        &broken_link.reason
+    ));
+}
+
+#[test]
+fn iterate_over_the_issues_in_a_broken_book() {
+    fn issues(root: &Path) -> Vec<(&'static str, String)> {
+        let output = run_link_checker(root).unwrap();
+        let mut issues = Vec::new();
+
+        output.for_each_issue(|issue| {
+            let kind = match issue {
+                Issue::BrokenLink(_) => "broken",
+                Issue::Incomplete(_) => "incomplete",
+                Issue::NotInSummary(_) => "not in summary",
+                Issue::AbsoluteLink(_) => "absolute",
+            };
+            issues.push((kind, issue.details().href.clone()));
+        });
+
+        issues
+    }
+
+    let got = issues(&test_dir().join("broken-links"));
+    for expected in &[
+        ("broken", "./foo/bar/baz.html"),
+        ("broken", "./asdf.png"),
+        ("incomplete", "incomplete link"),
+        ("not in summary", "sibling.md"),
+    ] {
+        assert!(
+            got.iter()
+                .any(|(kind, href)| (*kind, href.as_str()) == *expected),
+            "{:?} not in {:?}",
+            expected,
+            got
+        );
+    }
+    assert!(got.iter().all(|(kind, _)| *kind != "absolute"));
+
+    let got = issues(&test_dir().join("absolute-links"));
+    assert_eq!(
+        got,
+        vec![
+            ("absolute", String::from("/chapter_1.md")),
+            ("absolute", String::from("/nested/README.md")),
+            ("absolute", String::from("/chapter_1.md")),
+        ]
+    );
+}
+
+#[test]
+fn link_to_chapters_with_other_markdown_extensions() {
+    let root = test_dir().join("markdown-extensions");
+    let config = Config {
+        markdown_extensions: vec![String::from("md"), String::from("markdown")],
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(root, config)
+        .after_validation(|_, outcome, _| {
+            let valid: Vec<_> = outcome
+                .valid_links
+                .iter()
+                .map(|link| link.href.as_str())
+                .collect();
+            assert_same_links(
+                valid,
+                vec![
+                    "./appendix.markdown",
+                    "./appendix.html",
+                    "./chapter_1.md",
+                ],
+            );
+            assert_eq!(outcome.invalid_links.len(), 1);
+            let not_in_summary = &outcome.invalid_links[0];
+            assert_eq!(not_in_summary.link.href, "./notes.markdown");
+            assert!(is_specific_error::<mdbook_linkcheck::NotInSummary>(
+                &not_in_summary.reason
+            ));
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn generated_chapters_can_be_treated_as_part_of_the_book() {
+    let root = test_dir().join("generated-chapters");
+
+    let output =
+        run_link_checker_with_config(&root, Config::default()).unwrap();
+    assert_eq!(output.invalid_links.len(), 1);
+    assert!(is_specific_error::<mdbook_linkcheck::NotInSummary>(
+        &output.invalid_links[0].reason
+    ));
+
+    let config = Config {
+        additional_summary_files: vec![String::from("api/*.md")],
+        ..Default::default()
+    };
+    let output = run_link_checker_with_config(&root, config).unwrap();
+    assert!(
+        output.invalid_links.is_empty(),
+        "{:?}",
+        output.invalid_links
+    );
+    assert_eq!(output.valid_links.len(), 1);
+    assert_eq!(output.valid_links[0].href, "./api/reference.md");
+}
+
+#[test]
+fn suggest_where_to_add_a_missing_chapter_to_summary_md() {
+    let root = test_dir().join("not-in-summary");
+
+    TestRun::new_with_config(root, Config::default())
+        .after_validation(|files, outcome, _| {
+            let diags = outcome.generate_diagnostics(
+                files,
+                &Config {
+                    warning_policy: WarningPolicy::Error,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(diags.len(), 1);
+
+            let diag = &diags[0];
+            let summary = outcome.summary.unwrap();
+            let related = diag
+                .labels
+                .iter()
+                .find(|label| label.file_id == summary)
+                .unwrap();
+            assert_eq!(
+                &files.source(summary)[related.range.clone()],
+                "[Installation](./guide/installation.md)"
+            );
+            assert!(
+                diag.notes.iter().any(|note| note.contains(
+                    "to SUMMARY.md, perhaps next to \"guide/installation.md\""
+                )),
+                "{:?}",
+                diag
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn warn_about_duplicate_link_reference_definitions() {
+    let root = test_dir().join("duplicate-definitions");
+
+    TestRun::new_with_config(root, Config::default())
+        .after_validation(|files, outcome, _| {
+            assert!(outcome.invalid_links.is_empty());
+            assert_eq!(outcome.duplicate_definitions.len(), 1);
+            let duplicate = &outcome.duplicate_definitions[0];
+            assert_eq!(duplicate.reference, "Rust");
+            let src = files.source(duplicate.file);
+            let original: std::ops::Range<usize> = duplicate.original.into();
+            let redefined: std::ops::Range<usize> = duplicate.duplicate.into();
+            assert_eq!(&src[original], "[rust]: https://www.rust-lang.org/");
+            assert_eq!(&src[redefined], "[Rust]: https://doc.rust-lang.org/");
+
+            let diags = outcome.generate_diagnostics(files, &Config::default());
+            assert_eq!(diags.len(), 1);
+            assert_eq!(diags[0].severity, Severity::Warning);
+            assert_eq!(diags[0].labels.len(), 2);
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn only_check_urls_in_code_blocks_when_asked() {
+    let root = test_dir().join("code-blocks");
+    let config = Config {
+        check_code_blocks: true,
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(&root, config)
+        .after_validation(|files, outcome, _| {
+            let links: Vec<_> = outcome
+                .ignored
+                .iter()
+                .map(|link| {
+                    let span: std::ops::Range<usize> = link.span.into();
+                    (link.href.as_str(), &files.source(link.file)[span])
+                })
+                .collect();
+            assert_eq!(
+                links,
+                vec![
+                    (
+                        "https://example.com/api/v1",
+                        "https://example.com/api/v1"
+                    ),
+                    (
+                        "https://github.com/rust-lang/rust/issues/42",
+                        "https://github.com/rust-lang/rust/issues/42"
+                    ),
+                ]
+            );
+        })
+        .execute()
+        .unwrap();
+
+    let output =
+        run_link_checker_with_config(&root, Config::default()).unwrap();
+    assert!(output.ignored.is_empty());
+}
+
+#[test]
+fn only_check_urls_in_link_titles_when_asked() {
+    let root = test_dir().join("link-titles");
+    let config = Config {
+        check_titles: true,
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(&root, config)
+        .after_validation(|files, outcome, _| {
+            let links: Vec<_> = outcome
+                .ignored
+                .iter()
+                .map(|link| {
+                    let span: std::ops::Range<usize> = link.span.into();
+                    (link.href.as_str(), &files.source(link.file)[span])
+                })
+                .collect();
+            assert_eq!(
+                links,
+                vec![
+                    (
+                        "https://example.com/install",
+                        "https://example.com/install"
+                    ),
+                    // the title is in the reference definition, so the whole
+                    // link is used instead
+                    ("https://example.com/faq", "[FAQ][faq]"),
+                ]
+            );
+        })
+        .execute()
+        .unwrap();
+
+    let output =
+        run_link_checker_with_config(&root, Config::default()).unwrap();
+    assert!(output.ignored.is_empty());
+}
+
+#[test]
+fn fragments_can_point_to_generated_anchors() {
+    let root = test_dir().join("generated-anchors");
+    let config = Config {
+        check_fragments: true,
+        generated_anchors: HashMap::from_iter(vec![(
+            PathBuf::from("glossary.md"),
+            vec![String::from("rust")],
+        )]),
+        ..Default::default()
+    };
+
+    let output = run_link_checker_with_config(&root, config).unwrap();
+
+    let valid: Vec<_> = output
+        .valid_links
+        .iter()
+        .map(|link| link.href.as_str())
+        .collect();
+    assert_eq!(valid, vec!["./glossary.md#rust", "./glossary.md#glossary"]);
+    let broken: Vec<_> = output
+        .invalid_links
+        .iter()
+        .map(|invalid| invalid.link.href.as_str())
+        .collect();
+    assert_eq!(broken, vec!["./glossary.md#cargo"]);
+}
+
+#[test]
+fn broken_links_can_be_written_for_reviewdog() {
+    let root = test_dir().join("broken-links");
+
+    TestRun::new_with_config(root, Config::default())
+        .after_validation(|files, outcome, _| {
+            let diags =
+                outcome.generate_diagnostics(files, &Config::default());
+            let report = Report::from_diagnostics(files, &diags)
+                .with_prefix(Path::new("src"));
+            let mut buffer = Vec::new();
+            report.write(&mut buffer, ReportFormat::Rdjson).unwrap();
+            let rdjson: serde_json::Value =
+                serde_json::from_slice(&buffer).unwrap();
+
+            assert_eq!(rdjson["source"]["name"], "mdbook-linkcheck");
+            let missing_image = rdjson["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|diag| diag["message"] == "File not found: ./asdf.png")
+                .unwrap();
+            assert_eq!(missing_image["severity"], "ERROR");
+            assert_eq!(
+                missing_image["location"],
+                serde_json::json!({
+                    "path": Path::new("src").join("chapter_1.md").display().to_string(),
+                    "range": { "start": { "line": 15, "column": 1 } },
+                })
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn broken_links_can_be_written_as_github_annotations() {
+    let root = test_dir().join("broken-links");
+
+    TestRun::new_with_config(root, Config::default())
+        .after_validation(|files, outcome, _| {
+            let diags = outcome.generate_diagnostics(files, &Config::default());
+            let report = Report::from_diagnostics(files, &diags)
+                .with_prefix(Path::new("src"));
+            let mut buffer = Vec::new();
+            report.write(&mut buffer, ReportFormat::Github).unwrap();
+            let annotations = String::from_utf8(buffer).unwrap();
+
+            let expected = format!(
+                "::error file={},line=15,col=1::File not found: ./asdf.png",
+                Path::new("src").join("chapter_1.md").display()
+            );
+            assert!(
+                annotations.lines().any(|line| line == expected),
+                "{}",
+                annotations
+            );
+            assert!(annotations.lines().all(|line| line.starts_with("::")));
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn report_broken_links_in_summary_md() {
+    let root = test_dir().join("broken-summary");
+    let temp = tempfile::tempdir().unwrap();
+    let report_file = temp.path().join("report.json");
+    let config = mdbook::Config::from_disk(root.join("book.toml")).unwrap();
+    // mdbook refuses to load a book when SUMMARY.md points at a missing file,
+    // so only add the chapter which exists
+    let mut book = Book::new();
+    book.push_item(Chapter::new(
+        "Chapter 1",
+        std::fs::read_to_string(root.join("src").join("chapter_1.md")).unwrap(),
+        "chapter_1.md",
+        Vec::new(),
+    ));
+    let ctx = RenderContext::new(&root, book, config, temp.path());
+    let mut options = RunOptions::default();
+    options.colour = ColorChoice::Never;
+    options.report_file = Some(report_file.clone());
+
+    let got = mdbook_linkcheck::run_with_options(&ctx, &options);
+
+    assert!(got.is_err());
+    let report = Report::load(&report_file).unwrap();
+    assert_eq!(report.problems.len(), 1, "{:#?}", report.problems);
+    let problem = &report.problems[0];
+    assert_eq!(problem.file, "SUMMARY.md");
+    assert_eq!(problem.line, 4);
+    assert_eq!(problem.message, "File not found: ./chapter_2.md");
+}
+
+#[test]
+fn warn_when_a_fragment_matches_several_headings() {
+    let root = test_dir().join("ambiguous-fragments");
+    let config = Config {
+        check_fragments: true,
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(root, config)
+        .after_validation(|files, outcome, _| {
+            assert!(outcome.invalid_links.is_empty());
+            assert_eq!(outcome.ambiguous_fragments.len(), 1);
+            let ambiguous = &outcome.ambiguous_fragments[0];
+            assert_eq!(ambiguous.link.href, "./installation.md#setup");
+            assert_eq!(ambiguous.candidates, vec!["setup", "setup-1"]);
+
+            let diags = outcome.generate_diagnostics(files, &Config::default());
+            assert_eq!(diags.len(), 1);
+            assert_eq!(diags[0].severity, Severity::Warning);
+            assert_eq!(
+                diags[0].message,
+                "\"#setup\" matches 2 headings in \"installation.md\""
+            );
+            assert!(diags[0].notes[0].contains("\"#setup-1\""));
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn broken_images_are_reported_separately() {
+    let root = test_dir().join("broken-images");
+    let config = Config {
+        warning_policy_overrides: WarningPolicyOverrides {
+            broken_image: Some(WarningPolicy::Warn),
+            ..Default::default()
+        },
+        warning_policy: WarningPolicy::Error,
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(root, config.clone())
+        .after_validation(move |files, outcome, _| {
+            assert_eq!(outcome.invalid_links.len(), 1);
+            assert_eq!(outcome.invalid_links[0].link.href, "./missing.md");
+            assert_eq!(outcome.invalid_images.len(), 1);
+            assert_eq!(
+                outcome.invalid_images[0].link.href,
+                "./img/missing.png"
+            );
+
+            let diags = outcome.generate_diagnostics(files, &config);
+            assert_eq!(diags.len(), 2);
+            assert_eq!(diags[0].severity, Severity::Error);
+            assert_eq!(diags[0].message, "File not found: ./missing.md");
+            assert_eq!(diags[1].severity, Severity::Warning);
+            assert_eq!(diags[1].message, "File not found: ./img/missing.png");
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn broken_images_are_errors_by_default() {
+    let root = test_dir().join("broken-images");
+
+    TestRun::new(root)
+        .after_validation(|files, outcome, _| {
+            let diags = outcome.generate_diagnostics(files, &Config::default());
+            assert_eq!(diags.len(), 2);
+            assert!(diags.iter().all(|diag| diag.severity == Severity::Error));
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn the_summary_md_check_can_be_turned_off() {
+    let root = test_dir().join("not-in-summary");
+    let config = Config {
+        check_included_in_summary: false,
+        ..Default::default()
+    };
+
+    let output = run_link_checker_with_config(&root, config).unwrap();
+
+    assert!(output.invalid_links.is_empty());
+    assert!(output
+        .valid_links
+        .iter()
+        .any(|link| link.href == "./guide/advanced.md"));
+}
+
+#[test]
+fn use_a_custom_message_for_chapters_missing_from_summary_md() {
+    let root = test_dir().join("not-in-summary");
+    let config = Config {
+        not_in_summary_message: Some(String::from(
+            "Add {path} to SUMMARY.md (see CONTRIBUTING.md)",
+        )),
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(root, config)
+        .after_validation(|files, outcome, _| {
+            let diags = outcome.generate_diagnostics(
+                files,
+                &Config {
+                    warning_policy: WarningPolicy::Error,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(diags.len(), 1);
+            assert_eq!(
+                diags[0].message,
+                format!(
+                    "Add {} to SUMMARY.md (see CONTRIBUTING.md)",
+                    Path::new("guide").join("advanced.md").display()
+                )
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn chapters_missing_from_summary_md_can_be_downgraded_to_a_warning() {
+    let root = test_dir().join("not-in-summary");
+    let config = Config {
+        warning_policy_overrides: WarningPolicyOverrides {
+            not_in_summary: Some(WarningPolicy::Warn),
+            ..Default::default()
+        },
+        warning_policy: WarningPolicy::Error,
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(root, config.clone())
+        .after_validation(move |files, outcome, _| {
+            assert_eq!(outcome.invalid_links.len(), 1);
+
+            let diags = outcome.generate_diagnostics(files, &config);
+            assert_eq!(diags.len(), 1);
+            assert_eq!(diags[0].severity, Severity::Warning);
+            assert!(diags[0].message.contains("SUMMARY.md"));
+        })
+        .execute()
+        .unwrap();
+}
+
+fn linkcheck_exit_code(args: &[&str]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_mdbook-linkcheck"))
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap()
+        .code()
+}
+
+#[test]
+fn check_several_books_at_once() {
+    let footnotes = test_dir().join("footnotes");
+    let tables_and_lists = test_dir().join("tables-and-lists");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mdbook-linkcheck"))
+        .arg("--standalone")
+        .arg("--no-cache")
+        .arg("--colour=never")
+        .arg(&footnotes)
+        .arg(&tables_and_lists)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // both books link to a missing guide
+    let missing_guide = stderr
+        .lines()
+        .filter(|line| {
+            line.starts_with("error: File not found: ./missing-guide.md")
+        })
+        .count();
+    assert_eq!(missing_guide, 2, "{}", stderr);
+    assert!(stderr.contains("./missing-size.md"), "{}", stderr);
+
+    // a book which can't be loaded is more serious than broken links
+    let missing = test_dir().join("does-not-exist");
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--standalone",
+            "--no-cache",
+            footnotes.to_str().unwrap(),
+            missing.to_str().unwrap(),
+        ]),
+        Some(2)
+    );
+}
+
+#[test]
+fn books_checked_together_share_their_web_cache() {
+    let server = TestServer::start(|_| test_server::ok());
+    let first = tempfile::tempdir().unwrap();
+    let second = tempfile::tempdir().unwrap();
+    for book in &[&first, &second] {
+        write_web_book(book.path(), server.url(), "Accept: text/html");
+    }
+
+    let code = linkcheck_exit_code(&[
+        "--standalone",
+        first.path().to_str().unwrap(),
+        second.path().to_str().unwrap(),
+    ]);
+
+    assert_eq!(code, Some(0));
+    // the second book reused the first book's result
+    assert_eq!(server.request_count(), 1);
+}
+
+#[test]
+fn books_with_different_web_settings_keep_their_own_cache() {
+    let server = TestServer::start(|_| test_server::ok());
+    let first = tempfile::tempdir().unwrap();
+    let second = tempfile::tempdir().unwrap();
+    write_web_book(first.path(), server.url(), "Accept: text/html");
+    write_web_book(second.path(), server.url(), "Accept: text/plain");
+    let args = [
+        "--standalone",
+        first.path().to_str().unwrap(),
+        second.path().to_str().unwrap(),
+    ];
+
+    assert_eq!(linkcheck_exit_code(&args), Some(0));
+    assert_eq!(server.request_count(), 2);
+
+    // neither book threw away the other's results
+    assert_eq!(linkcheck_exit_code(&args), Some(0));
+    assert_eq!(server.request_count(), 2);
+}
+
+#[test]
+fn exit_codes_distinguish_broken_links_from_other_errors() {
+    let broken_links = test_dir().join("broken-links");
+    let temp = tempfile::tempdir().unwrap();
+    std::fs::create_dir(temp.path().join("src")).unwrap();
+    std::fs::write(temp.path().join("src").join("SUMMARY.md"), "# Summary\n")
+        .unwrap();
+    std::fs::write(
+        temp.path().join("book.toml"),
+        "[output.linkcheck]\nwarning-policy = \"sometimes\"\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--standalone",
+            "--no-cache",
+            broken_links.to_str().unwrap()
+        ]),
+        Some(1)
+    );
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--standalone",
+            "--no-cache",
+            temp.path().to_str().unwrap()
+        ]),
+        Some(2)
+    );
+    assert_eq!(linkcheck_exit_code(&["--format", "yaml"]), Some(2));
+    // it's not obvious whether these should narrow down or add to `--files`
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--standalone",
+            "--files",
+            "chapter_1.md",
+            "--changed-since",
+            "HEAD",
+            broken_links.to_str().unwrap()
+        ]),
+        Some(2)
+    );
+}
+
+#[test]
+fn read_the_render_context_from_a_file() {
+    let root = test_dir().join("broken-links");
+    let temp = tempfile::tempdir().unwrap();
+    let md = MDBook::load(&root).unwrap();
+    let ctx = RenderContext::new(md.root, md.book, md.config, temp.path());
+    let context_file = temp.path().join("context.json");
+    std::fs::write(&context_file, serde_json::to_string(&ctx).unwrap())
+        .unwrap();
+
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--no-cache",
+            "--context",
+            context_file.to_str().unwrap()
+        ]),
+        Some(1)
+    );
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--context",
+            temp.path().join("missing.json").to_str().unwrap()
+        ]),
+        Some(2)
+    );
+}
+
+#[test]
+fn a_clean_book_prints_nothing_when_quiet() {
+    let temp = tempfile::tempdir().unwrap();
+    let src = temp.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    std::fs::write(
+        src.join("SUMMARY.md"),
+        "- [Chapter 1](./chapter_1.md)\n- [Chapter 2](./chapter_2.md)\n",
+    )
+    .unwrap();
+    std::fs::write(src.join("chapter_1.md"), "[next](./chapter_2.md)\n")
+        .unwrap();
+    std::fs::write(src.join("chapter_2.md"), "[previous](./chapter_1.md)\n")
+        .unwrap();
+    std::fs::write(temp.path().join("book.toml"), "[output.linkcheck]\n")
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_mdbook-linkcheck"))
+        .arg("--standalone")
+        .arg("--no-cache")
+        .arg("--quiet")
+        .arg(temp.path())
+        .env("RUST_LOG", "mdbook_linkcheck=info")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn fail_when_too_many_links_are_ignored() {
+    let temp = tempfile::tempdir().unwrap();
+    let src = temp.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    std::fs::write(
+        src.join("SUMMARY.md"),
+        "- [Chapter 1](./chapter_1.md)\n- [Chapter 2](./chapter_2.md)\n",
+    )
+    .unwrap();
+    std::fs::write(
+        src.join("chapter_1.md"),
+        "[next](./chapter_2.md) [guide](./chapter_2.md#guide)\n",
+    )
+    .unwrap();
+    std::fs::write(src.join("chapter_2.md"), "# Guide\n").unwrap();
+    let book_toml = temp.path().join("book.toml");
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_mdbook-linkcheck"))
+            .arg("--standalone")
+            .arg("--no-cache")
+            .arg(temp.path())
+            .output()
+            .unwrap()
+    };
+
+    // the exclude was meant to skip the fragment, but it matches almost every
+    // link (including the ones in SUMMARY.md)
+    std::fs::write(
+        &book_toml,
+        "[output.linkcheck]\nexclude = ['chapter_2']\nmax-ignored-ratio = 0.3\n",
+    )
+    .unwrap();
+    let output = run();
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("3 of 4 links (75%) were ignored"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("max-ignored-ratio = 0.3"), "{}", stderr);
+
+    std::fs::write(
+        &book_toml,
+        "[output.linkcheck]\nexclude = ['#guide$']\nmax-ignored-ratio = 0.5\n",
+    )
+    .unwrap();
+    let output = run();
+
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn exclude_patterns_from_the_command_line_are_added_to_the_config() {
+    let temp = tempfile::tempdir().unwrap();
+    let src = temp.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    std::fs::write(src.join("SUMMARY.md"), "- [Chapter 1](./chapter_1.md)\n")
+        .unwrap();
+    std::fs::write(
+        src.join("chapter_1.md"),
+        "[draft](./drafts/chapter_2.md) and [old](./old/chapter_3.md)\n",
+    )
+    .unwrap();
+    std::fs::write(
+        temp.path().join("book.toml"),
+        "[output.linkcheck]\nexclude = ['^\\./drafts/']\n",
+    )
+    .unwrap();
+    let root = temp.path().to_str().unwrap();
+
+    assert_eq!(
+        linkcheck_exit_code(&["--standalone", "--no-cache", root]),
+        Some(1)
+    );
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--standalone",
+            "--no-cache",
+            "--exclude",
+            "^\\./old/",
+            root,
+        ]),
+        Some(0)
+    );
+}
+
+#[test]
+fn strict_mode_fails_the_build_on_warnings() {
+    let root = test_dir().join("absolute-links");
+    let root = root.to_str().unwrap();
+
+    assert_eq!(
+        linkcheck_exit_code(&["--standalone", "--no-cache", root]),
+        Some(0)
+    );
+    assert_eq!(
+        linkcheck_exit_code(&["--standalone", "--no-cache", "--strict", root]),
+        Some(1)
+    );
+}
+
+#[test]
+fn no_cache_neither_reads_nor_writes_the_cache() {
+    let temp = tempfile::tempdir().unwrap();
+    let src = temp.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    std::fs::write(src.join("SUMMARY.md"), "- [Chapter 1](./chapter_1.md)\n")
+        .unwrap();
+    // nothing is listening on port 1, so this is only valid if the cache is
+    // used
+    let url = "http://127.0.0.1:1/";
+    std::fs::write(src.join("chapter_1.md"), format!("[link]({})\n", url))
+        .unwrap();
+    std::fs::write(
+        temp.path().join("book.toml"),
+        "[output.linkcheck]\nfollow-web-links = true\ncache-file = \"cache.json\"\n",
+    )
+    .unwrap();
+    let cfg = Config {
+        follow_web_links: true,
+        ..Default::default()
+    };
+    let mut cache = Cache::default();
+    cache.set_config_fingerprint(cfg.fingerprint());
+    cache.insert(url, CacheEntry::new(true));
+    let cache_file = temp.path().join("cache.json");
+    let original = serde_json::to_string(&cache).unwrap();
+    std::fs::write(&cache_file, &original).unwrap();
+    let root = temp.path().to_str().unwrap();
+
+    assert_eq!(
+        linkcheck_exit_code(&["--standalone", "--no-cache", root]),
+        Some(1)
+    );
+    assert_eq!(std::fs::read_to_string(&cache_file).unwrap(), original);
+
+    // but the cached result is trusted when caching is enabled
+    assert_eq!(linkcheck_exit_code(&["--standalone", root]), Some(0));
+}
+
+#[test]
+fn links_from_included_files_are_reported_against_the_chapter() {
+    let root = test_dir().join("included-snippets");
+
+    TestRun::new(root)
+        .after_validation(|files, outcome, _| {
+            let broken: Vec<_> = outcome
+                .invalid_links
+                .iter()
+                .map(|invalid| {
+                    let link = &invalid.link;
+                    let location =
+                        files.location(link.file, link.span.start()).unwrap();
+                    (
+                        link.href.as_str(),
+                        files.name(link.file).to_string_lossy().into_owned(),
+                        location.line.number().to_usize(),
+                    )
+                })
+                .collect();
+
+            // the snippet is 6 lines long, so line numbers after it are
+            // shifted by 5 relative to chapter_1.md on disk
+            assert_eq!(
+                broken,
+                vec![
+                    ("./missing-install.md", String::from("chapter_1.md"), 8),
+                    ("./missing-after.md", String::from("chapter_1.md"), 12),
+                ]
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn standalone_mode_expands_includes() {
+    let root = test_dir().join("included-snippets");
+    let temp = tempfile::tempdir().unwrap();
+    let report_file = temp.path().join("report.json");
+
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--standalone",
+            "--no-cache",
+            "--report",
+            report_file.to_str().unwrap(),
+            root.to_str().unwrap(),
+        ]),
+        Some(1)
+    );
+
+    let report = Report::load(&report_file).unwrap();
+    let got: Vec<_> = report
+        .problems
+        .iter()
+        .map(|problem| (problem.file.as_str(), problem.line))
+        .collect();
+    assert_eq!(got, vec![("chapter_1.md", 8), ("chapter_1.md", 12)]);
+}
+
+#[test]
+fn links_are_skipped_when_the_max_duration_is_exceeded() {
+    let server = TestServer::unresponsive();
+    let url = server.url();
+    let temp = tempfile::tempdir().unwrap();
+    let src = temp.path().join("src");
+    std::fs::create_dir(&src).unwrap();
+    std::fs::write(src.join("SUMMARY.md"), "- [Chapter 1](./chapter_1.md)\n")
+        .unwrap();
+    std::fs::write(
+        src.join("chapter_1.md"),
+        format!("[slow]({})\n\n[missing](./missing.md)\n", url),
+    )
+    .unwrap();
+    std::fs::write(
+        temp.path().join("book.toml"),
+        "[output.linkcheck]\nfollow-web-links = true\n",
+    )
+    .unwrap();
+    let report_file = temp.path().join("report.json");
+
+    assert_eq!(
+        linkcheck_exit_code(&[
+            "--standalone",
+            "--no-cache",
+            "--max-duration",
+            "1",
+            "--report",
+            report_file.to_str().unwrap(),
+            temp.path().to_str().unwrap(),
+        ]),
+        Some(4)
+    );
+
+    // local links were still checked, but the web link is only skipped
+    let report = Report::load(&report_file).unwrap();
+    let got: Vec<_> = report
+        .problems
+        .iter()
+        .map(|problem| (problem.severity.as_str(), problem.message.clone()))
+        .collect();
+    assert_eq!(
+        got,
+        vec![
+            ("warning", format!("\"{}\" was skipped due to timeout", url)),
+            ("error", String::from("File not found: ./missing.md")),
+        ]
+    );
+}
+
+#[test]
+fn absolute_links_within_the_site_url_are_resolved_against_the_book() {
+    let root = test_dir().join("site-url");
+    let config = Config {
+        site_url: Some(String::from("https://example.com/docs/")),
+        ..Default::default()
+    };
+
+    TestRun::new_with_config(root, config)
+        .after_validation(|files, outcome, _| {
+            let valid: Vec<_> = outcome
+                .valid_links
+                .iter()
+                .map(|link| link.href.as_str())
+                .collect();
+            assert_same_links(
+                valid,
+                vec!["/docs/chapter_2.md", "/chapter_2.md"],
+            );
+            assert_eq!(outcome.invalid_links.len(), 1);
+            assert_eq!(outcome.invalid_links[0].link.href, "/docs/missing.md");
+
+            // only the link outside the site is warned about
+            let diags = outcome.generate_diagnostics(files, &Config::default());
+            let absolute_link_warnings: Vec<_> = diags
+                .iter()
+                .filter(|diag| {
+                    diag.message == "Absolute link should be made relative"
+                })
+                .collect();
+            assert_eq!(absolute_link_warnings.len(), 1);
+            let span = absolute_link_warnings[0].labels[0].range.clone();
+            assert_eq!(
+                &files.source(outcome.valid_links[0].file)[span],
+                "[this](/chapter_2.md)"
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn absolute_links_are_warned_about_without_a_site_url() {
+    let root = test_dir().join("site-url");
+
+    TestRun::new_with_config(root, Config::default())
+        .after_validation(|files, outcome, _| {
+            let diags = outcome.generate_diagnostics(files, &Config::default());
+            let absolute_link_warnings = diags
+                .iter()
+                .filter(|diag| {
+                    diag.message == "Absolute link should be made relative"
+                })
+                .count();
+            assert_eq!(absolute_link_warnings, 1);
+            let broken: Vec<_> = outcome
+                .invalid_links
+                .iter()
+                .map(|invalid| invalid.link.href.as_str())
+                .collect();
+            assert_same_links(
+                broken,
+                vec!["/docs/chapter_2.md", "/docs/missing.md"],
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn only_check_the_selected_files() {
+    let root = test_dir().join("broken-links");
+    let temp = tempfile::tempdir().unwrap();
+    let report_file = temp.path().join("report.json");
+    let md = MDBook::load(&root).unwrap();
+    let ctx = RenderContext::new(md.root, md.book, md.config, temp.path());
+    let mut options = RunOptions::default();
+    options.colour = ColorChoice::Never;
+    options.selected_files = Some(vec![Path::new("deeply")
+        .join("nested")
+        .join("index.md")
+        .display()
+        .to_string()]);
+    options.report_file = Some(report_file.clone());
 
-    let output = run_link_checker(&root).unwrap();
+    let got = mdbook_linkcheck::run_with_options(&ctx, &options);
 
-    let valid_links: Vec<_> = output
-        .valid_links
+    assert!(got.is_err());
+    let report = Report::load(&report_file).unwrap();
+    let messages: Vec<_> = report
+        .problems
         .iter()
-        .map(|link| link.href.to_string())
+        .map(|problem| problem.message.as_str())
         .collect();
-    assert_same_links(expected_valid, valid_links);
-    assert!(
-        output.invalid_links.is_empty(),
-        "Found invalid links: {:?}",
-        output.invalid_links
+    // links to chapters which weren't selected are still part of the book
+    assert_eq!(
+        messages,
+        vec![
+            "File not found: ./chapter_1.md",
+            "File not found: ./second/directory.md",
+        ]
     );
+    assert!(report
+        .problems
+        .iter()
+        .all(|problem| problem.file.ends_with("index.md")));
 }
 
 #[test]
-fn correctly_find_broken_links() {
+fn select_files_with_a_glob() {
     let root = test_dir().join("broken-links");
-    let expected = &[
-        "./foo/bar/baz.html",
-        "../../../../../../../../../../../../etc/shadow",
-        "./asdf.png",
-        "./chapter_1.md",
-        "./second/directory.md",
-        "http://this-doesnt-exist.com.au.nz.us/",
-        "latex_with_latex_support_disabled",
-        "sibling.md",
-    ];
+    let temp = tempfile::tempdir().unwrap();
+    let report_file = temp.path().join("report.json");
+    let md = MDBook::load(&root).unwrap();
+    let ctx = RenderContext::new(md.root, md.book, md.config, temp.path());
+    let mut options = RunOptions::default();
+    options.colour = ColorChoice::Never;
+    options.selected_files = Some(vec![String::from("deeply/*/index.md")]);
+    options.report_file = Some(report_file.clone());
 
-    let output = run_link_checker(&root).unwrap();
+    let got = mdbook_linkcheck::run_with_options(&ctx, &options);
 
-    let broken: Vec<_> = output
-        .invalid_links
+    assert!(got.is_err());
+    let report = Report::load(&report_file).unwrap();
+    assert_eq!(report.problems.len(), 2);
+    assert!(report
+        .problems
         .iter()
-        .map(|invalid| invalid.link.href.to_string())
-        .collect();
-    assert_same_links(broken, expected);
-    // we also have three incomplete link (one normal, one latex)
-    assert_eq!(output.incomplete_links.len(), 2);
-    assert_eq!(output.incomplete_links[0].reference, "incomplete link");
-    assert_eq!(output.incomplete_links[1].reference, "math_var");
+        .all(|problem| problem.file.ends_with("index.md")));
 }
 
 #[test]
-fn correctly_find_links_with_latex() {
-    let root = test_dir().join("latex-support-links");
-    let expected = &[
-        "./foo/bar/baz.html",
-        "../../../../../../../../../../../../etc/shadow",
-        "./asdf.png",
-        "http://this-doesnt-exist.com.au.nz.us/",
-        "sibling.md",
-        "first_broken_link_nonlatex",
-        "second_broken_link_nonlatex",
-    ];
+fn compact_output_includes_the_line_and_column() {
+    let root = test_dir().join("broken-links");
+
+    TestRun::new_with_config(root, Config::default())
+        .after_validation(|files, outcome, _| {
+            let diags = outcome.generate_diagnostics(files, &Config::default());
+            let report = Report::from_diagnostics(files, &diags)
+                .with_prefix(Path::new("src"));
+            let mut buffer = Vec::new();
+            report.write(&mut buffer, ReportFormat::Text).unwrap();
+            let text = String::from_utf8(buffer).unwrap();
+
+            let chapter_1 = Path::new("src").join("chapter_1.md");
+            let expected = format!(
+                "{}:15:1: error: File not found: ./asdf.png",
+                chapter_1.display()
+            );
+            assert!(text.lines().any(|line| line == expected), "{}", text);
+            let expected = format!(
+                "{}:11:1: warning: Potential incomplete link",
+                chapter_1.display()
+            );
+            assert!(text.lines().any(|line| line == expected), "{}", text);
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn links_to_directories_use_the_default_index_file() {
+    let root = test_dir().join("custom-index");
+
+    let got = run_link_checker(&root).unwrap();
+    assert_eq!(got.invalid_links.len(), 1);
+    assert_eq!(got.invalid_links[0].link.href, "./guide/");
 
     let config = Config {
-        follow_web_links: true,
-        latex_support: true,
+        default_index_file: String::from("index.md"),
         ..Default::default()
     };
-    let output = run_link_checker_with_config(&root, config).unwrap();
+    let got = run_link_checker_with_config(&root, config).unwrap();
+    assert!(got.invalid_links.is_empty(), "{:#?}", got.invalid_links);
+    let valid_links: Vec<_> =
+        got.valid_links.iter().map(|link| &link.href).collect();
+    assert_same_links(valid_links, vec!["./guide/", "../intro.md"]);
+}
 
-    let broken: Vec<_> = output
-        .invalid_links
-        .iter()
-        .map(|invalid| invalid.link.href.to_string())
-        .collect();
-    assert_same_links(broken, expected);
+#[test]
+fn hint_when_a_fragment_looks_like_a_section_number() {
+    let root = test_dir().join("section-number-anchors");
+    let config = Config {
+        check_fragments: true,
+        ..Default::default()
+    };
 
-    // we also have two incomplete link
-    assert_eq!(output.incomplete_links.len(), 2);
-    assert_eq!(
-        output.incomplete_links[0].reference,
-        "this_incomplete_link_inside_nonlatex"
-    );
-    assert_eq!(output.incomplete_links[1].reference, "incomplete link");
+    TestRun::new_with_config(root, config)
+        .after_validation(|files, outcome, _| {
+            let broken: Vec<_> = outcome
+                .invalid_links
+                .iter()
+                .map(|invalid| invalid.link.href.as_str())
+                .collect();
+            assert_eq!(broken, vec!["./chapter_2.md#2-1"]);
+            assert!(is_specific_error::<mdbook_linkcheck::MissingFragment>(
+                &outcome.invalid_links[0].reason
+            ));
+
+            let diags = outcome.generate_diagnostics(
+                files,
+                &Config {
+                    warning_policy: WarningPolicy::Error,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(diags.len(), 1);
+            assert!(
+                diags[0].notes.iter().any(|note| note.contains(
+                    "mdbook doesn't generate anchors from section numbers"
+                )),
+                "{:?}",
+                diags[0]
+            );
+        })
+        .execute()
+        .unwrap();
 }
 
 #[test]
-fn detect_when_a_linked_file_isnt_in_summary_md() {
-    let root = test_dir().join("broken-links");
+fn suggest_the_closest_heading_for_a_misspelled_fragment() {
+    let root = test_dir().join("misspelled-fragments");
+    let config = Config {
+        check_fragments: true,
+        ..Default::default()
+    };
 
-    let output = run_link_checker(&root).unwrap();
+    TestRun::new_with_config(root, config)
+        .after_validation(|files, outcome, _| {
+            let broken: Vec<_> = outcome
+                .invalid_links
+                .iter()
+                .map(|invalid| invalid.link.href.as_str())
+                .collect();
+            assert_eq!(
+                broken,
+                vec![
+                    "./chapter_2.md#my_heading",
+                    "./chapter_2.md#troubleshooting"
+                ]
+            );
 
-    let broken_link = output
-        .invalid_links
-        .iter()
-        .find(|invalid| invalid.link.href == "sibling.md")
+            let diags = outcome.generate_diagnostics(
+                files,
+                &Config {
+                    warning_policy: WarningPolicy::Error,
+                    ..Default::default()
+                },
+            );
+            assert_eq!(diags.len(), 2);
+            assert!(
+                diags[0].notes.contains(&String::from(
+                    "No heading \"my_heading\"; did you mean \"my-heading\"?"
+                )),
+                "{:?}",
+                diags[0]
+            );
+            assert!(
+                !diags[1]
+                    .notes
+                    .iter()
+                    .any(|note| note.contains("did you mean")),
+                "{:?}",
+                diags[1]
+            );
+        })
+        .execute()
         .unwrap();
+}
 
-    assert!(is_specific_error::<mdbook_linkcheck::NotInSummary>(
-        &broken_link.reason
-    ));
+#[test]
+fn absolute_filesystem_paths_are_errors() {
+    let root = test_dir().join("absolute-fs-paths");
+
+    TestRun::new_with_config(root, Config::default())
+        .after_validation(|files, outcome, _| {
+            let absolute: Vec<_> = outcome
+                .absolute_fs_paths
+                .iter()
+                .map(|link| link.href.as_str())
+                .collect();
+            assert_eq!(
+                absolute,
+                vec!["/home/michael/notes.md", r"C:\Users\michael\report.md"]
+            );
+
+            let diags =
+                outcome.generate_diagnostics(
+                files,
+                &Config {
+                    warning_policy: WarningPolicy::Ignore,
+                    ..Default::default()
+                },
+            );
+            let messages: Vec<_> = diags
+                .iter()
+                .filter(|diag| diag.severity == Severity::Error)
+                .map(|diag| diag.message.as_str())
+                .collect();
+            assert_eq!(
+                messages,
+                vec![
+                    "\"/home/michael/notes.md\" is an absolute path on the author's computer",
+                    r#""C:\Users\michael\report.md" is an absolute path on the author's computer"#,
+                ]
+            );
+        })
+        .execute()
+        .unwrap();
 }
 
 #[test]
@@ -141,8 +1549,13 @@ fn emit_valid_suggestions_on_absolute_links() {
 
     TestRun::new(root)
         .after_validation(|files, outcome, _| {
-            let diags =
-                outcome.generate_diagnostics(files, WarningPolicy::Error);
+            let diags = outcome.generate_diagnostics(
+                files,
+                &Config {
+                    warning_policy: WarningPolicy::Error,
+                    ..Default::default()
+                },
+            );
 
             let suggestions = vec![
                 "\"chapter_1.md\"",
@@ -216,6 +1629,458 @@ fn skip_web_links() {
     );
 }
 
+#[test]
+fn exclude_links_listed_in_the_ignore_file() {
+    let root = test_dir().join("ignore-file");
+    let expected_ignored =
+        &["./drafts/unfinished.md", "https://example.com/private/page"];
+    let mut config = Config::default();
+    config
+        .exclude
+        .extend(mdbook_linkcheck::load_ignore_file(&root).unwrap());
+
+    let output = run_link_checker_with_config(&root, config).unwrap();
+
+    let ignored: Vec<_> = output
+        .ignored
+        .iter()
+        .map(|link| link.href.as_str())
+        .collect();
+    assert_same_links(expected_ignored, ignored);
+    assert!(output.invalid_links.is_empty());
+}
+
+#[test]
+fn skip_links_in_excluded_files() {
+    let root = test_dir().join("exclude-files");
+    let config = Config {
+        exclude_files: vec![String::from("generated/*.md")],
+        ..Default::default()
+    };
+
+    let output = run_link_checker_with_config(&root, config).unwrap();
+
+    // links in the excluded chapter are never checked, but it's still part
+    // of the book so other chapters can link to it
+    let valid: Vec<_> = output
+        .valid_links
+        .iter()
+        .map(|link| link.href.as_str())
+        .collect();
+    assert_eq!(valid, vec!["./generated/placeholders.md"]);
+    let broken: Vec<_> = output
+        .invalid_links
+        .iter()
+        .map(|invalid| invalid.link.href.as_str())
+        .collect();
+    assert_eq!(broken, vec!["./missing.md"]);
+}
+
+#[test]
+fn find_links_in_tables_and_nested_lists() {
+    let root = test_dir().join("tables-and-lists");
+
+    TestRun::new(&root)
+        .after_validation(|files, outcome, _| {
+            fn text<'a>(
+                files: &'a Files<String>,
+                link: &'a linkcheck::Link,
+            ) -> (&'a str, &'a str) {
+                let span: std::ops::Range<usize> = link.span.into();
+                (link.href.as_str(), &files.source(link.file)[span])
+            }
+            let valid: Vec<_> = outcome
+                .valid_links
+                .iter()
+                .map(|link| text(files, link))
+                .collect();
+            let broken: Vec<_> = outcome
+                .invalid_links
+                .iter()
+                .map(|invalid| text(files, &invalid.link))
+                .collect();
+
+            assert_eq!(
+                valid,
+                vec![
+                    ("./chapter_2.md", "[Chapter 2](./chapter_2.md)"),
+                    ("./chapter_2.md", "[the build guide](./chapter_2.md)"),
+                ]
+            );
+            assert_eq!(
+                broken,
+                vec![
+                    ("./missing-size.md", "[the size docs](./missing-size.md)"),
+                    (
+                        "./missing-guide.md",
+                        "[the old guide](./missing-guide.md)"
+                    ),
+                ]
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn find_links_in_footnotes() {
+    let root = test_dir().join("footnotes");
+
+    TestRun::new(&root)
+        .after_validation(|files, outcome, _| {
+            let text = |link: &linkcheck::Link| {
+                let span: std::ops::Range<usize> = link.span.into();
+                files.source(link.file)[span].to_string()
+            };
+            let valid: Vec<_> = outcome
+                .valid_links
+                .iter()
+                .map(|link| (link.href.as_str(), text(link)))
+                .collect();
+            let broken: Vec<_> = outcome
+                .invalid_links
+                .iter()
+                .map(|invalid| {
+                    (invalid.link.href.as_str(), text(&invalid.link))
+                })
+                .collect();
+
+            assert_eq!(
+                valid,
+                vec![(
+                    "./chapter_2.md",
+                    String::from("[the caching docs](./chapter_2.md)")
+                )]
+            );
+            assert_eq!(
+                broken,
+                vec![(
+                    "./missing-guide.md",
+                    String::from("[the old guide](./missing-guide.md)")
+                )]
+            );
+        })
+        .execute()
+        .unwrap();
+}
+
+#[test]
+fn ignore_unexpanded_mdbook_helpers() {
+    let root = test_dir().join("mdbook-helpers");
+
+    let output =
+        run_link_checker_with_config(&root, Config::default()).unwrap();
+
+    let valid: Vec<_> = output
+        .valid_links
+        .iter()
+        .map(|link| link.href.as_str())
+        .collect();
+    assert_eq!(valid, vec!["./chapter_1.md"]);
+    assert!(
+        output.invalid_links.is_empty(),
+        "{:?}",
+        output.invalid_links
+    );
+    assert!(
+        output.incomplete_links.is_empty(),
+        "{:?}",
+        output.incomplete_links
+    );
+}
+
+#[test]
+fn check_every_translation_in_a_multilingual_book() {
+    let root = test_dir().join("multilingual");
+    let config = mdbook::Config::from_disk(root.join("book.toml")).unwrap();
+    let ctx = multilingual_context(&root);
+
+    // The French translation links to a chapter that doesn't exist
+    let got = mdbook_linkcheck::run(None, ColorChoice::Never, &ctx, None);
+    assert!(got.is_err());
+
+    // ... but the English translation is fine on its own
+    let mut config = config;
+    config.book.multilingual = false;
+    config.book.src = PathBuf::from("src/en");
+    let book = load_book(root.join("src/en"), &config.build).unwrap();
+    let ctx = RenderContext::new(&root, book, config, root.join("book"));
+    mdbook_linkcheck::run(None, ColorChoice::Never, &ctx, None).unwrap();
+}
+
+#[test]
+fn translations_can_share_a_web_cache() {
+    let server = TestServer::start(|_| test_server::ok());
+    let url = server.url();
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    write_multilingual_book(root, url, true);
+    let cache_file = root.join("book").join("cache.json");
+
+    let ctx = multilingual_context(root);
+    mdbook_linkcheck::run(Some(&cache_file), ColorChoice::Never, &ctx, None)
+        .unwrap();
+
+    // The French translation was served from the English translation's cache
+    assert_eq!(server.request_count(), 1);
+}
+
+#[test]
+fn translations_can_have_their_own_web_cache() {
+    let server = TestServer::start(|_| test_server::ok());
+    let url = server.url();
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    write_multilingual_book(root, url, false);
+    let cache_file = root.join("book").join("cache.json");
+
+    let ctx = multilingual_context(root);
+    mdbook_linkcheck::run(Some(&cache_file), ColorChoice::Never, &ctx, None)
+        .unwrap();
+
+    assert_eq!(server.request_count(), 2);
+
+    // both translations' results are kept in the same file
+    mdbook_linkcheck::run(Some(&cache_file), ColorChoice::Never, &ctx, None)
+        .unwrap();
+    assert_eq!(server.request_count(), 2);
+    let files: Vec<_> = std::fs::read_dir(root.join("book"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files, vec![cache_file.file_name().unwrap()]);
+}
+
+#[test]
+fn translations_are_preprocessed() {
+    let server = TestServer::start(|_| test_server::ok());
+    let url = server.url();
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    write_multilingual_book(root, url, false);
+    // the link is only visible once `{{#include}}` has been expanded
+    for language in &["en", "fr"] {
+        let src = root.join("src").join(language);
+        std::fs::write(
+            src.join("chapter_1.md"),
+            "# Chapter 1\n\n{{#include links.md}}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            src.join("links.md"),
+            format!("[A web page]({})\n", url),
+        )
+        .unwrap();
+    }
+
+    let ctx = multilingual_context(root);
+    mdbook_linkcheck::run(None, ColorChoice::Never, &ctx, None).unwrap();
+
+    assert_eq!(server.request_count(), 2);
+}
+
+#[test]
+fn changing_the_http_headers_invalidates_the_cache() {
+    let server = TestServer::start(|_| test_server::ok());
+    let url = server.url();
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    let cache_file = root.join("book").join("cache.json");
+    let check_with_header = |header: &str| {
+        write_web_book(root, url, header);
+        let config = mdbook::Config::from_disk(root.join("book.toml")).unwrap();
+        let book = load_book(root.join("src"), &config.build).unwrap();
+        let ctx = RenderContext::new(root, book, config, root.join("book"));
+        mdbook_linkcheck::run(
+            Some(&cache_file),
+            ColorChoice::Never,
+            &ctx,
+            None,
+        )
+        .unwrap();
+    };
+
+    check_with_header("Authorization: Basic first");
+    assert_eq!(server.request_count(), 1);
+
+    // nothing changed, so the cached result is still trusted
+    check_with_header("Authorization: Basic first");
+    assert_eq!(server.request_count(), 1);
+
+    check_with_header("Authorization: Basic second");
+    assert_eq!(server.request_count(), 2);
+}
+
+#[test]
+fn the_second_run_is_served_from_the_cache() {
+    let server = TestServer::start(|_| test_server::ok());
+    let url = server.url();
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    write_web_book(root, url, "Accept: text/html");
+    let cache_file = root.join("book").join("cache.json");
+    let config = mdbook::Config::from_disk(root.join("book.toml")).unwrap();
+    let cfg = mdbook_linkcheck::get_config(&config).unwrap();
+    let book = load_book(root.join("src"), &config.build).unwrap();
+    let ctx = RenderContext::new(root, book, config, root.join("book"));
+
+    mdbook_linkcheck::run(Some(&cache_file), ColorChoice::Never, &ctx, None)
+        .unwrap();
+    let first = mdbook_linkcheck::cache_summary(&cache_file, &cfg).stats;
+    assert_eq!((first.hits, first.misses), (0, 1));
+
+    mdbook_linkcheck::run(Some(&cache_file), ColorChoice::Never, &ctx, None)
+        .unwrap();
+    let second = mdbook_linkcheck::cache_summary(&cache_file, &cfg).stats;
+    assert_eq!((second.hits, second.misses), (1, 0));
+    assert_eq!(server.request_count(), 1);
+}
+
+#[test]
+fn the_cache_can_be_kept_somewhere_else() {
+    let server = TestServer::start(|_| test_server::ok());
+    let url = server.url();
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    write_web_book(root, url, "Accept: text/html");
+    let book_toml = std::fs::read_to_string(root.join("book.toml")).unwrap();
+    let book_toml = book_toml.replace(
+        "follow-web-links = true\n",
+        "follow-web-links = true\ncache-file = \"ci-cache/links.json\"\n",
+    );
+    std::fs::write(root.join("book.toml"), book_toml).unwrap();
+    let default_cache_file = root.join("book").join("cache.json");
+
+    let config = mdbook::Config::from_disk(root.join("book.toml")).unwrap();
+    let book = load_book(root.join("src"), &config.build).unwrap();
+    let ctx = RenderContext::new(root, book, config, root.join("book"));
+    mdbook_linkcheck::run(
+        Some(&default_cache_file),
+        ColorChoice::Never,
+        &ctx,
+        None,
+    )
+    .unwrap();
+
+    let custom_cache_file = root.join("ci-cache").join("links.json");
+    assert!(!default_cache_file.exists());
+    let cache: Cache = serde_json::from_str(
+        &std::fs::read_to_string(&custom_cache_file).unwrap(),
+    )
+    .unwrap();
+    assert!(cache.lookup(url).unwrap().successful);
+
+    // the next run uses the cached result
+    mdbook_linkcheck::run(
+        Some(&default_cache_file),
+        ColorChoice::Never,
+        &ctx,
+        None,
+    )
+    .unwrap();
+    assert_eq!(server.request_count(), 1);
+}
+
+#[test]
+fn clearing_the_cache_only_removes_the_cache_file() {
+    let temp = tempfile::tempdir().unwrap();
+    let cache_file = temp.path().join("cache.json");
+    let similar_name = temp.path().join("cache.backup.json");
+    for path in &[&cache_file, &similar_name] {
+        std::fs::write(path, "{}").unwrap();
+    }
+
+    mdbook_linkcheck::clear_cache(&cache_file).unwrap();
+    // clearing a cache that doesn't exist isn't an error
+    mdbook_linkcheck::clear_cache(&cache_file).unwrap();
+
+    assert!(!cache_file.exists());
+    assert!(similar_name.exists());
+}
+
+/// Write a book to disk which links to `url`, sending `header` with any
+/// requests.
+fn write_web_book(root: &Path, url: &str, header: &str) {
+    let book_toml = format!(
+        r#"[book]
+title = "Web Links"
+
+[output.linkcheck]
+follow-web-links = true
+
+[output.linkcheck.http-headers]
+'127\.0\.0\.1' = ["{}"]
+"#,
+        header
+    );
+    std::fs::write(root.join("book.toml"), book_toml).unwrap();
+
+    let src = root.join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(
+        src.join("SUMMARY.md"),
+        "# Summary\n\n- [Chapter 1](chapter_1.md)\n",
+    )
+    .unwrap();
+    std::fs::write(
+        src.join("chapter_1.md"),
+        format!("# Chapter 1\n\n[A web page]({})\n", url),
+    )
+    .unwrap();
+}
+
+/// Write a book to disk with an English and French translation which both
+/// link to the same URL.
+fn write_multilingual_book(root: &Path, url: &str, shared_web_cache: bool) {
+    let book_toml = format!(
+        r#"[book]
+title = "Multilingual"
+multilingual = true
+
+[language.en]
+name = "English"
+default = true
+
+[language.fr]
+name = "Français"
+
+[output.linkcheck]
+follow-web-links = true
+shared-web-cache = {}
+"#,
+        shared_web_cache
+    );
+    std::fs::write(root.join("book.toml"), book_toml).unwrap();
+
+    for language in &["en", "fr"] {
+        let src = root.join("src").join(language);
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(
+            src.join("SUMMARY.md"),
+            "# Summary\n\n- [Chapter 1](chapter_1.md)\n",
+        )
+        .unwrap();
+        std::fs::write(
+            src.join("chapter_1.md"),
+            format!("# Chapter 1\n\n[A web page]({})\n", url),
+        )
+        .unwrap();
+    }
+}
+
+/// Get the [`RenderContext`] mdbook would give us for a multilingual book,
+/// where the book is the default (English) translation after preprocessing.
+fn multilingual_context(root: &Path) -> RenderContext {
+    let config = mdbook::Config::from_disk(root.join("book.toml")).unwrap();
+    let mut english = config.clone();
+    english.book.src = english.book.src.join("en");
+    let md = MDBook::load_with_config(root, english).unwrap();
+    let renderer = CmdRenderer::new(String::from("linkcheck"), String::new());
+    let (book, _) = md.preprocess_book(&renderer).unwrap();
+
+    RenderContext::new(root, book, config, root.join("book"))
+}
+
 fn is_specific_error<E>(reason: &Reason) -> bool
 where
     E: std::error::Error + 'static,
@@ -250,6 +2115,7 @@ where
 struct TestRun {
     config: Config,
     root: PathBuf,
+    scope: LinkScope,
     after_validation:
         Box<dyn Fn(&Files<String>, &ValidationOutcome, &Vec<FileId>)>,
     validation_outcome: Cell<Option<ValidationOutcome>>,
@@ -265,10 +2131,11 @@ impl TestRun {
                 exclude: vec![r"forbidden\.com".parse().unwrap()],
                 http_headers: HashMap::from_iter(vec![(
                     HashedRegex::new(r"crates\.io").unwrap(),
-                    vec!["Accept: text/html".try_into().unwrap()],
+                    vec!["Accept: text/html".try_into().unwrap()].into(),
                 )]),
                 ..Default::default()
             },
+            scope: LinkScope::All,
             after_validation: Box::new(|_, _, _| {}),
             validation_outcome: Cell::new(None),
         }
@@ -278,11 +2145,14 @@ impl TestRun {
         TestRun {
             root: root.into(),
             config,
+            scope: LinkScope::All,
             after_validation: Box::new(|_, _, _| {}),
             validation_outcome: Cell::new(None),
         }
     }
 
+    fn with_scope(self, scope: LinkScope) -> Self { TestRun { scope, ..self } }
+
     fn after_validation<F>(self, cb: F) -> Self
     where
         F: Fn(&Files<String>, &ValidationOutcome, &Vec<FileId>) + 'static,
@@ -336,9 +2206,10 @@ impl Renderer for TestRun {
             file_ids.clone(),
             &files,
         );
+        let (links, skipped) = self.scope.partition(links);
 
         let mut cache = Cache::default();
-        let outcome = mdbook_linkcheck::validate(
+        let mut outcome = mdbook_linkcheck::validate(
             &links,
             &self.config,
             &src,
@@ -347,6 +2218,15 @@ impl Renderer for TestRun {
             &file_ids,
             incomplete,
         )?;
+        outcome.ignored.extend(skipped);
+        outcome.duplicate_definitions =
+            mdbook_linkcheck::find_duplicate_definitions(
+                &self.config,
+                file_ids.clone(),
+                &files,
+            );
+        outcome.summary =
+            mdbook_linkcheck::load_summary_into_memory(&src, &mut files);
 
         (self.after_validation)(&files, &outcome, &file_ids);
 